@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tdigest::{AvlTreeDigest, ConstTDigest, TDigest};
+
+#[path = "../src/lcg.rs"]
+mod lcg;
+
+/// Counts every allocation so the benchmark can report allocations per insert.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn bench_insert(c: &mut Criterion) {
+    let values = lcg::lcg_values(1_000_000, 7);
+
+    // Allocations of a warmed-up digest over a million inserts, i.e. thousands of flushes.
+    let mut digest = TDigest::new_with_size(100);
+    digest.extend(values.iter().copied().take(10_000));
+    digest.reset_stats();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    digest.extend(values.iter().copied());
+    digest.flush();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let stats = digest.stats();
+    eprintln!(
+        "insert: {} allocations for {} values ({} compressions, {} centroids allocated)",
+        allocations, stats.values_buffered, stats.compressions, stats.centroids_allocated
+    );
+
+    c.bench_function("insert/1M", |b| {
+        b.iter(|| {
+            let mut digest = TDigest::new_with_size(100);
+            digest.extend(black_box(&values).iter().copied());
+            digest.flush();
+            digest
+        })
+    });
+}
+
+/// The same million inserts into the other backends, each at about 100 centroids.
+fn bench_insert_backends(c: &mut Criterion) {
+    let values = lcg::lcg_values(1_000_000, 7);
+    let mut group = c.benchmark_group("insert_backends");
+    group.sample_size(10);
+    group.bench_function("avl/1M", |b| {
+        b.iter(|| {
+            let mut digest = AvlTreeDigest::new(100);
+            black_box(&values).iter().for_each(|&v| digest.insert(v));
+            digest
+        })
+    });
+    group.bench_function("const/1M", |b| {
+        b.iter(|| {
+            let mut digest = ConstTDigest::<200>::new();
+            black_box(&values).iter().for_each(|&v| digest.insert(v));
+            digest.flush();
+            digest
+        })
+    });
+    #[cfg(feature = "std")]
+    group.bench_function("concurrent/1M", |b| {
+        b.iter(|| {
+            let digest = tdigest::ConcurrentTDigest::new(100);
+            black_box(&values).iter().for_each(|&v| digest.record(v));
+            digest.into_digest()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert, bench_insert_backends);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use tdigest::TDigest;
+
+#[path = "../src/lcg.rs"]
+mod lcg;
+
+/// `n` small digests of 100 pseudo-random values each, like per-shard digests.
+fn shard_digests(n: usize) -> Vec<TDigest> {
+    lcg::lcg_values(100 * n, 42)
+        .chunks(100)
+        .map(|values| TDigest::new_with_size(100).merge_unsorted(values.to_vec()))
+        .collect()
+}
+
+/// Merges of 1k to 100k shard digests. The parallel variant needs `--features rayon`.
+fn bench_merge_digests(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge_digests");
+    group.sample_size(10);
+    for n in [1_000, 10_000, 100_000] {
+        let digests = shard_digests(n);
+        group.bench_with_input(BenchmarkId::new("sequential", n), &digests, |b, d| {
+            b.iter(|| TDigest::merge_digests(black_box(d.clone())))
+        });
+        #[cfg(feature = "rayon")]
+        group.bench_with_input(BenchmarkId::new("parallel", n), &digests, |b, d| {
+            b.iter(|| TDigest::par_merge_digests(black_box(d.clone())))
+        });
+    }
+    group.finish();
+}
+
+/// Folding 1k shard digests into one, one `merge` at a time, as a long-lived
+/// aggregate does.
+fn bench_merge_pairwise(c: &mut Criterion) {
+    let digests = shard_digests(1_000);
+    c.bench_function("merge/pairwise/1000", |b| {
+        b.iter(|| {
+            let mut total = TDigest::new_with_size(100);
+            black_box(&digests).iter().for_each(|d| total.merge(d));
+            total
+        })
+    });
+}
+
+criterion_group!(benches, bench_merge_digests, bench_merge_pairwise);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tdigest::TDigest;
+
+#[path = "../src/lcg.rs"]
+mod lcg;
+
+/// Single-point queries on a digest of ten thousand centroids, each scanning the
+/// cumulative weights from the start. Compare runs with and without `--features simd`.
+fn bench_query(c: &mut Criterion) {
+    let values = lcg::lcg_values(1_000_000, 11);
+    let mut digest = TDigest::new_with_size(10_000);
+    digest.extend(values.iter().copied());
+    digest.flush();
+    let probes: Vec<f64> = lcg::lcg_values(1_000, 13);
+
+    c.bench_function("query/quantile", |b| {
+        b.iter(|| {
+            black_box(&probes)
+                .iter()
+                .map(|&q| digest.estimate_quantile(q))
+                .sum::<f64>()
+        })
+    });
+    c.bench_function("query/cdf", |b| {
+        b.iter(|| {
+            black_box(&probes)
+                .iter()
+                .map(|&x| digest.cdf(x))
+                .sum::<f64>()
+        })
+    });
+}
+
+/// A one-off p99 over 50 shard digests, answered over the unmerged shards or after
+/// merging them.
+fn bench_query_shards(c: &mut Criterion) {
+    let shards: Vec<TDigest> = lcg::lcg_values(50 * 10_000, 17)
+        .chunks(10_000)
+        .map(|values| TDigest::new_with_size(100).merge_unsorted(values.to_vec()))
+        .collect();
+
+    c.bench_function("query/quantile_over/50", |b| {
+        b.iter(|| TDigest::quantile_over(black_box(&shards), 0.99))
+    });
+    c.bench_function("query/merge_then_quantile/50", |b| {
+        b.iter(|| TDigest::merge_digests(black_box(&shards).clone()).estimate_quantile(0.99))
+    });
+}
+
+criterion_group!(benches, bench_query, bench_query_shards);
+criterion_main!(benches);
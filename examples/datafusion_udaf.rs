@@ -0,0 +1,206 @@
+//! `tdigest(col)` and `approx_quantile(digest, q)` for DataFusion, backed by this crate.
+//!
+//! `tdigest` aggregates numbers, or already built digests, into a digest in the canonical
+//! Arrow layout of [`TDigest::arrow_data_type`], so digests can be stored in a table and
+//! rolled up later. `approx_quantile` reads a quantile from such a digest.
+//!
+//! Run with `cargo run --example datafusion_udaf --features datafusion`.
+
+use datafusion::arrow::array::{Array, ArrayRef, Float64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::cast::{as_float64_array, as_struct_array};
+use datafusion::common::{plan_err, DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::function::AccumulatorArgs;
+use datafusion::logical_expr::{
+    Accumulator, AggregateUDF, AggregateUDFImpl, ColumnarValue, ScalarFunctionArgs, ScalarUDF,
+    ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::prelude::SessionContext;
+use std::sync::Arc;
+use tdigest::TDigest;
+
+fn decode(array: &ArrayRef) -> Result<Vec<Option<TDigest>>> {
+    TDigest::from_arrow(as_struct_array(array)?)
+        .map_err(|e| DataFusionError::Execution(format!("invalid digest: {}", e)))
+}
+
+/// The `tdigest(col)` aggregate.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct TDigestUdaf {
+    signature: Signature,
+}
+
+impl TDigestUdaf {
+    fn new() -> Self {
+        TDigestUdaf {
+            signature: Signature::user_defined(Volatility::Immutable),
+        }
+    }
+}
+
+impl AggregateUDFImpl for TDigestUdaf {
+    fn name(&self) -> &str {
+        "tdigest"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        match arg_types {
+            [t] if *t == TDigest::arrow_data_type() => Ok(vec![t.clone()]),
+            [t] if t.is_numeric() => Ok(vec![DataType::Float64]),
+            _ => plan_err!("tdigest takes one numeric or digest argument"),
+        }
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(TDigest::arrow_data_type())
+    }
+
+    fn accumulator(&self, _args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        Ok(Box::new(TDigestAccumulator {
+            digest: TDigest::default(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct TDigestAccumulator {
+    digest: TDigest,
+}
+
+impl TDigestAccumulator {
+    fn merge_digests(&mut self, digests: &ArrayRef) -> Result<()> {
+        for digest in decode(digests)?.into_iter().flatten() {
+            self.digest.merge(&digest);
+        }
+        Ok(())
+    }
+
+    fn to_scalar(&self) -> ScalarValue {
+        ScalarValue::Struct(Arc::new(TDigest::to_arrow(std::slice::from_ref(
+            &self.digest,
+        ))))
+    }
+}
+
+impl Accumulator for TDigestAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        if values[0].data_type() == &TDigest::arrow_data_type() {
+            return self.merge_digests(&values[0]);
+        }
+        self.digest
+            .extend(as_float64_array(&values[0])?.iter().flatten());
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        self.merge_digests(&states[0])
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![self.to_scalar()])
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        Ok(self.to_scalar())
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self) + self.digest.byte_size()
+    }
+}
+
+/// The `approx_quantile(digest, q)` scalar function.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ApproxQuantileUdf {
+    signature: Signature,
+}
+
+impl ApproxQuantileUdf {
+    fn new() -> Self {
+        ApproxQuantileUdf {
+            signature: Signature::exact(
+                vec![TDigest::arrow_data_type(), DataType::Float64],
+                Volatility::Immutable,
+            ),
+        }
+    }
+}
+
+impl ScalarUDFImpl for ApproxQuantileUdf {
+    fn name(&self) -> &str {
+        "approx_quantile"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let args = ColumnarValue::values_to_arrays(&args.args)?;
+        let digests = decode(&args[0])?;
+        let quantiles = as_float64_array(&args[1])?;
+
+        let estimates: Float64Array = digests
+            .iter()
+            .enumerate()
+            .map(|(row, digest)| match digest {
+                Some(digest) if !digest.is_empty() && quantiles.is_valid(row) => {
+                    Some(digest.estimate_quantile(quantiles.value(row)))
+                }
+                _ => None,
+            })
+            .collect();
+        Ok(ColumnarValue::Array(Arc::new(estimates)))
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let ctx = SessionContext::new();
+    ctx.register_udaf(AggregateUDF::from(TDigestUdaf::new()));
+    ctx.register_udf(ScalarUDF::from(ApproxQuantileUdf::new()));
+
+    let services: StringArray = (0..10_000)
+        .map(|i| Some(if i % 4 == 0 { "search" } else { "checkout" }))
+        .collect();
+    let latencies: Float64Array = (0..10_000).map(|i| Some(f64::from(i % 1000))).collect();
+    let batch = RecordBatch::try_from_iter(vec![
+        ("service", Arc::new(services) as ArrayRef),
+        ("latency", Arc::new(latencies) as ArrayRef),
+    ])?;
+    ctx.register_batch("requests", batch)?;
+
+    ctx.sql(
+        "CREATE TABLE digests AS \
+         SELECT service, tdigest(latency) AS digest FROM requests GROUP BY service",
+    )
+    .await?
+    .collect()
+    .await?;
+
+    ctx.sql(
+        "SELECT service, \
+                approx_quantile(digest, 0.5) AS p50, \
+                approx_quantile(digest, 0.99) AS p99 \
+         FROM digests ORDER BY service",
+    )
+    .await?
+    .show()
+    .await?;
+
+    // Stored digests roll up into one without going back to the raw values.
+    ctx.sql("SELECT approx_quantile(tdigest(digest), 0.5) AS p50 FROM digests")
+        .await?
+        .show()
+        .await?;
+
+    Ok(())
+}
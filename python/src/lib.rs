@@ -0,0 +1,172 @@
+//! Python bindings, built into a `tdigest` extension module with
+//! [maturin](https://www.maturin.rs/): `maturin develop --release` in this directory.
+//!
+//! ```python
+//! from tdigest import TDigest
+//!
+//! digest = TDigest.from_bytes(payload)  # written by `TDigest::to_bytes` in Rust
+//! digest.update(12.5)
+//! digest.quantile(0.99)
+//! ```
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use tdigest::TDigest;
+
+/// A t-digest, interchangeable with the Rust `TDigest` through `to_bytes`/`from_bytes`.
+#[pyclass(name = "TDigest", module = "tdigest")]
+#[derive(Debug, Clone)]
+pub struct PyTDigest {
+    digest: TDigest,
+}
+
+#[pymethods]
+impl PyTDigest {
+    /// Creates an empty digest of at most `max_size` centroids.
+    #[new]
+    #[pyo3(signature = (max_size = 100))]
+    fn new(max_size: usize) -> Self {
+        PyTDigest {
+            digest: TDigest::new_with_size(max_size),
+        }
+    }
+
+    /// Adds `value` as if it had been seen `weight` times.
+    #[pyo3(signature = (value, weight = 1.0))]
+    fn update(&mut self, value: f64, weight: f64) {
+        self.digest.insert_weighted(value, weight);
+    }
+
+    /// Adds every value of `values`, e.g. a list or a NumPy array.
+    fn batch_update(&mut self, values: Vec<f64>) {
+        self.digest.extend(values);
+    }
+
+    /// Folds `other` into this digest.
+    fn merge(&mut self, other: PyRef<'_, PyTDigest>) {
+        self.digest.merge(&other.digest);
+    }
+
+    /// Estimates the value at quantile `q` in `[0, 1]`.
+    fn quantile(&self, q: f64) -> f64 {
+        self.digest.estimate_quantile(q)
+    }
+
+    /// Estimates the fraction of values at most `x`.
+    fn cdf(&self, x: f64) -> f64 {
+        self.digest.cdf(x)
+    }
+
+    /// Encodes the digest in the binary format of the Rust `TDigest::to_bytes`.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.digest.to_bytes())
+    }
+
+    /// Decodes bytes written by `to_bytes` here or in Rust, raising `ValueError` on an
+    /// invalid encoding.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        TDigest::from_bytes(data)
+            .map(|digest| PyTDigest { digest })
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn count(&self) -> f64 {
+        self.digest.count()
+    }
+
+    #[getter]
+    fn min(&self) -> f64 {
+        self.digest.min()
+    }
+
+    #[getter]
+    fn max(&self) -> f64 {
+        self.digest.max()
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.digest.max_size()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TDigest(max_size={}, count={})",
+            self.digest.max_size(),
+            self.digest.count()
+        )
+    }
+}
+
+impl PyTDigest {
+    /// The wrapped digest.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+}
+
+impl From<TDigest> for PyTDigest {
+    fn from(digest: TDigest) -> Self {
+        PyTDigest { digest }
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "tdigest")]
+fn tdigest_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTDigest>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::ffi::c_str;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python_api_interoperates_with_rust_bytes() {
+        let rust = TDigest::new_with_size(50).merge_sorted((1..=1_000).map(f64::from).collect());
+        Python::attach(|py| {
+            let module = PyModule::new(py, "tdigest").unwrap();
+            tdigest_module(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("tdigest", module).unwrap();
+            locals
+                .set_item("payload", PyBytes::new(py, &rust.to_bytes()))
+                .unwrap();
+            py.run(
+                c_str!(
+                    r#"
+TDigest = tdigest.TDigest
+d = TDigest.from_bytes(payload)
+assert d.count == 1000 and d.max_size == 50
+other = TDigest(50)
+for v in range(1001, 2001):
+    other.update(float(v))
+other.update(0.0, weight=0.5)
+d.merge(other)
+assert abs(d.quantile(0.5) - 1000) < 20
+assert abs(d.cdf(1000.0) - 0.5) < 0.01
+out = d.to_bytes()
+try:
+    TDigest.from_bytes(b"junk")
+    raise AssertionError("expected ValueError")
+except ValueError:
+    pass
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+
+            let out = locals.get_item("out").unwrap().unwrap();
+            let decoded = TDigest::from_bytes(out.extract::<&[u8]>().unwrap()).unwrap();
+            assert_eq!(decoded.count(), 2_000.5);
+            assert_eq!(decoded.min(), 0.0);
+        });
+    }
+}
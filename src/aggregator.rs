@@ -0,0 +1,235 @@
+use crate::{SharedTDigest, SharedTDigestWriter, TDigest};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::vec::Vec;
+
+/// Values a recorder buffers before handing them to its aggregator, by default.
+const DEFAULT_RECORDER_CAPACITY: usize = 1024;
+
+/// A per-thread recorder of an [`Aggregator`]: `record` only appends to a buffer the
+/// recorder owns, with no locks or atomics. Once `capacity` values are buffered they are
+/// sent to the aggregator as one batch, which is also done by
+/// [`flush`](LocalRecorder::flush) and on drop.
+///
+/// Recorders are `Send` but not shared; give each thread its own, e.g. from
+/// [`Aggregator::recorder`] before spawning it.
+#[derive(Debug)]
+pub struct LocalRecorder {
+    buffer: Vec<f64>,
+    capacity: usize,
+    sender: Sender<Vec<f64>>,
+}
+
+impl LocalRecorder {
+    /// Records `value`.
+    #[inline]
+    pub fn record(&mut self, value: f64) {
+        self.buffer.push(value);
+        if self.buffer.len() >= self.capacity {
+            self.flush();
+        }
+    }
+
+    /// Values recorded since the last hand-over.
+    #[inline]
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Hands the buffered values to the aggregator, which folds them in on its next
+    /// drain. Values recorded after the aggregator is gone are dropped.
+    pub fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let batch = core::mem::replace(&mut self.buffer, Vec::with_capacity(self.capacity));
+        let _ = self.sender.send(batch);
+    }
+}
+
+impl Drop for LocalRecorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Collects the batches of any number of [`LocalRecorder`]s into a master digest, which
+/// readers load through a [`SharedTDigest`].
+///
+/// Unlike [`crate::ConcurrentTDigest`], recording never synchronizes with other threads
+/// except when a recorder hands over a full buffer. In exchange reads are stale: a value
+/// becomes visible once its recorder has handed it over and the aggregator has drained
+/// since, so with [`Aggregator::spawn`] within one interval of its recorder's flush.
+#[derive(Debug)]
+pub struct Aggregator {
+    sender: Sender<Vec<f64>>,
+    receiver: Receiver<Vec<f64>>,
+    writer: SharedTDigestWriter,
+    recorder_capacity: usize,
+}
+
+impl Aggregator {
+    /// Creates an aggregator into a digest of `max_size` centroids, whose recorders
+    /// buffer 1024 values each.
+    pub fn new(max_size: usize) -> Self {
+        Aggregator::with_recorder_capacity(max_size, DEFAULT_RECORDER_CAPACITY)
+    }
+
+    /// Creates an aggregator into a digest of `max_size` centroids, whose recorders hand
+    /// over every `recorder_capacity` values.
+    pub fn with_recorder_capacity(max_size: usize, recorder_capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Aggregator {
+            sender,
+            receiver,
+            writer: SharedTDigestWriter::new(max_size),
+            recorder_capacity: recorder_capacity.max(1),
+        }
+    }
+
+    /// A new recorder for one thread.
+    pub fn recorder(&self) -> LocalRecorder {
+        LocalRecorder {
+            buffer: Vec::new(),
+            capacity: self.recorder_capacity,
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// A handle to the master digest as of the last drain, for any number of readers.
+    pub fn reader(&self) -> SharedTDigest {
+        self.writer.reader()
+    }
+
+    /// The master digest as of the last drain.
+    pub fn digest(&self) -> &TDigest {
+        self.writer.digest()
+    }
+
+    /// Folds every batch handed over so far into the master digest and publishes it to
+    /// readers. Returns the number of values folded in.
+    pub fn drain(&mut self) -> usize {
+        let mut drained = 0;
+        for batch in self.receiver.try_iter() {
+            drained += batch.len();
+            batch.iter().for_each(|&v| self.writer.insert(v));
+        }
+        self.writer.publish();
+        drained
+    }
+
+    /// Moves the aggregator to a background thread that drains it every `interval`,
+    /// until [`AggregatorHandle::stop`].
+    pub fn spawn(mut self, interval: Duration) -> AggregatorHandle {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let sender = self.sender.clone();
+        let reader = self.reader();
+        let recorder_capacity = self.recorder_capacity;
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                self.drain();
+            }
+            self.drain();
+            self
+        });
+        AggregatorHandle {
+            stop,
+            thread,
+            sender,
+            reader,
+            recorder_capacity,
+        }
+    }
+}
+
+/// An [`Aggregator`] draining on a background thread, see [`Aggregator::spawn`].
+#[derive(Debug)]
+pub struct AggregatorHandle {
+    stop: Sender<()>,
+    thread: JoinHandle<Aggregator>,
+    sender: Sender<Vec<f64>>,
+    reader: SharedTDigest,
+    recorder_capacity: usize,
+}
+
+impl AggregatorHandle {
+    /// A new recorder for one thread, see [`Aggregator::recorder`].
+    pub fn recorder(&self) -> LocalRecorder {
+        LocalRecorder {
+            buffer: Vec::new(),
+            capacity: self.recorder_capacity,
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// A handle to the master digest as of the last drain.
+    pub fn reader(&self) -> SharedTDigest {
+        self.reader.clone()
+    }
+
+    /// Stops the background thread after a last drain and returns the aggregator, e.g.
+    /// to read its final digest.
+    pub fn stop(self) -> Aggregator {
+        let _ = self.stop.send(());
+        match self.thread.join() {
+            Ok(aggregator) => aggregator,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorders_hand_over_batches() {
+        let mut aggregator = Aggregator::with_recorder_capacity(100, 10);
+        let reader = aggregator.reader();
+        let mut recorder = aggregator.recorder();
+        for i in 0..25 {
+            recorder.record(f64::from(i));
+        }
+        assert_eq!(recorder.buffered_len(), 5);
+        assert_eq!(aggregator.drain(), 20);
+        assert_eq!(reader.load().count(), 20.0);
+
+        drop(recorder);
+        assert_eq!(aggregator.drain(), 5);
+        assert_eq!(reader.load().count(), 25.0);
+        assert_eq!(aggregator.digest().max(), 24.0);
+
+        let mut recorder = aggregator.recorder();
+        for i in 0..40_000 {
+            recorder.record(f64::from(i));
+        }
+        drop(recorder);
+        aggregator.drain();
+        assert!((reader.load().median() - 20_000.0).abs() < 200.0);
+    }
+
+    #[test]
+    fn test_background_aggregation() {
+        let handle = Aggregator::new(100).spawn(Duration::from_millis(1));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let mut recorder = handle.recorder();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        recorder.record(f64::from(t * 10_000 + i));
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        let reader = handle.reader();
+        let aggregator = handle.stop();
+        assert_eq!(aggregator.digest().count(), 40_000.0);
+        // Batches arrive in an order that depends on thread timing, so only the totals,
+        // not the estimates, are deterministic here.
+        assert_eq!(reader.load().count(), 40_000.0);
+        assert_eq!((reader.load().min(), reader.load().max()), (0.0, 39_999.0));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{Centroid, TDigest, TDigestError};
+use arrow_array::builder::{Float64Builder, ListBuilder};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, UInt64Type};
+use arrow_array::{Array, ArrayRef, Float64Array, StructArray, UInt64Array};
+use arrow_schema::{DataType, Field, Fields};
+use std::sync::Arc;
+use std::vec;
+use std::vec::Vec;
+
+fn item_field() -> Field {
+    Field::new("item", DataType::Float64, false)
+}
+
+fn encoding_error(reason: &'static str) -> TDigestError {
+    TDigestError::InvalidEncoding { reason }
+}
+
+impl TDigest {
+    /// Fields of the canonical Arrow layout of a digest: the centroid `means` and
+    /// `weights` as two lists of equal length, followed by the `sum`, `count`, `min`,
+    /// `max` and `max_size` scalars. One struct row holds one digest.
+    pub fn arrow_fields() -> Fields {
+        let list = DataType::List(Arc::new(item_field()));
+        Fields::from(vec![
+            Field::new("means", list.clone(), false),
+            Field::new("weights", list, false),
+            Field::new("sum", DataType::Float64, false),
+            Field::new("count", DataType::Float64, false),
+            Field::new("min", DataType::Float64, true),
+            Field::new("max", DataType::Float64, true),
+            Field::new("max_size", DataType::UInt64, false),
+        ])
+    }
+
+    /// The Arrow struct type of a digest, see [`TDigest::arrow_fields`].
+    pub fn arrow_data_type() -> DataType {
+        DataType::Struct(Self::arrow_fields())
+    }
+
+    /// Encodes the digests as an Arrow struct array, one row each, compressing buffered
+    /// values first. An empty digest stores null `min`/`max`.
+    pub fn to_arrow(digests: &[TDigest]) -> StructArray {
+        let mut means = ListBuilder::new(Float64Builder::new()).with_field(item_field());
+        let mut weights = ListBuilder::new(Float64Builder::new()).with_field(item_field());
+        let (mut sums, mut counts, mut mins, mut maxs, mut max_sizes) =
+            (vec![], vec![], vec![], vec![], vec![]);
+
+        for digest in digests {
+            let flushed;
+            let digest = if digest.buffer.is_empty() {
+                digest
+            } else {
+                let mut copy = digest.clone();
+                copy.flush();
+                flushed = copy;
+                &flushed
+            };
+
+            means
+                .values()
+                .extend(digest.centroids.iter().map(|c| Some(c.mean())));
+            means.append(true);
+            weights
+                .values()
+                .extend(digest.centroids.iter().map(|c| Some(c.weight())));
+            weights.append(true);
+            sums.push(digest.sum());
+            counts.push(digest.count());
+            let empty = digest.centroids.is_empty();
+            mins.push((!empty).then_some(digest.min()));
+            maxs.push((!empty).then_some(digest.max()));
+            max_sizes.push(digest.max_size() as u64);
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(means.finish()),
+            Arc::new(weights.finish()),
+            Arc::new(Float64Array::from(sums)),
+            Arc::new(Float64Array::from(counts)),
+            Arc::new(Float64Array::from(mins)),
+            Arc::new(Float64Array::from(maxs)),
+            Arc::new(UInt64Array::from(max_sizes)),
+        ];
+        StructArray::new(Self::arrow_fields(), columns, None)
+    }
+
+    /// Decodes a struct array in the layout of [`TDigest::arrow_fields`], giving `None`
+    /// for null rows. Columns are looked up by name, and every digest is checked like
+    /// [`TDigest::try_new`].
+    pub fn from_arrow(array: &StructArray) -> Result<Vec<Option<TDigest>>, TDigestError> {
+        let column = |name: &str| {
+            array
+                .column_by_name(name)
+                .ok_or_else(|| encoding_error("missing column"))
+        };
+        let list = |name: &str| {
+            column(name)?
+                .as_list_opt::<i32>()
+                .ok_or_else(|| encoding_error("centroid column is not a list"))
+        };
+        let float = |name: &str| {
+            column(name)?
+                .as_primitive_opt::<Float64Type>()
+                .ok_or_else(|| encoding_error("scalar column is not Float64"))
+        };
+
+        let (means, weights) = (list("means")?, list("weights")?);
+        let (sums, counts, mins, maxs) =
+            (float("sum")?, float("count")?, float("min")?, float("max")?);
+        let max_sizes = column("max_size")?
+            .as_primitive_opt::<UInt64Type>()
+            .ok_or_else(|| encoding_error("max_size column is not UInt64"))?;
+
+        (0..array.len())
+            .map(|row| {
+                if array.is_null(row) {
+                    return Ok(None);
+                }
+
+                let row_means = means.value(row);
+                let row_weights = weights.value(row);
+                let (Some(row_means), Some(row_weights)) = (
+                    row_means.as_primitive_opt::<Float64Type>(),
+                    row_weights.as_primitive_opt::<Float64Type>(),
+                ) else {
+                    return Err(encoding_error("centroid values are not Float64"));
+                };
+                if row_means.len() != row_weights.len() {
+                    return Err(encoding_error("means and weights differ in length"));
+                }
+                if row_means.null_count() > 0 || row_weights.null_count() > 0 {
+                    return Err(encoding_error("null centroid"));
+                }
+
+                let centroids: Vec<Centroid> = row_means
+                    .values()
+                    .iter()
+                    .zip(row_weights.values().iter())
+                    .map(|(&mean, &weight)| Centroid { mean, weight })
+                    .collect();
+                let value_or_nan = |column: &Float64Array| {
+                    if column.is_null(row) {
+                        f64::NAN
+                    } else {
+                        column.value(row)
+                    }
+                };
+                TDigest::try_new(
+                    centroids,
+                    max_sizes.value(row) as usize,
+                    sums.value(row),
+                    counts.value(row),
+                    value_or_nan(maxs),
+                    value_or_nan(mins),
+                )
+                .map(Some)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::builder::{Int64Builder, NullBufferBuilder};
+    use std::vec;
+
+    #[test]
+    fn test_arrow_round_trip() {
+        let mut buffered = TDigest::new_with_size(50);
+        buffered.extend((0..1_000).map(f64::from));
+        let digests = vec![
+            TDigest::new_with_size(100).merge_unsorted(vec![1.0, 2.0, 3.0]),
+            TDigest::new_with_size(20),
+            buffered,
+        ];
+
+        let array = TDigest::to_arrow(&digests);
+        assert_eq!(array.data_type(), &TDigest::arrow_data_type());
+        assert_eq!(array.len(), 3);
+
+        let decoded = TDigest::from_arrow(&array).unwrap();
+        assert_eq!(decoded[0].as_ref(), Some(&digests[0]));
+        assert!(decoded[1].as_ref().unwrap().is_empty());
+        assert_eq!(decoded[1].as_ref().unwrap().max_size(), 20);
+        let mut flushed = digests[2].clone();
+        flushed.flush();
+        assert_eq!(decoded[2].as_ref(), Some(&flushed));
+    }
+
+    #[test]
+    fn test_from_arrow_rejects_bad_layouts() {
+        let array = TDigest::to_arrow(&[TDigest::new_with_size(10).merge_unsorted(vec![1.0])]);
+
+        let mut columns = array.columns().to_vec();
+        columns[1] = columns[0].clone();
+        columns[0] = Arc::new(Float64Array::from(vec![1.0]));
+        let mut fields: Vec<Field> = array.fields().iter().map(|f| f.as_ref().clone()).collect();
+        fields[0] = Field::new("means", DataType::Float64, false);
+        let bad = StructArray::new(Fields::from(fields), columns, None);
+        assert_eq!(
+            TDigest::from_arrow(&bad),
+            Err(encoding_error("centroid column is not a list"))
+        );
+
+        let mut ints = ListBuilder::new(Int64Builder::new());
+        ints.values().append_value(1);
+        ints.append(true);
+        let ints: ArrayRef = Arc::new(ints.finish());
+        let mut columns = array.columns().to_vec();
+        let mut fields: Vec<Field> = array.fields().iter().map(|f| f.as_ref().clone()).collect();
+        fields[0] = Field::new("means", ints.data_type().clone(), false);
+        columns[0] = ints;
+        let bad = StructArray::new(Fields::from(fields), columns, None);
+        assert_eq!(
+            TDigest::from_arrow(&bad),
+            Err(encoding_error("centroid values are not Float64"))
+        );
+
+        let (fields, columns, _) = array.into_parts();
+        let mut nulls = NullBufferBuilder::new(1);
+        nulls.append_null();
+        let with_null = StructArray::new(fields, columns, nulls.finish());
+        assert_eq!(TDigest::from_arrow(&with_null).unwrap(), vec![None]);
+    }
+}
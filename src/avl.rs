@@ -0,0 +1,482 @@
+use crate::centroid::{checked_weight, NeumaierSum};
+use crate::{Centroid, Digest, QuantileSketch, ScaleFn, ScaleFunction, TDigest};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Index of the missing child.
+const NIL: usize = usize::MAX;
+
+/// The tree is rebuilt from a compressed copy of its centroids once it holds this many
+/// times `max_size` of them.
+const COMPRESS_FACTOR: usize = 4;
+
+/// Whether `value` belongs in the left subtree of `node`; ties go right.
+fn goes_left(value: f64, node: &Node) -> bool {
+    value.total_cmp(&node.centroid.mean()) == Ordering::Less
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    centroid: Centroid,
+    left: usize,
+    right: usize,
+    height: u32,
+    /// Total weight of the subtree rooted here.
+    total: f64,
+}
+
+/// A digest that folds every value into its nearest centroid as it arrives, for workloads
+/// dominated by single inserts that are queried rarely.
+///
+/// Centroids live in an AVL tree ordered by mean that also tracks subtree weights, so an
+/// insert finds the nearest centroid and the weight in front of it in `O(log n)`. The
+/// value joins that centroid if the scale function allows it, and starts a new one
+/// otherwise; nothing is buffered. Once the tree grows to `4 * max_size` centroids it is
+/// compressed back to `max_size` like a [`TDigest`].
+///
+/// Queries expand the tree into a [`TDigest`]; use [`AvlTreeDigest::to_digest`] to run
+/// several.
+#[derive(Debug, Clone)]
+pub struct AvlTreeDigest {
+    nodes: Vec<Node>,
+    root: usize,
+    max_size: usize,
+    scale: ScaleFunction,
+    sum: NeumaierSum,
+    count: f64,
+    max: f64,
+    min: f64,
+}
+
+impl AvlTreeDigest {
+    /// Creates a digest of about `max_size` centroids using the default scale function.
+    pub fn new(max_size: usize) -> Self {
+        Self::new_with_scale(max_size, ScaleFunction::default())
+    }
+
+    pub fn new_with_scale(max_size: usize, scale: ScaleFunction) -> Self {
+        AvlTreeDigest {
+            nodes: Vec::new(),
+            root: NIL,
+            max_size,
+            scale,
+            sum: NeumaierSum::default(),
+            count: 0.0,
+            max: f64::NAN,
+            min: f64::NAN,
+        }
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.sum.value()
+    }
+
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    #[inline]
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Number of centroids in the tree, between compressions up to `4 * max_size`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The centroids in ascending order of mean.
+    pub fn centroids(&self) -> Vec<Centroid> {
+        let mut centroids = Vec::with_capacity(self.nodes.len());
+        let mut stack = Vec::new();
+        let mut node = self.root;
+        while node != NIL || !stack.is_empty() {
+            while node != NIL {
+                stack.push(node);
+                node = self.nodes[node].left;
+            }
+            let top = stack.pop().unwrap();
+            centroids.push(self.nodes[top].centroid);
+            node = self.nodes[top].right;
+        }
+        centroids
+    }
+
+    fn height(&self, node: usize) -> u32 {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node].height
+        }
+    }
+
+    fn total(&self, node: usize) -> f64 {
+        if node == NIL {
+            0.0
+        } else {
+            self.nodes[node].total
+        }
+    }
+
+    /// Recomputes the height and subtree weight of `node` from its children.
+    fn update(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        let height = self.height(left).max(self.height(right)) + 1;
+        let total = self.total(left) + self.total(right) + self.nodes[node].centroid.weight();
+        self.nodes[node].height = height;
+        self.nodes[node].total = total;
+    }
+
+    fn rotate_left(&mut self, node: usize) -> usize {
+        let pivot = self.nodes[node].right;
+        self.nodes[node].right = self.nodes[pivot].left;
+        self.nodes[pivot].left = node;
+        self.update(node);
+        self.update(pivot);
+        pivot
+    }
+
+    fn rotate_right(&mut self, node: usize) -> usize {
+        let pivot = self.nodes[node].left;
+        self.nodes[node].left = self.nodes[pivot].right;
+        self.nodes[pivot].right = node;
+        self.update(node);
+        self.update(pivot);
+        pivot
+    }
+
+    /// Restores the AVL invariant at `node`, returning the new root of its subtree.
+    fn rebalance(&mut self, node: usize) -> usize {
+        self.update(node);
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        let (hl, hr) = (self.height(left), self.height(right));
+
+        if hl > hr + 1 {
+            if self.height(self.nodes[left].left) < self.height(self.nodes[left].right) {
+                self.nodes[node].left = self.rotate_left(left);
+            }
+            self.rotate_right(node)
+        } else if hr > hl + 1 {
+            if self.height(self.nodes[right].right) < self.height(self.nodes[right].left) {
+                self.nodes[node].right = self.rotate_right(right);
+            }
+            self.rotate_left(node)
+        } else {
+            node
+        }
+    }
+
+    /// Inserts `centroid` into the subtree at `node`, returning its new root.
+    fn insert_at(&mut self, node: usize, centroid: Centroid) -> usize {
+        if node == NIL {
+            self.nodes.push(Node {
+                centroid,
+                left: NIL,
+                right: NIL,
+                height: 1,
+                total: centroid.weight(),
+            });
+            return self.nodes.len() - 1;
+        }
+
+        if goes_left(centroid.mean(), &self.nodes[node]) {
+            let left = self.insert_at(self.nodes[node].left, centroid);
+            self.nodes[node].left = left;
+        } else {
+            let right = self.insert_at(self.nodes[node].right, centroid);
+            self.nodes[node].right = right;
+        }
+        self.rebalance(node)
+    }
+
+    /// Builds a perfectly balanced subtree of the sorted `centroids`, returning its root.
+    fn build(&mut self, centroids: &[Centroid]) -> usize {
+        if centroids.is_empty() {
+            return NIL;
+        }
+
+        let mid = centroids.len() / 2;
+        let left = self.build(&centroids[..mid]);
+        let right = self.build(&centroids[mid + 1..]);
+        self.nodes.push(Node {
+            centroid: centroids[mid],
+            left,
+            right,
+            height: 0,
+            total: 0.0,
+        });
+        let node = self.nodes.len() - 1;
+        self.update(node);
+        node
+    }
+
+    /// The centroid nearest to `value` together with the weight of all centroids before
+    /// it. Both neighbours of `value` lie on its search path, so one descent finds them.
+    fn nearest(&self, value: f64) -> Option<(usize, f64)> {
+        let mut floor: Option<(usize, f64)> = None;
+        let mut ceiling: Option<(usize, f64)> = None;
+        let (mut node, mut head) = (self.root, 0.0);
+        while node != NIL {
+            let n = &self.nodes[node];
+            let before = head + self.total(n.left);
+            if goes_left(value, n) {
+                ceiling = Some((node, before));
+                node = n.left;
+            } else {
+                floor = Some((node, before));
+                head = before + n.centroid.weight();
+                node = n.right;
+            }
+        }
+
+        match (floor, ceiling) {
+            (Some(f), Some(c)) => {
+                let below = value - self.nodes[f.0].centroid.mean();
+                let above = self.nodes[c.0].centroid.mean() - value;
+                Some(if below <= above { f } else { c })
+            }
+            (f, c) => f.or(c),
+        }
+    }
+
+    /// Adds `weight` to the centroid `target`, which lies on the search path of `value`,
+    /// and to the subtree weights above it. Its mean moves towards `value` but never past
+    /// a neighbour, since `target` is the centroid nearest to `value`, so the tree stays
+    /// ordered without restructuring.
+    fn absorb(&mut self, target: usize, value: f64, weight: f64) {
+        let mut node = self.root;
+        loop {
+            self.nodes[node].total += weight;
+            if node == target {
+                self.nodes[node].centroid.update(value * weight, weight);
+                return;
+            }
+            node = if goes_left(value, &self.nodes[node]) {
+                self.nodes[node].left
+            } else {
+                self.nodes[node].right
+            };
+        }
+    }
+
+    /// Adds a single value, see [`AvlTreeDigest::insert_weighted`].
+    pub fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times. NaN and infinite values, and
+    /// weights that are not positive and finite or that would overflow the count, are
+    /// ignored.
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        let Ok(count) = checked_weight(self.count, weight) else {
+            return;
+        };
+        if !value.is_finite() {
+            return;
+        }
+
+        let joined = self.nearest(value).filter(|&(node, before)| {
+            let compression = self.scale.compression(self.max_size, count);
+            let after = before + self.nodes[node].centroid.weight() + weight;
+            self.scale.k(after / count, compression) - self.scale.k(before / count, compression)
+                <= 1.0
+        });
+        match joined {
+            Some((node, _)) => self.absorb(node, value, weight),
+            None => self.root = self.insert_at(self.root, Centroid::new(value, weight)),
+        }
+
+        self.count = count;
+        self.sum.add(value * weight);
+        if self.min.is_nan() || value < self.min {
+            self.min = value;
+        }
+        if self.max.is_nan() || value > self.max {
+            self.max = value;
+        }
+
+        if self.nodes.len() > COMPRESS_FACTOR * self.max_size.max(1) {
+            self.compress();
+        }
+    }
+
+    /// Inserts every centroid of `other` as a weighted value.
+    pub fn merge(&mut self, other: &AvlTreeDigest) {
+        if other.is_empty() {
+            return;
+        }
+
+        for c in other.centroids() {
+            self.insert_weighted(c.mean(), c.weight());
+        }
+        // The centroids' means lie inside `other`'s range but need not reach its ends.
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Compresses the centroids down to about `max_size` and rebuilds a balanced tree.
+    pub fn compress(&mut self) {
+        let centroids = self.centroids();
+        let mut compressed = Vec::with_capacity(self.max_size);
+        TDigest::compress_sorted(
+            centroids,
+            self.count,
+            self.max_size,
+            &self.scale,
+            &mut compressed,
+        );
+
+        self.nodes.clear();
+        self.root = self.build(&compressed);
+    }
+
+    /// Estimates quantile `q`, see [`TDigest::estimate_quantile`]. Expands the tree on
+    /// every call; use [`AvlTreeDigest::to_digest`] for repeated queries.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.to_digest().estimate_quantile(q)
+    }
+
+    /// Estimates the fraction of values at most `x`, see [`TDigest::cdf`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.to_digest().cdf(x)
+    }
+
+    /// A [`TDigest`] holding the tree's centroids as they are, without compressing them.
+    pub fn to_digest(&self) -> TDigest {
+        let mut digest = TDigest::new_with_scale(self.max_size, self.scale);
+        if self.is_empty() {
+            return digest;
+        }
+
+        digest.centroids = self.centroids();
+        digest.sum = self.sum();
+        digest.count = self.count;
+        digest.max = self.max;
+        digest.min = self.min;
+        digest
+    }
+}
+
+impl Digest for AvlTreeDigest {
+    fn insert_weighted(&mut self, value: f64, weight: f64) {
+        AvlTreeDigest::insert_weighted(self, value, weight);
+    }
+
+    fn count(&self) -> f64 {
+        AvlTreeDigest::count(self)
+    }
+
+    fn min(&self) -> f64 {
+        AvlTreeDigest::min(self)
+    }
+
+    fn max(&self) -> f64 {
+        AvlTreeDigest::max(self)
+    }
+
+    fn estimate_quantile(&self, q: f64) -> f64 {
+        AvlTreeDigest::estimate_quantile(self, q)
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        AvlTreeDigest::cdf(self, x)
+    }
+
+    fn to_digest(&self) -> TDigest {
+        AvlTreeDigest::to_digest(self)
+    }
+}
+
+impl QuantileSketch for AvlTreeDigest {
+    fn merge(&mut self, other: &AvlTreeDigest) {
+        AvlTreeDigest::merge(self, other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks ordering, balance and subtree weights below `node`, returning its height.
+    fn check(t: &AvlTreeDigest, node: usize) -> u32 {
+        if node == NIL {
+            return 0;
+        }
+        let n = &t.nodes[node];
+        let (hl, hr) = (check(t, n.left), check(t, n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, hl.max(hr) + 1);
+        let total = t.total(n.left) + t.total(n.right) + n.centroid.weight();
+        assert!((n.total - total).abs() <= 1e-9 * total);
+        n.height
+    }
+
+    #[test]
+    fn test_avl_digest_matches_merging_digest() {
+        let values: Vec<f64> = (0..100_000)
+            .map(|i| ((i * 7919) % 100_000) as f64)
+            .collect();
+
+        let mut tree = AvlTreeDigest::new(100);
+        let mut merging = TDigest::new_with_size(100);
+        for &v in values.iter() {
+            Digest::insert(&mut tree, v);
+            Digest::insert(&mut merging, v);
+        }
+        Digest::flush(&mut merging);
+
+        check(&tree, tree.root);
+        assert!(tree.len() <= 4 * 100);
+        let centroids = tree.centroids();
+        assert!(centroids.windows(2).all(|w| w[0].mean() <= w[1].mean()));
+
+        assert_eq!(Digest::count(&tree), 100_000.0);
+        assert_eq!((tree.min(), tree.max()), (0.0, 99_999.0));
+        assert_eq!(tree.sum(), values.iter().sum::<f64>());
+        for q in [0.001, 0.01, 0.1, 0.5, 0.9, 0.99, 0.999] {
+            let expected = q * 100_000.0;
+            assert!(
+                (tree.estimate_quantile(q) - expected).abs() < 500.0,
+                "q = {}",
+                q
+            );
+            assert!((merging.estimate_quantile(q) - expected).abs() < 500.0);
+        }
+        assert!((Digest::cdf(&tree, 25_000.0) - 0.25).abs() < 0.005);
+    }
+
+    #[test]
+    fn test_avl_digest_small_and_empty() {
+        let mut tree = AvlTreeDigest::new(100);
+        assert!(tree.estimate_quantile(0.5).is_nan());
+        assert!(tree.to_digest().is_empty());
+
+        tree.insert(f64::NAN);
+        tree.insert_weighted(1.0, 0.0);
+        for v in [3.0, 1.0, 2.0] {
+            tree.insert(v);
+        }
+        check(&tree, tree.root);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.estimate_quantile(0.5), 2.0);
+        assert_eq!(tree.to_digest().count(), 3.0);
+    }
+}
@@ -0,0 +1,222 @@
+//! Ad-hoc quantiles over newline-delimited numbers.
+//!
+//! ```text
+//! tdigest [--quantiles 0.5,0.95,0.99] [--compression 100] [--emit] [--digests] [FILE...]
+//! ```
+//!
+//! Reads numbers, one per line, from the files or from stdin (also given as `-`) and
+//! prints `quantile<TAB>estimate` lines. `--emit` writes the digest in the binary format
+//! of `TDigest::to_bytes` instead, and `--digests` reads such digests, one per input, and
+//! merges them, so pipelines can pre-aggregate and combine later. `--compression` is the
+//! number of centroids kept, 100 by default or, for `--digests`, that of the first digest:
+//!
+//! ```text
+//! tdigest --emit a.log > a.td
+//! tdigest --emit b.log > b.td
+//! tdigest --digests a.td b.td
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::ExitCode;
+use tdigest::{InvalidValuePolicy, TDigest};
+
+const USAGE: &str = "usage: tdigest [--quantiles Q,Q,...] [--compression N] [--emit] \
+                     [--digests] [FILE...]";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    quantiles: Vec<f64>,
+    compression: Option<usize>,
+    emit: bool,
+    digests: bool,
+    inputs: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            quantiles: vec![0.5, 0.9, 0.99],
+            compression: None,
+            emit: false,
+            digests: false,
+            inputs: Vec::new(),
+        }
+    }
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--quantiles" | "-q" => {
+                options.quantiles = value(&arg)?
+                    .split(',')
+                    .map(|q| match q.trim().parse::<f64>() {
+                        Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+                        _ => Err(format!("invalid quantile {:?}", q)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                options.quantiles.sort_by(f64::total_cmp);
+            }
+            "--compression" | "-c" => {
+                options.compression = match value(&arg)?.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err("--compression needs a positive integer".into()),
+                };
+            }
+            "--emit" => options.emit = true,
+            "--digests" => options.digests = true,
+            "--help" | "-h" => return Err(USAGE.into()),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option {}\n{}", flag, USAGE));
+            }
+            _ => options.inputs.push(arg),
+        }
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push("-".into());
+    }
+    Ok(options)
+}
+
+fn open(input: &str) -> Result<Box<dyn Read>, String> {
+    if input == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    File::open(input)
+        .map(|file| Box::new(file) as Box<dyn Read>)
+        .map_err(|e| format!("{}: {}", input, e))
+}
+
+/// Adds the numbers of `reader` to `digest`, skipping blank lines.
+fn read_values<R: Read>(digest: &mut TDigest, reader: R, input: &str) -> Result<(), String> {
+    for (line_number, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", input, e))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let value = line
+            .parse::<f64>()
+            .map_err(|_| format!("{}:{}: not a number: {:?}", input, line_number + 1, line))?;
+        digest
+            .try_insert(value)
+            .map_err(|e| format!("{}:{}: {}", input, line_number + 1, e))?;
+    }
+    Ok(())
+}
+
+fn read_digest<R: Read>(mut reader: R, input: &str) -> Result<TDigest, String> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("{}: {}", input, e))?;
+    TDigest::from_bytes(&bytes).map_err(|e| format!("{}: {}", input, e))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let digest = if options.digests {
+        let mut digests = Vec::with_capacity(options.inputs.len());
+        for input in options.inputs.iter() {
+            digests.push(read_digest(open(input)?, input)?);
+        }
+        let merged = TDigest::merge_digests(digests);
+        match options.compression {
+            Some(compression) => merged.recompress(compression),
+            None => merged,
+        }
+    } else {
+        let mut digest = TDigest::builder()
+            .max_size(options.compression.unwrap_or(100))
+            .invalid_value_policy(InvalidValuePolicy::Error)
+            .build();
+        for input in options.inputs.iter() {
+            read_values(&mut digest, open(input)?, input)?;
+        }
+        digest
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let written = if options.emit {
+        out.write_all(&digest.to_bytes())
+    } else {
+        options
+            .quantiles
+            .iter()
+            .zip(digest.estimate_quantiles(&options.quantiles))
+            .try_for_each(|(q, value)| writeln!(out, "{}\t{}", q, value))
+    };
+    written
+        .and_then(|()| out.flush())
+        .map_err(|e| format!("stdout: {}", e))
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(|options| run(&options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("tdigest: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&[]).unwrap(),
+            Options {
+                inputs: vec!["-".into()],
+                ..Options::default()
+            }
+        );
+
+        let options = args(&[
+            "-q",
+            "0.99, 0.5",
+            "--compression",
+            "200",
+            "--emit",
+            "a",
+            "-",
+        ]);
+        let options = options.unwrap();
+        assert_eq!(options.quantiles, vec![0.5, 0.99]);
+        assert_eq!(options.compression, Some(200));
+        assert!(options.emit && !options.digests);
+        assert_eq!(options.inputs, vec!["a", "-"]);
+
+        assert!(args(&["--quantiles", "1.5"]).is_err());
+        assert!(args(&["--compression", "0"]).is_err());
+        assert!(args(&["--compression"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_read_values_and_digests() {
+        let mut digest = TDigest::new_with_size(100);
+        read_values(&mut digest, &b"1\n\n  2.5 \n3\n"[..], "input").unwrap();
+        assert_eq!(digest.count(), 3.0);
+        assert_eq!(digest.sum(), 6.5);
+
+        let err = read_values(&mut digest, &b"4\nfour\n"[..], "input").unwrap_err();
+        assert_eq!(err, "input:2: not a number: \"four\"");
+
+        let bytes = digest.to_bytes();
+        assert_eq!(read_digest(&bytes[..], "input").unwrap().count(), 4.0);
+        assert!(read_digest(&b"junk"[..], "input").is_err());
+    }
+}
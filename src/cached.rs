@@ -0,0 +1,166 @@
+use crate::TDigest;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Quantile answers a [`CachedTDigest`] remembers, by default.
+const DEFAULT_CACHE_CAPACITY: usize = 8;
+
+/// A [`TDigest`] that remembers its most recent quantile estimates, for read-heavy
+/// workloads such as dashboards polling p50, p95 and p99 of a digest that rarely changes.
+///
+/// Up to `capacity` answers are kept, keyed by the exact `q`, so repeated reads of an
+/// unchanged digest cost a scan of at most `capacity` entries instead of a pass over the
+/// centroids. Every mutation, i.e. inserts, merges, flushes and anything done through
+/// [`CachedTDigest::digest_mut`], clears the cache, so answers are always those of the
+/// current digest. The cache sits in a `RefCell`, so a `CachedTDigest` is not `Sync`;
+/// share one per thread, or a [`crate::SharedTDigest`] across threads.
+#[derive(Debug, Clone)]
+pub struct CachedTDigest {
+    digest: TDigest,
+    /// Recent `(q, estimate)` pairs, oldest first.
+    cache: RefCell<Vec<(f64, f64)>>,
+    capacity: usize,
+}
+
+impl CachedTDigest {
+    /// Creates a digest of `max_size` centroids that caches 8 quantile answers.
+    pub fn new(max_size: usize) -> Self {
+        CachedTDigest::with_capacity(TDigest::new_with_size(max_size), DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Wraps `digest`, caching up to `capacity` quantile answers.
+    pub fn with_capacity(digest: TDigest, capacity: usize) -> Self {
+        CachedTDigest {
+            digest,
+            cache: RefCell::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// The underlying digest.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// The underlying digest, for mutations the wrapper does not forward. Clears the
+    /// cache.
+    pub fn digest_mut(&mut self) -> &mut TDigest {
+        self.invalidate();
+        &mut self.digest
+    }
+
+    /// Unwraps the digest.
+    pub fn into_inner(self) -> TDigest {
+        self.digest
+    }
+
+    /// Number of answers currently cached.
+    pub fn cached_len(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    /// Forgets every cached answer.
+    pub fn invalidate(&mut self) {
+        self.cache.get_mut().clear();
+    }
+
+    /// Adds a single value, see [`TDigest::insert`].
+    pub fn insert(&mut self, value: f64) {
+        self.digest_mut().insert(value);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times, see [`TDigest::insert_weighted`].
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        self.digest_mut().insert_weighted(value, weight);
+    }
+
+    /// Folds `other` into `self`, see [`TDigest::merge`].
+    pub fn merge(&mut self, other: &TDigest) {
+        self.digest_mut().merge(other);
+    }
+
+    /// Compresses any buffered values, see [`TDigest::flush`].
+    pub fn flush(&mut self) {
+        self.digest_mut().flush();
+    }
+
+    /// Estimates quantile `q`, see [`TDigest::estimate_quantile`], answering from the
+    /// cache if `q` was asked for since the last mutation.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(&(_, value)) = cache
+            .iter()
+            .find(|(cached, _)| cached.to_bits() == q.to_bits())
+        {
+            return value;
+        }
+
+        let value = self.digest.estimate_quantile(q);
+        if self.capacity > 0 {
+            if cache.len() == self.capacity {
+                cache.remove(0);
+            }
+            cache.push((q, value));
+        }
+        value
+    }
+
+    /// Estimates each quantile in `qs`, see [`CachedTDigest::estimate_quantile`].
+    pub fn estimate_quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        qs.iter().map(|&q| self.estimate_quantile(q)).collect()
+    }
+}
+
+impl From<TDigest> for CachedTDigest {
+    fn from(digest: TDigest) -> CachedTDigest {
+        CachedTDigest::with_capacity(digest, DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl From<CachedTDigest> for TDigest {
+    fn from(cached: CachedTDigest) -> TDigest {
+        cached.digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+
+    #[test]
+    fn test_cache_answers_until_mutation() {
+        let mut cached =
+            CachedTDigest::from(TDigest::new_with_size(50).merge_unsorted(lcg_values(10_000, 4)));
+        let qs = [0.5, 0.95, 0.99];
+        let expected = cached.digest().estimate_quantiles(&qs);
+        assert_eq!(cached.estimate_quantiles(&qs), expected);
+        assert_eq!(cached.cached_len(), 3);
+        assert_eq!(cached.estimate_quantiles(&qs), expected);
+        assert_eq!(cached.cached_len(), 3);
+
+        for v in lcg_values(1_000, 5) {
+            cached.insert(v + 10.0);
+        }
+        assert_eq!(cached.cached_len(), 0);
+        let p99 = cached.estimate_quantile(0.99);
+        assert_eq!(p99, cached.digest().estimate_quantile(0.99));
+        assert!(p99 > 10.0);
+
+        cached.merge(&TDigest::new_with_size(50).merge_unsorted(lcg_values(1_000, 6)));
+        assert_eq!(cached.cached_len(), 0);
+        cached.estimate_quantile(0.5);
+        cached.flush();
+        assert_eq!(cached.cached_len(), 0);
+        cached.estimate_quantile(0.5);
+        cached.digest_mut().scale_weight(2.0);
+        assert_eq!(cached.cached_len(), 0);
+
+        // The oldest answer makes way once the cache is full.
+        let mut small = CachedTDigest::with_capacity(TDigest::from_iter(lcg_values(100, 7)), 2);
+        small.estimate_quantiles(&[0.1, 0.2, 0.3]);
+        assert_eq!(small.cached_len(), 2);
+        assert_eq!(small.cache.get_mut().first().map(|&(q, _)| q), Some(0.2));
+        assert!(CachedTDigest::new(10).estimate_quantile(0.5).is_nan());
+    }
+}
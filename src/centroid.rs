@@ -0,0 +1,355 @@
+use crate::TDigestError;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+// Implementing the Centroid data structure
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub struct Centroid {
+    pub(crate) mean: f64,
+    pub(crate) weight: f64,
+}
+
+impl PartialOrd for Centroid {
+    fn partial_cmp(&self, other: &Centroid) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Centroids are ordered by mean, ties broken by weight, both under `f64::total_cmp`,
+/// which makes the order total and consistent with `Eq`.
+///
+/// Means of a valid digest are always finite. Should a NaN mean slip through in a
+/// release build, `total_cmp` sorts it after every other value, including +inf.
+impl Ord for Centroid {
+    fn cmp(&self, other: &Centroid) -> Ordering {
+        self.mean
+            .total_cmp(&other.mean)
+            .then_with(|| self.weight.total_cmp(&other.weight))
+    }
+}
+
+impl PartialEq for Centroid {
+    fn eq(&self, other: &Centroid) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Centroid {}
+
+/// Hashes the bit patterns of the mean and weight, which `total_cmp` equality compares.
+impl Hash for Centroid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mean.to_bits().hash(state);
+        self.weight.to_bits().hash(state);
+    }
+}
+
+impl Centroid {
+    pub fn new(mean: f64, weight: f64) -> Self {
+        debug_assert!(mean.is_finite(), "centroid mean must be finite");
+        Centroid { mean, weight }
+    }
+
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    #[inline]
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Folds in values summing to `value` with a combined `weight`.
+    ///
+    /// The mean moves by the weighted deviation of the new values rather than being
+    /// recomputed from `mean * weight`, which keeps it accurate for very heavy centroids
+    /// without a compensation term, for which a centroid has no room: the step is rounded
+    /// relative to the deviation, and adding it to the mean costs at most half an ulp of
+    /// the mean however heavy the centroid is, where a running `mean * weight` loses
+    /// precision as the total grows. Compression folds many values in with a single
+    /// update, accumulating their sums with compensated summation first.
+    /// The inputs are not checked: a weight that is not positive and finite gives a NaN or
+    /// infinite centroid, see [`Centroid::try_update`].
+    pub fn update(&mut self, value: f64, weight: f64) -> (f64, f64) {
+        let _weight: f64 = self.weight();
+        let _mean: f64 = self.mean();
+
+        self.weight = weight + _weight;
+        self.mean = _mean + (value - weight * _mean) / self.weight();
+
+        (self.mean(), self.weight())
+    }
+
+    /// Like [`Centroid::update`], but leaves the centroid untouched and fails with
+    /// [`TDigestError::InvalidWeight`] for a `weight` that is not positive and finite,
+    /// [`TDigestError::WeightOverflow`] if the combined weight overflows, and
+    /// [`TDigestError::InvalidValue`] for a non-finite `value`.
+    pub fn try_update(&mut self, value: f64, weight: f64) -> Result<(f64, f64), TDigestError> {
+        checked_weight(self.weight, weight)?;
+        if !value.is_finite() {
+            return Err(TDigestError::InvalidValue { value });
+        }
+        Ok(self.update(value, weight))
+    }
+}
+
+/// `total + weight`, or why `weight` cannot be added to a `total` weight: it is not
+/// positive and finite, or the sum overflows. Every insert path checks its weights here.
+pub(crate) fn checked_weight(total: f64, weight: f64) -> Result<f64, TDigestError> {
+    if !(weight.is_finite() && weight > 0.0) {
+        return Err(TDigestError::InvalidWeight { weight });
+    }
+    let sum = total + weight;
+    if !sum.is_finite() {
+        return Err(TDigestError::WeightOverflow { total, weight });
+    }
+    Ok(sum)
+}
+
+/// Neumaier's compensated sum, which tracks the low-order bits lost by each addition so
+/// that long sums of small values do not drift.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct NeumaierSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl NeumaierSum {
+    pub(crate) fn add(&mut self, value: f64) {
+        let t = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - t) + value;
+        } else {
+            self.compensation += (value - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    pub(crate) fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+/// Compensated sum of `values`, see [`NeumaierSum`].
+pub(crate) fn compensated_sum<I: IntoIterator<Item = f64>>(values: I) -> f64 {
+    let mut sum = NeumaierSum::default();
+    values.into_iter().for_each(|v| sum.add(v));
+    sum.value()
+}
+
+/// Total weight, mean and sum of squared deviations from the mean (`m2`) of weighted
+/// values. Values are added with Welford's update and sets combined with Chan et al.'s
+/// parallel formula, which both stay accurate far from zero, where a raw sum of squares
+/// cancels catastrophically.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Moments {
+    pub(crate) count: f64,
+    pub(crate) mean: f64,
+    pub(crate) m2: f64,
+}
+
+impl Moments {
+    pub(crate) fn add(&mut self, value: f64, weight: f64) {
+        self.count += weight;
+        let delta = value - self.mean;
+        self.mean += delta * weight / self.count;
+        self.m2 += weight * delta * (value - self.mean);
+    }
+
+    pub(crate) fn merge(self, other: Moments) -> Moments {
+        if other.count <= 0.0 {
+            return self;
+        }
+        if self.count <= 0.0 {
+            return other;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        Moments {
+            count,
+            mean: self.mean + delta * other.count / count,
+            m2: compensated_sum([
+                self.m2,
+                other.m2,
+                delta * delta * self.count * other.count / count,
+            ]),
+        }
+    }
+
+    /// Inverse of [`Moments::merge`]: the moments of `self` without the part `other`.
+    pub(crate) fn remove(self, other: Moments) -> Moments {
+        let count = self.count - other.count;
+        if count <= 0.0 {
+            return Moments::default();
+        }
+
+        let mean = (self.mean * self.count - other.mean * other.count) / count;
+        let delta = other.mean - mean;
+        Moments {
+            count,
+            mean,
+            m2: compensated_sum([
+                self.m2,
+                -other.m2,
+                -delta * delta * count * other.count / self.count,
+            ])
+            .max(0.0),
+        }
+    }
+}
+
+impl Default for Centroid {
+    fn default() -> Self {
+        Centroid::new(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TDigest;
+    use alloc::{format, vec};
+
+    #[test]
+    fn test_new_centroid() {
+        let c = Centroid::new(5.0, 1.0);
+        assert_eq!(c.mean(), 5.0);
+        assert_eq!(c.weight(), 1.0);
+    }
+
+    #[test]
+    fn test_update_centroid() {
+        let mut c = Centroid::new(5.0, 1.0);
+        let (new_mean, new_weight) = c.update(7.0, 2.0);
+        assert_eq!(new_mean, (5.0 * 1.0 + 7.0) / 3.0); // New mean
+        assert_eq!(new_weight, 3.0); // New weight
+    }
+
+    #[test]
+    fn test_try_update_rejects_degenerate_weights() {
+        let mut c = Centroid::new(5.0, 1.0);
+        for weight in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                c.try_update(weight, weight),
+                Err(TDigestError::InvalidWeight { .. })
+            ));
+        }
+        assert_eq!(
+            c.try_update(f64::INFINITY, 1.0),
+            Err(TDigestError::InvalidValue {
+                value: f64::INFINITY
+            })
+        );
+        let mut heavy = Centroid::new(5.0, f64::MAX);
+        assert_eq!(
+            heavy.try_update(5.0 * f64::MAX, f64::MAX),
+            Err(TDigestError::WeightOverflow {
+                total: f64::MAX,
+                weight: f64::MAX
+            })
+        );
+        assert_eq!(c, Centroid::new(5.0, 1.0));
+        assert_eq!(c.weight(), 1.0);
+        assert_eq!(c.try_update(7.0, 2.0), Ok(((5.0 + 7.0) / 3.0, 3.0)));
+    }
+
+    #[test]
+    fn test_partial_cmp() {
+        let c1 = Centroid::new(5.0, 1.0);
+        let c2 = Centroid::new(7.0, 1.0);
+        assert!(c1 < c2);
+
+        let c3 = Centroid::new(3.0, 5.0);
+        let c4 = Centroid::new(5.0, 3.0);
+        assert!(c4 > c3);
+    }
+
+    #[test]
+    fn test_ord() {
+        let c1 = Centroid::new(5.0, 1.0);
+        let c2 = Centroid::new(7.0, 1.0);
+        assert_eq!(c1.cmp(&c2), Ordering::Less);
+        assert_eq!(c2.cmp(&c1), Ordering::Greater);
+        let c3 = Centroid::new(5.0, 2.0);
+        assert_eq!(c1.cmp(&c3), Ordering::Less); // Equal means are ordered by weight
+        assert_eq!(c3.cmp(&c1), Ordering::Greater);
+        assert_eq!(c1.cmp(&Centroid::new(5.0, 1.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_ord_nan_sorts_last() {
+        let nan = Centroid {
+            mean: f64::NAN,
+            weight: 1.0,
+        };
+        let inf = Centroid {
+            mean: f64::INFINITY,
+            weight: 1.0,
+        };
+        assert!(nan > inf);
+        assert!(nan > Centroid::new(5.0, 1.0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "centroid mean must be finite")]
+    fn test_new_centroid_rejects_nan_mean() {
+        Centroid::new(f64::NAN, 1.0);
+    }
+
+    #[test]
+    fn test_clone_copy() {
+        let c1 = Centroid::new(5.0, 1.0);
+        let c2 = c1; // Copy
+        assert_eq!(c1, c2);
+        let c3 = c1;
+        assert_eq!(c1, c3);
+    }
+
+    #[test]
+    fn test_debug() {
+        let c = Centroid::new(5.0, 1.0);
+        let debug_str = format!("{:?}", c);
+        let actual_str = "Centroid { mean: 5.0, weight: 1.0 }";
+        assert_eq!(debug_str, actual_str);
+    }
+
+    #[test]
+    fn test_eq() {
+        let c1 = Centroid::new(5.0, 1.0);
+        let c2 = Centroid::new(5.0, 1.0);
+        assert_eq!(c1, c2);
+        let c3 = Centroid::new(5.0, 2.0);
+        assert_ne!(c1, c3); // Centroids are only equal if they have the same mean and weight
+    }
+
+    #[test]
+    fn test_compensated_sum() {
+        let naive = (0..1_000_000).fold(0.0, |sum, _| sum + 0.1);
+        assert!((naive - 100_000.0f64).abs() > 1e-7);
+        assert_eq!(
+            compensated_sum(core::iter::repeat_n(0.1, 1_000_000)),
+            100_000.0
+        );
+
+        let t = TDigest::new_with_size(100).merge_unsorted(vec![0.1; 1_000_000]);
+        assert_eq!(t.sum(), 100_000.0);
+        assert_eq!(t.mean(), 0.1);
+        assert!(t.centroids().all(|c| c.mean() == 0.1));
+
+        let merged = TDigest::merge_digests(vec![t.clone(); 10]);
+        assert_eq!(merged.sum(), 1_000_000.0);
+        assert_eq!(merged.count(), 10_000_000.0);
+
+        let weighted = TDigest::new_with_size(100).merge_weighted(vec![(2.0, 0.1); 1_000_000]);
+        assert_eq!(weighted.count(), 100_000.0);
+        assert_eq!(weighted.geometric_mean(), 2.0);
+        assert_eq!(weighted.trimmed_mean(0.0, 1.0), 2.0);
+    }
+}
@@ -0,0 +1,582 @@
+use crate::centroid::compensated_sum;
+use crate::digest::enclosing_bounds;
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::{Centroid, ScaleFunction, TDigest, TDigestError, TailBias};
+use alloc::vec::Vec;
+
+/// Version byte written by [`TDigest::to_bytes`].
+pub(crate) const ENCODING_VERSION: u8 = 1;
+
+/// Set in the flags byte when every centroid weight is stored as an integer varint.
+pub(crate) const FLAG_INTEGRAL_WEIGHTS: u8 = 1;
+/// Flag bit: the digest compresses under `TailBias::Upper`.
+const FLAG_UPPER_TAIL: u8 = 2;
+/// Flag bit: the digest compresses under `TailBias::Lower`.
+const FLAG_LOWER_TAIL: u8 = 4;
+
+/// Maps `value` to a `u64` whose unsigned order matches the total order of the floats,
+/// so the means of sorted centroids become non-decreasing keys.
+fn order_key(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+fn from_order_key(key: u64) -> f64 {
+    if key >> 63 == 1 {
+        f64::from_bits(key & !(1 << 63))
+    } else {
+        f64::from_bits(!key)
+    }
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decodes a varint as written by [`write_varint`], pulling its bytes from `next_byte`;
+/// `invalid` turns a malformed varint into the caller's error type.
+pub(crate) fn read_varint<E>(
+    mut next_byte: impl FnMut() -> Result<u8, E>,
+    invalid: impl FnOnce(TDigestError) -> E,
+) -> Result<u64, E> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = next_byte()?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid(TDigestError::InvalidEncoding {
+        reason: "varint too long",
+    }))
+}
+
+/// Reads `bytes` front to back, failing with [`TDigestError::InvalidEncoding`] once they
+/// run out.
+pub(crate) struct ByteReader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], TDigestError> {
+        if self.bytes.len() < n {
+            return Err(TDigestError::InvalidEncoding {
+                reason: "unexpected end of input",
+            });
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, TDigestError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, TDigestError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64, TDigestError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(buf))
+    }
+
+    fn i16_be(&mut self) -> Result<i16, TDigestError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(i16::from_be_bytes(buf))
+    }
+
+    fn i32_be(&mut self) -> Result<i32, TDigestError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(i32::from_be_bytes(buf))
+    }
+
+    fn f32_be(&mut self) -> Result<f32, TDigestError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(f32::from_be_bytes(buf))
+    }
+
+    fn f64_be(&mut self) -> Result<f64, TDigestError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_be_bytes(buf))
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, TDigestError> {
+        read_varint(|| self.u8(), |e| e)
+    }
+}
+
+impl TDigest {
+    /// Encodes the digest in a compact, versioned little-endian layout:
+    ///
+    /// - `u8` version, `u8` scale function, `u8` flags, `u32` max size
+    /// - `f64` count, sum, min and max
+    /// - varint centroid count, then per centroid the varint delta of its mean's order key
+    ///   from the previous one and its weight, as a varint when every weight is a whole
+    ///   number and as an `f64` otherwise
+    ///
+    /// Sorted means of nearby centroids have small key deltas, so most take a few bytes.
+    /// Buffered values are compressed into the encoding; the buffer size is not stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if !self.buffer.is_empty() {
+            let mut flushed = self.clone();
+            flushed.flush();
+            return flushed.to_bytes();
+        }
+
+        let integral = self
+            .centroids
+            .iter()
+            .all(|c| c.weight().fract() == 0.0 && c.weight() <= u64::MAX as f64);
+        let scale = match self.scale {
+            ScaleFunction::K0 => 0,
+            ScaleFunction::K1 => 1,
+            ScaleFunction::K2 => 2,
+            ScaleFunction::K3 => 3,
+        };
+
+        let mut out = Vec::with_capacity(39 + self.centroids.len() * 4);
+        out.push(ENCODING_VERSION);
+        out.push(scale);
+        let bias = match self.tail_bias {
+            TailBias::Symmetric => 0,
+            TailBias::Upper => FLAG_UPPER_TAIL,
+            TailBias::Lower => FLAG_LOWER_TAIL,
+        };
+        out.push(if integral { FLAG_INTEGRAL_WEIGHTS } else { 0 } | bias);
+        out.extend_from_slice(&(self.max_size.min(u32::MAX as usize) as u32).to_le_bytes());
+        for value in [self.count(), self.sum(), self.min(), self.max()] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        write_varint(&mut out, self.centroids.len() as u64);
+        let mut prev_key = 0;
+        for centroid in self.centroids.iter() {
+            let key = order_key(centroid.mean());
+            write_varint(&mut out, key - prev_key);
+            prev_key = key;
+            if integral {
+                write_varint(&mut out, centroid.weight() as u64);
+            } else {
+                out.extend_from_slice(&centroid.weight().to_le_bytes());
+            }
+        }
+
+        out
+    }
+
+    /// Decodes a digest written by [`TDigest::to_bytes`].
+    ///
+    /// Fails on an unknown version, truncated input, trailing bytes, or centroids that are
+    /// not finite with positive weight.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TDigest, TDigestError> {
+        let invalid = |reason| TDigestError::InvalidEncoding { reason };
+        let mut reader = ByteReader { bytes };
+
+        let version = reader.u8()?;
+        if version != ENCODING_VERSION {
+            return Err(TDigestError::UnsupportedVersion { version });
+        }
+        let scale = match reader.u8()? {
+            0 => ScaleFunction::K0,
+            1 => ScaleFunction::K1,
+            2 => ScaleFunction::K2,
+            3 => ScaleFunction::K3,
+            _ => return Err(invalid("unknown scale function")),
+        };
+        let flags = reader.u8()?;
+        let integral = flags & FLAG_INTEGRAL_WEIGHTS != 0;
+        let tail_bias = match (flags & FLAG_UPPER_TAIL != 0, flags & FLAG_LOWER_TAIL != 0) {
+            (false, false) => TailBias::Symmetric,
+            (true, false) => TailBias::Upper,
+            (false, true) => TailBias::Lower,
+            (true, true) => return Err(invalid("conflicting tail biases")),
+        };
+        let max_size = reader.u32()? as usize;
+        let count = reader.f64()?;
+        let sum = reader.f64()?;
+        let min = reader.f64()?;
+        let max = reader.f64()?;
+
+        let len = reader.varint()?;
+        // Every centroid takes at least two bytes, which bounds the preallocation.
+        if len > (reader.bytes.len() / 2) as u64 {
+            return Err(invalid("centroid count exceeds input length"));
+        }
+        let mut centroids = Vec::with_capacity(len as usize);
+        let mut key = 0u64;
+        for _ in 0..len {
+            key = key
+                .checked_add(reader.varint()?)
+                .ok_or(invalid("centroid mean out of range"))?;
+            let mean = from_order_key(key);
+            let weight = if integral {
+                reader.varint()? as f64
+            } else {
+                reader.f64()?
+            };
+            if !mean.is_finite() || !weight.is_finite() || weight <= 0.0 {
+                return Err(invalid("centroid is not finite with positive weight"));
+            }
+            centroids.push(Centroid::new(mean, weight));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+
+        let mut digest = TDigest::new_with_scale(max_size, scale);
+        digest.tail_bias = tail_bias;
+        digest.centroids = centroids;
+        digest.count = count;
+        digest.sum = sum;
+        (digest.min, digest.max) = enclosing_bounds(&digest.centroids, min, max);
+        Ok(digest)
+    }
+}
+
+/// Layouts of the Java reference implementation's `MergingDigest.asBytes` (`Verbose`) and
+/// `asSmallBytes` (`Small`). Both are big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JavaEncoding {
+    /// Code 1: `f64` min, max and compression, `i32` centroid count, then `f64` weight and
+    /// mean per centroid.
+    Verbose,
+    /// Code 2: `f64` min and max, `f32` compression, `i16` array size, buffer size and
+    /// centroid count, then `f32` weight and mean per centroid. The array size is the
+    /// capacity Java allocates for centroids and must be at least the count.
+    Small,
+}
+
+impl JavaEncoding {
+    fn code(self) -> i32 {
+        match self {
+            JavaEncoding::Verbose => 1,
+            JavaEncoding::Small => 2,
+        }
+    }
+
+    /// The most centroids the encoding's count field can hold.
+    fn centroid_limit(self) -> usize {
+        match self {
+            JavaEncoding::Verbose => i32::MAX as usize,
+            JavaEncoding::Small => i16::MAX as usize,
+        }
+    }
+}
+
+impl TDigest {
+    /// Encodes the digest like the Java `MergingDigest`, with `max_size` written as the
+    /// compression. [`JavaEncoding::Small`] rounds means and weights to `f32`, and writes
+    /// the array size and buffer size Java would allocate for that compression.
+    ///
+    /// Fails with [`TDigestError::TooManyCentroids`] if the encoding's count field cannot
+    /// hold every centroid, `i16::MAX` of them for [`JavaEncoding::Small`].
+    pub fn to_java_bytes(&self, encoding: JavaEncoding) -> Result<Vec<u8>, TDigestError> {
+        if !self.buffer.is_empty() {
+            let mut flushed = self.clone();
+            flushed.flush();
+            return flushed.to_java_bytes(encoding);
+        }
+
+        if self.centroids.len() > encoding.centroid_limit() {
+            return Err(TDigestError::TooManyCentroids {
+                centroids: self.centroids.len(),
+                limit: encoding.centroid_limit(),
+            });
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&encoding.code().to_be_bytes());
+        out.extend_from_slice(&self.min().to_be_bytes());
+        out.extend_from_slice(&self.max().to_be_bytes());
+
+        match encoding {
+            JavaEncoding::Verbose => {
+                out.extend_from_slice(&(self.max_size as f64).to_be_bytes());
+                out.extend_from_slice(&(self.centroids.len() as i32).to_be_bytes());
+                for centroid in self.centroids.iter() {
+                    out.extend_from_slice(&centroid.weight().to_be_bytes());
+                    out.extend_from_slice(&centroid.mean().to_be_bytes());
+                }
+            }
+            JavaEncoding::Small => {
+                // Java sizes its arrays as `2 * compression` plus some slack for the
+                // weight limit, and its buffer at five times that.
+                let slack = if self.max_size < 30 { 30 } else { 10 };
+                let size = (2 * self.max_size + slack).max(self.centroids.len());
+                let short = |n: usize| n.min(i16::MAX as usize) as i16;
+                out.extend_from_slice(&(self.max_size as f32).to_be_bytes());
+                out.extend_from_slice(&short(size).to_be_bytes());
+                out.extend_from_slice(&short(5 * size).to_be_bytes());
+                out.extend_from_slice(&(self.centroids.len() as i16).to_be_bytes());
+                for centroid in self.centroids.iter() {
+                    out.extend_from_slice(&(centroid.weight() as f32).to_be_bytes());
+                    out.extend_from_slice(&(centroid.mean() as f32).to_be_bytes());
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Decodes bytes written by the Java `MergingDigest` in either [`JavaEncoding`],
+    /// detected from the leading code. The compression becomes `max_size`; `count` and
+    /// `sum` are rebuilt from the centroids, which Java does not store separately. Java
+    /// keeps up to about twice as many centroids as its compression, so they are
+    /// recompressed if there are more than `max_size`.
+    pub fn from_java_bytes(bytes: &[u8]) -> Result<TDigest, TDigestError> {
+        let invalid = |reason| TDigestError::InvalidEncoding { reason };
+        let mut reader = ByteReader { bytes };
+
+        let code = reader.i32_be()?;
+        let min = reader.f64_be()?;
+        let max = reader.f64_be()?;
+        let (compression, len, small) = match code {
+            1 => (reader.f64_be()?, reader.i32_be()? as i64, false),
+            2 => {
+                let compression = f64::from(reader.f32_be()?);
+                let size = reader.i16_be()?;
+                let _buffer_size = reader.i16_be()?;
+                let len = reader.i16_be()?;
+                if len > size {
+                    return Err(invalid("centroid count exceeds the array size"));
+                }
+                (compression, i64::from(len), true)
+            }
+            _ => return Err(invalid("unknown java encoding")),
+        };
+        // Capped at what `to_bytes` can store, so a corrupt payload cannot ask for a
+        // digest of an absurd size.
+        if !(1.0..=f64::from(u32::MAX)).contains(&compression) {
+            return Err(invalid("compression out of range"));
+        }
+        let centroid_bytes = if small { 8 } else { 16 };
+        if len < 0 || len as u64 * centroid_bytes != reader.bytes.len() as u64 {
+            return Err(invalid("centroid count does not match input length"));
+        }
+
+        let mut centroids = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let (weight, mean) = if small {
+                (f64::from(reader.f32_be()?), f64::from(reader.f32_be()?))
+            } else {
+                (reader.f64_be()?, reader.f64_be()?)
+            };
+            if !mean.is_finite() || !weight.is_finite() || weight <= 0.0 {
+                return Err(invalid("centroid is not finite with positive weight"));
+            }
+            centroids.push(Centroid::new(mean, weight));
+        }
+        centroids.sort();
+
+        let mut digest = TDigest::new_with_size(compression.round() as usize);
+        if !centroids.is_empty() {
+            digest.count = compensated_sum(centroids.iter().map(|c| c.weight()));
+            (digest.min, digest.max) = enclosing_bounds(&centroids, min, max);
+            if centroids.len() > digest.max_size {
+                digest.sum = digest.compress_with(centroids.into_iter(), digest.count);
+            } else {
+                digest.sum = compensated_sum(centroids.iter().map(|c| c.mean() * c.weight()));
+                digest.centroids = centroids;
+            }
+        }
+        Ok(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+    use alloc::vec;
+
+    /// `asBytes` and `asSmallBytes` of a Java `MergingDigest(100)` holding 1.0 to 5.0, as
+    /// laid out by t-digest 3.3: the small encoding records Java's array size of 210 and
+    /// buffer size of 1050 for that compression.
+    const JAVA_VERBOSE_1_TO_5: [u8; 112] = [
+        0x00, 0x00, 0x00, 0x01, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x59, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x05, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3f, 0xf0, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x08, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x10,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40,
+        0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    const JAVA_SMALL_1_TO_5: [u8; 70] = [
+        0x00, 0x00, 0x00, 0x02, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0xc8, 0x00, 0x00, 0x00, 0xd2, 0x04, 0x1a, 0x00, 0x05,
+        0x3f, 0x80, 0x00, 0x00, 0x3f, 0x80, 0x00, 0x00, 0x3f, 0x80, 0x00, 0x00, 0x40, 0x00, 0x00,
+        0x00, 0x3f, 0x80, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0x3f, 0x80, 0x00, 0x00, 0x40, 0x80,
+        0x00, 0x00, 0x3f, 0x80, 0x00, 0x00, 0x40, 0xa0, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let t =
+            TDigest::new_with_scale(50, ScaleFunction::K2).merge_unsorted(lcg_values(10_000, 3));
+        let bytes = t.to_bytes();
+        assert!(bytes.len() < 8 * 2 * t.centroids.len());
+        assert_eq!(TDigest::from_bytes(&bytes), Ok(t));
+
+        let mut mixed = TDigest::new_with_size(100);
+        mixed.insert_weighted(-2.5, 0.5);
+        mixed.insert_weighted(0.0, 3.0);
+        mixed.insert(7.0);
+        let decoded = TDigest::from_bytes(&mixed.to_bytes()).unwrap();
+        mixed.flush();
+        assert_eq!(decoded, mixed);
+
+        let empty = TDigest::new_with_size(100);
+        assert_eq!(TDigest::from_bytes(&empty.to_bytes()).unwrap().count(), 0.0);
+        assert!(TDigest::from_bytes(&empty.to_bytes())
+            .unwrap()
+            .min()
+            .is_nan());
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_bad_input() {
+        let bytes = TDigest::new_with_size(100)
+            .merge_unsorted(vec![1.0, 2.0, 3.0])
+            .to_bytes();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = 9;
+        assert_eq!(
+            TDigest::from_bytes(&wrong_version),
+            Err(TDigestError::UnsupportedVersion { version: 9 })
+        );
+        assert!(matches!(
+            TDigest::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(TDigestError::InvalidEncoding { .. })
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(TDigest::from_bytes(&trailing).is_err());
+        assert!(TDigest::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_java_bytes_round_trip() {
+        let t = TDigest::new_with_size(100).merge_unsorted(lcg_values(5_000, 11));
+
+        let verbose = t.to_java_bytes(JavaEncoding::Verbose).unwrap();
+        assert_eq!(&verbose[..4], &[0, 0, 0, 1]);
+        assert_eq!(verbose.len(), 32 + 16 * t.centroids.len());
+        let decoded = TDigest::from_java_bytes(&verbose).unwrap();
+        assert_eq!(decoded.centroids, t.centroids);
+        assert_eq!(decoded.count(), t.count());
+        assert_eq!(decoded.max_size(), 100);
+        assert_eq!((decoded.min(), decoded.max()), (t.min(), t.max()));
+
+        let small = t.to_java_bytes(JavaEncoding::Small).unwrap();
+        assert_eq!(&small[..4], &[0, 0, 0, 2]);
+        let decoded = TDigest::from_java_bytes(&small).unwrap();
+        assert_eq!(decoded.centroids.len(), t.centroids.len());
+        assert!((decoded.estimate_quantile(0.5) - t.estimate_quantile(0.5)).abs() < 1e-6);
+
+        let wide = TDigest::new_with_size(40_000).merge_unsorted(lcg_values(40_000, 12));
+        assert_eq!(
+            wide.to_java_bytes(JavaEncoding::Small),
+            Err(TDigestError::TooManyCentroids {
+                centroids: 40_000,
+                limit: i16::MAX as usize
+            })
+        );
+        assert!(wide.to_java_bytes(JavaEncoding::Verbose).is_ok());
+    }
+
+    #[test]
+    fn test_java_bytes_golden() {
+        let t = TDigest::new_with_size(100).merge_sorted(vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(
+            t.to_java_bytes(JavaEncoding::Verbose).unwrap(),
+            JAVA_VERBOSE_1_TO_5
+        );
+        assert_eq!(
+            t.to_java_bytes(JavaEncoding::Small).unwrap(),
+            JAVA_SMALL_1_TO_5
+        );
+
+        for bytes in [&JAVA_VERBOSE_1_TO_5[..], &JAVA_SMALL_1_TO_5[..]] {
+            let decoded = TDigest::from_java_bytes(bytes).unwrap();
+            assert_eq!(decoded, t);
+        }
+    }
+
+    #[test]
+    fn test_from_java_bytes_recompresses_or_rejects_oversized() {
+        // Java keeps about twice its compression in centroids: 150 of them at compression 100.
+        let mut bytes = vec![0, 0, 0, 1];
+        for v in [0.0f64, 149.0, 100.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(&150i32.to_be_bytes());
+        for i in 0..150 {
+            bytes.extend_from_slice(&10.0f64.to_be_bytes());
+            bytes.extend_from_slice(&f64::from(i).to_be_bytes());
+        }
+        let t = TDigest::from_java_bytes(&bytes).unwrap();
+        assert!(t.centroids.len() <= t.max_size());
+        assert_eq!(t.count(), 1_500.0);
+        assert_eq!(t.sum(), 10.0 * (0..150).sum::<i32>() as f64);
+        assert_eq!((t.min(), t.max()), (0.0, 149.0));
+
+        // A small encoding claiming more centroids than its array size.
+        let mut small = JAVA_SMALL_1_TO_5;
+        small[24..26].copy_from_slice(&4i16.to_be_bytes());
+        assert!(TDigest::from_java_bytes(&small).is_err());
+
+        // A verbose encoding asking for an absurd compression.
+        let mut huge = bytes.clone();
+        huge[20..28].copy_from_slice(&1e300f64.to_be_bytes());
+        assert_eq!(
+            TDigest::from_java_bytes(&huge),
+            Err(TDigestError::InvalidEncoding {
+                reason: "compression out of range"
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_java_bytes_hand_built() {
+        // A Java MergingDigest with compression 100 holding 1.0 twice and 3.0 once.
+        let mut bytes = vec![0, 0, 0, 1];
+        for v in [1.0f64, 3.0, 100.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(&2i32.to_be_bytes());
+        for v in [2.0f64, 1.0, 1.0, 3.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+
+        let t = TDigest::from_java_bytes(&bytes).unwrap();
+        assert_eq!(t.count(), 3.0);
+        assert_eq!(t.sum(), 5.0);
+        assert_eq!(t.estimate_quantile(1.0), 3.0);
+
+        assert!(TDigest::from_java_bytes(&bytes[..bytes.len() - 8]).is_err());
+        bytes[3] = 7;
+        assert!(TDigest::from_java_bytes(&bytes).is_err());
+    }
+}
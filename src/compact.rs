@@ -0,0 +1,195 @@
+use crate::digest::enclosing_bounds;
+use crate::{Centroid, ScaleFunction, TDigest, TailBias};
+use alloc::vec::Vec;
+
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+/// Compact storage form of a [`TDigest`] for keeping very many digests around: centroid
+/// means and weights are stored as `f32`, halving their footprint, while the totals stay
+/// `f64` and all arithmetic happens in `f64` on the way back to a [`TDigest`].
+///
+/// Rounding to `f32` keeps about seven significant digits per mean, and weights above
+/// 2^24 lose integer precision; `count`, `sum`, `min` and `max` are kept exactly.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub struct CompactTDigest {
+    /// `(mean, weight)` per centroid, in ascending order of mean.
+    centroids: Vec<(f32, f32)>,
+    max_size: usize,
+    scale: ScaleFunction,
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    tail_bias: TailBias,
+    sum: f64,
+    count: f64,
+    #[cfg_attr(feature = "use_serde", serde(with = "crate::nan_as_none"))]
+    max: f64,
+    #[cfg_attr(feature = "use_serde", serde(with = "crate::nan_as_none"))]
+    min: f64,
+}
+
+impl CompactTDigest {
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Number of stored centroids.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.centroids.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Memory held in bytes, like [`TDigest::byte_size`].
+    pub fn byte_size(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.centroids.capacity() * core::mem::size_of::<(f32, f32)>()
+    }
+
+    /// Expands back into a full [`TDigest`] for querying or merging.
+    pub fn to_digest(&self) -> TDigest {
+        TDigest::from(self)
+    }
+
+    /// Estimates quantile `q`, see [`TDigest::estimate_quantile`]. Expands the digest on
+    /// every call; use [`CompactTDigest::to_digest`] for repeated queries.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.to_digest().estimate_quantile(q)
+    }
+
+    /// Estimates the fraction of values at most `x`, see [`TDigest::cdf`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.to_digest().cdf(x)
+    }
+}
+
+impl From<&TDigest> for CompactTDigest {
+    /// Rounds the centroids to `f32`, compressing any buffered values first.
+    fn from(digest: &TDigest) -> Self {
+        if !digest.buffer.is_empty() {
+            let mut flushed = digest.clone();
+            flushed.flush();
+            return CompactTDigest::from(&flushed);
+        }
+
+        CompactTDigest {
+            centroids: digest
+                .centroids
+                .iter()
+                .map(|c| (c.mean() as f32, c.weight() as f32))
+                .collect(),
+            max_size: digest.max_size,
+            scale: digest.scale,
+            tail_bias: digest.tail_bias,
+            sum: digest.sum(),
+            count: digest.count(),
+            max: digest.max(),
+            min: digest.min(),
+        }
+    }
+}
+
+impl From<TDigest> for CompactTDigest {
+    fn from(digest: TDigest) -> Self {
+        CompactTDigest::from(&digest)
+    }
+}
+
+impl From<&CompactTDigest> for TDigest {
+    /// Widens the centroids back to `f64`, clamping rounded means into `[min, max]`. Bounds
+    /// that are NaN or out of order, e.g. from a hand-edited serialized form, are ignored
+    /// and the centroid means enclosed instead.
+    fn from(compact: &CompactTDigest) -> Self {
+        let mut digest = TDigest::new_with_scale(compact.max_size, compact.scale);
+        digest.tail_bias = compact.tail_bias;
+        if compact.centroids.is_empty() {
+            return digest;
+        }
+
+        let (min, max) = if compact.min <= compact.max {
+            (compact.min, compact.max)
+        } else {
+            (f64::NAN, f64::NAN)
+        };
+        digest.centroids = compact
+            .centroids
+            .iter()
+            .map(|&(mean, weight)| {
+                // `f64::max`/`min` skip a NaN bound, where `clamp` would panic.
+                let mean = f64::from(mean).max(min).min(max);
+                Centroid::new(mean, f64::from(weight))
+            })
+            .collect();
+        digest.sum = compact.sum;
+        digest.count = compact.count;
+        (digest.min, digest.max) = enclosing_bounds(&digest.centroids, min, max);
+        digest
+    }
+}
+
+impl From<CompactTDigest> for TDigest {
+    fn from(compact: CompactTDigest) -> Self {
+        TDigest::from(&compact)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        let values: Vec<f64> = (0..10_000).map(|i| (i as f64 * 0.37).sin() * 1e3).collect();
+        let t = TDigest::new_with_size(100).merge_unsorted(values);
+
+        let compact = CompactTDigest::from(&t);
+        assert_eq!(compact.len(), t.len());
+        assert!(compact.byte_size() < t.byte_size());
+        assert_eq!(
+            (compact.count(), compact.sum(), compact.min(), compact.max()),
+            (t.count(), t.sum(), t.min(), t.max())
+        );
+
+        let restored = compact.to_digest();
+        assert_eq!(restored.max_size(), 100);
+        for q in [0.0, 0.01, 0.5, 0.99, 1.0] {
+            let diff = restored.estimate_quantile(q) - t.estimate_quantile(q);
+            assert!(diff.abs() < 1e-3, "q = {}: {}", q, diff);
+        }
+        assert!((compact.cdf(0.0) - t.cdf(0.0)).abs() < 1e-6);
+
+        let empty = CompactTDigest::from(TDigest::new_with_size(10));
+        assert!(empty.is_empty());
+        assert!(TDigest::from(empty).min().is_nan());
+
+        // Bounds that are NaN or inverted, which `Deserialize` does not rule out.
+        for (min, max) in [(f64::NAN, 5.0), (f64::NAN, f64::NAN), (5.0, 1.0)] {
+            let mut broken = compact.clone();
+            (broken.min, broken.max) = (min, max);
+            let digest = broken.to_digest();
+            assert_eq!(digest.count(), t.count());
+            assert!(digest.min() < digest.max());
+            assert!((digest.median() - t.median()).abs() < 1e-3);
+        }
+    }
+}
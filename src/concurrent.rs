@@ -0,0 +1,397 @@
+use crate::TDigest;
+use std::boxed::Box;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread_local;
+use std::vec::Vec;
+
+/// Values a shard holds before it is folded into the shared digest, by default.
+const DEFAULT_SHARD_CAPACITY: usize = 1024;
+
+/// Bits of a slot holding no value. `record` drops NaN, so no value has these bits.
+const EMPTY: u64 = f64::NAN.to_bits();
+
+/// Source of the per-thread home shard indices, handed out round-robin.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static HOME_SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic while holding a lock cannot leave a buffer or digest half-updated in a way
+    // that matters for estimates, so poisoning is ignored.
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A fixed-capacity write buffer of `f64` bits. Writers claim slots with `next`, count
+/// finished writes in `written`, and whoever finishes the last slot folds the shard.
+#[derive(Debug)]
+struct Shard {
+    /// The slots, allocated on first use; null until then.
+    slots: AtomicPtr<Box<[AtomicU64]>>,
+    /// The next slot to claim; at or past the capacity while the shard is full.
+    next: AtomicUsize,
+    /// Claimed slots that have been written, or given up by `flush`.
+    written: AtomicUsize,
+    /// Odd while the shard is being folded; tags the batch a fold hands over.
+    epoch: AtomicU64,
+}
+
+impl Shard {
+    fn new() -> Self {
+        Shard {
+            slots: AtomicPtr::new(core::ptr::null_mut()),
+            next: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    /// The slots, if allocated.
+    fn slots(&self) -> Option<&[AtomicU64]> {
+        let slots = self.slots.load(Ordering::Acquire);
+        // SAFETY: a non-null pointer comes from `Box::into_raw` in `slots_or_alloc` and
+        // is only freed by `drop`, which has exclusive access.
+        (!slots.is_null()).then(|| unsafe { &**slots })
+    }
+
+    /// The slots, allocating `capacity` empty ones if no thread has yet.
+    fn slots_or_alloc(&self, capacity: usize) -> &[AtomicU64] {
+        if let Some(slots) = self.slots() {
+            return slots;
+        }
+
+        let fresh: Box<[AtomicU64]> = (0..capacity).map(|_| AtomicU64::new(EMPTY)).collect();
+        let fresh = Box::into_raw(Box::new(fresh));
+        let null = core::ptr::null_mut();
+        match self
+            .slots
+            .compare_exchange(null, fresh, Ordering::AcqRel, Ordering::Acquire)
+        {
+            // SAFETY: as in `slots`; the loser of a race frees its own, unpublished box.
+            Ok(_) => unsafe { &*fresh },
+            Err(existing) => unsafe {
+                drop(Box::from_raw(fresh));
+                &*existing
+            },
+        }
+    }
+
+    /// The values of the slots written so far; only consistent while not being folded.
+    fn values(slots: &[AtomicU64]) -> impl Iterator<Item = f64> + '_ {
+        slots
+            .iter()
+            .map(|slot| slot.load(Ordering::Acquire))
+            .filter(|&bits| bits != EMPTY)
+            .map(f64::from_bits)
+    }
+}
+
+impl Drop for Shard {
+    fn drop(&mut self) {
+        let slots = *self.slots.get_mut();
+        if !slots.is_null() {
+            // SAFETY: see `Shard::slots`; nothing can borrow the slots any more.
+            drop(unsafe { Box::from_raw(slots) });
+        }
+    }
+}
+
+/// Values handed over by a fold, tagged with their shard and its epoch while it filled.
+#[derive(Debug)]
+struct Batch {
+    source: Option<(usize, u64)>,
+    values: Vec<f64>,
+}
+
+/// The shared digest and the batches handed over to it but not yet merged.
+#[derive(Debug)]
+struct Shared {
+    digest: TDigest,
+    batches: Receiver<Batch>,
+}
+
+impl Shared {
+    /// Merges every batch handed over so far, returning the tags of the shard batches.
+    fn merge_batches(&mut self) -> BTreeSet<(usize, u64)> {
+        let mut merged = BTreeSet::new();
+        while let Ok(batch) = self.batches.try_recv() {
+            self.digest.extend(batch.values);
+            merged.extend(batch.source);
+        }
+        merged
+    }
+}
+
+/// A digest many threads can record into at once, for metrics hot paths.
+///
+/// Writers store values into one of several sharded buffers of atomic slots, so
+/// [`ConcurrentTDigest::record`] is lock-free: it never waits for another thread. The
+/// writer that finishes the last slot of a shard hands the shard's values over to the
+/// shared digest through a channel and merges them in if the shared digest is not
+/// locked at the time; otherwise whoever holds it next does, e.g. the next
+/// [`ConcurrentTDigest::snapshot`]. Each thread has a home shard; while it is full,
+/// `record` moves on to the next one, and hands its value over on its own if every
+/// shard is full.
+///
+/// Shard buffers are allocated on their first value, so idle digests, e.g. one per
+/// metric key, stay small. NaN and infinite values are ignored, as by the default
+/// [`crate::InvalidValuePolicy`].
+///
+/// [`ConcurrentTDigest::snapshot`] includes every value whose `record` call returned
+/// before it began. Readers lock the shared digest, and wait for any shard in the middle
+/// of being folded.
+#[derive(Debug)]
+pub struct ConcurrentTDigest {
+    shards: Vec<Shard>,
+    shard_capacity: usize,
+    batches: Sender<Batch>,
+    shared: Mutex<Shared>,
+}
+
+impl ConcurrentTDigest {
+    /// Creates a digest of `max_size` centroids with one shard per available CPU.
+    pub fn new(max_size: usize) -> Self {
+        let n_shards = std::thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(max_size, n_shards, DEFAULT_SHARD_CAPACITY)
+    }
+
+    /// Creates a digest of `max_size` centroids whose `n_shards` write buffers each hold
+    /// up to `shard_capacity` values before being folded into the shared digest. The
+    /// buffers are allocated when first written to.
+    pub fn with_shards(max_size: usize, n_shards: usize, shard_capacity: usize) -> Self {
+        assert!(n_shards > 0, "a concurrent digest needs at least one shard");
+        let (batches, receiver) = channel();
+        ConcurrentTDigest {
+            shards: (0..n_shards).map(|_| Shard::new()).collect(),
+            shard_capacity: shard_capacity.max(1),
+            batches,
+            shared: Mutex::new(Shared {
+                digest: TDigest::new_with_size(max_size),
+                batches: receiver,
+            }),
+        }
+    }
+
+    /// Records `value` from any thread without waiting for other threads.
+    pub fn record(&self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+
+        let n = self.shards.len();
+        let home = HOME_SHARD.with(|i| *i) % n;
+        for i in 0..n {
+            let index = (home + i) % n;
+            let shard = &self.shards[index];
+            let slots = shard.slots_or_alloc(self.shard_capacity);
+            let slot = shard.next.fetch_add(1, Ordering::AcqRel);
+            if slot >= self.shard_capacity {
+                continue;
+            }
+            slots[slot].store(value.to_bits(), Ordering::Release);
+            self.finish_writes(index, slots, 1);
+            return;
+        }
+
+        // Every shard is full and waiting for its last writers.
+        self.hand_over(Batch {
+            source: None,
+            values: Vec::from([value]),
+        });
+    }
+
+    /// Counts `n` more slots of shard `index` as written, folding it if that was the
+    /// last of them.
+    fn finish_writes(&self, index: usize, slots: &[AtomicU64], n: usize) {
+        let shard = &self.shards[index];
+        if shard.written.fetch_add(n, Ordering::AcqRel) + n == self.shard_capacity {
+            self.fold(index, slots);
+        }
+    }
+
+    /// Hands the values of the full shard `index` over to the shared digest and reopens
+    /// the shard. Only the writer finishing its last slot calls this, so no slot is
+    /// written meanwhile.
+    fn fold(&self, index: usize, slots: &[AtomicU64]) {
+        let shard = &self.shards[index];
+        let epoch = shard.epoch.fetch_add(1, Ordering::AcqRel);
+        let values = slots
+            .iter()
+            .map(|slot| slot.swap(EMPTY, Ordering::AcqRel))
+            .filter(|&bits| bits != EMPTY)
+            .map(f64::from_bits)
+            .collect();
+        // Sent before the shard reopens, so readers find either the values in the shard
+        // or the batch tagged with the epoch they saw.
+        let source = Some((index, epoch));
+        let _ = self.batches.send(Batch { source, values });
+
+        shard.written.store(0, Ordering::Release);
+        shard.epoch.fetch_add(1, Ordering::AcqRel);
+        shard.next.store(0, Ordering::Release);
+        self.try_merge();
+    }
+
+    /// Sends `batch` to the shared digest, merging it in if nobody holds it.
+    fn hand_over(&self, batch: Batch) {
+        let _ = self.batches.send(batch);
+        self.try_merge();
+    }
+
+    /// Merges the handed over batches if the shared digest is free, without waiting.
+    fn try_merge(&self) {
+        let mut shared = match self.shared.try_lock() {
+            Ok(shared) => shared,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        shared.merge_batches();
+    }
+
+    /// Folds every shard into the shared digest and compresses it. Values whose `record`
+    /// is still writing them are folded by that `record` instead.
+    pub fn flush(&self) {
+        for (index, shard) in self.shards.iter().enumerate() {
+            let Some(slots) = shard.slots() else {
+                continue;
+            };
+            // Claims the free slots, leaving them empty, so the shard counts as full.
+            let claimed = shard.next.fetch_add(self.shard_capacity, Ordering::AcqRel);
+            if claimed < self.shard_capacity {
+                self.finish_writes(index, slots, self.shard_capacity - claimed);
+            }
+        }
+        let mut shared = lock(&self.shared);
+        shared.merge_batches();
+        shared.digest.flush();
+    }
+
+    /// A consistent copy of everything recorded so far.
+    pub fn snapshot(&self) -> TDigest {
+        let mut shared = lock(&self.shared);
+        let mut unfolded = Vec::with_capacity(self.shards.len());
+        for (index, shard) in self.shards.iter().enumerate() {
+            let Some(slots) = shard.slots() else {
+                continue;
+            };
+            loop {
+                let epoch = shard.epoch.load(Ordering::Acquire);
+                if epoch % 2 == 1 {
+                    std::hint::spin_loop();
+                    continue;
+                }
+                let values: Vec<f64> = Shard::values(slots).collect();
+                if shard.epoch.load(Ordering::Acquire) == epoch {
+                    unfolded.push(((index, epoch), values));
+                    break;
+                }
+            }
+        }
+
+        // A shard folded since it was read has handed over exactly the values read.
+        let merged = shared.merge_batches();
+        let mut digest = shared.digest.clone();
+        for (source, values) in unfolded {
+            if !merged.contains(&source) {
+                digest.extend(values);
+            }
+        }
+        digest.flush();
+        digest
+    }
+
+    /// Consumes the concurrent digest, returning everything recorded.
+    pub fn into_digest(self) -> TDigest {
+        self.flush();
+        let shared = self.shared.into_inner().unwrap_or_else(|e| e.into_inner());
+        shared.digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_concurrent_record() {
+        let digest = Arc::new(ConcurrentTDigest::with_shards(100, 4, 64));
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let digest = Arc::clone(&digest);
+                std::thread::spawn(move || {
+                    for i in 0..10_000 {
+                        digest.record((t * 10_000 + i) as f64);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let snapshot = digest.snapshot();
+        assert_eq!(snapshot.count(), 80_000.0);
+        assert_eq!(snapshot.min(), 0.0);
+        assert_eq!(snapshot.max(), 79_999.0);
+        // Each thread records an ascending run, a hard case for the digest.
+        assert!((snapshot.estimate_quantile(0.5) - 40_000.0).abs() < 2_000.0);
+
+        let digest = Arc::try_unwrap(digest).unwrap();
+        assert_eq!(digest.into_digest().count(), 80_000.0);
+    }
+
+    #[test]
+    fn test_snapshots_during_writes_see_each_value_once() {
+        let digest = Arc::new(ConcurrentTDigest::with_shards(100, 2, 16));
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let digest = Arc::clone(&digest);
+                std::thread::spawn(move || (0..20_000).for_each(|i| digest.record(f64::from(i))))
+            })
+            .collect();
+        let mut last = 0.0;
+        while writers.iter().any(|w| !w.is_finished()) {
+            let count = digest.snapshot().count();
+            assert!(count >= last && count <= 80_000.0);
+            last = count;
+        }
+        writers.into_iter().for_each(|w| w.join().unwrap());
+        assert_eq!(digest.snapshot().count(), 80_000.0);
+    }
+
+    #[test]
+    fn test_snapshot_includes_unfolded_values() {
+        let digest = ConcurrentTDigest::with_shards(100, 2, 1_000);
+        for v in [1.0, 2.0, 3.0, f64::NAN] {
+            digest.record(v);
+        }
+        assert_eq!(digest.snapshot().count(), 3.0);
+        assert!(lock(&digest.shared).digest.is_empty());
+
+        digest.flush();
+        assert_eq!(lock(&digest.shared).digest.count(), 3.0);
+        assert_eq!(digest.snapshot().count(), 3.0);
+        digest.record(4.0);
+        assert_eq!(digest.into_digest().count(), 4.0);
+    }
+
+    #[test]
+    fn test_shards_allocate_on_first_value() {
+        let digest = ConcurrentTDigest::with_shards(100, 4, 1_000);
+        assert!(digest.shards.iter().all(|s| s.slots().is_none()));
+
+        digest.record(1.0);
+        let allocated: Vec<usize> = digest
+            .shards
+            .iter()
+            .map(|s| s.slots().map_or(0, <[AtomicU64]>::len))
+            .collect();
+        assert_eq!(allocated.iter().filter(|&&n| n > 0).count(), 1);
+        assert!(allocated.iter().all(|&n| n == 0 || n == 1_000));
+    }
+}
@@ -0,0 +1,151 @@
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::{TDigest, TDigestError};
+
+/// A [`TDigest`] whose weights are genuine observation counts: values go in with `u64`
+/// counts, centroid weights come out as `u64`, and [`CountedTDigest::count`] is an exact
+/// integer however many values were recorded.
+///
+/// Centroid weights are sums of integer counts, so they stay exact integers in the
+/// underlying `f64` storage while each is below 2^53, and the binary encoding of
+/// [`TDigest::to_bytes`] stores them as integer varints. Means and all estimates are still
+/// computed in `f64`. Operations that produce fractional weights, like decay, are only
+/// available on the [`TDigest`] this converts into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountedTDigest {
+    digest: TDigest,
+    count: u64,
+}
+
+impl CountedTDigest {
+    /// Creates a digest of `max_size` centroids.
+    pub fn new(max_size: usize) -> Self {
+        CountedTDigest {
+            digest: TDigest::new_with_size(max_size),
+            count: 0,
+        }
+    }
+
+    /// Records a single observation of `value`.
+    pub fn insert(&mut self, value: f64) {
+        self.insert_n(value, 1);
+    }
+
+    /// Records `n` observations of `value`. Values the digest rejects under its
+    /// [`crate::InvalidValuePolicy`] are not counted.
+    pub fn insert_n(&mut self, value: f64, n: u64) {
+        if n > 0 && self.digest.ingest(value, n as f64) {
+            self.count += n;
+        }
+    }
+
+    /// Folds `other` into `self`, see [`TDigest::merge`].
+    pub fn merge(&mut self, other: &CountedTDigest) {
+        self.digest.merge(&other.digest);
+        self.count += other.count;
+    }
+
+    /// The exact number of observations.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The underlying digest, for queries.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// `(mean, count)` per centroid, in ascending order of mean, compressing buffered
+    /// values first.
+    pub fn centroids(&mut self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.digest.flush();
+        self.digest
+            .centroids()
+            .map(|c| (c.mean(), c.weight() as u64))
+    }
+
+    /// Estimates quantile `q`, see [`TDigest::estimate_quantile`].
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.digest.estimate_quantile(q)
+    }
+
+    /// Estimates the fraction of observations at most `x`, see [`TDigest::cdf`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.digest.cdf(x)
+    }
+}
+
+impl From<CountedTDigest> for TDigest {
+    fn from(counted: CountedTDigest) -> TDigest {
+        counted.digest
+    }
+}
+
+impl TryFrom<TDigest> for CountedTDigest {
+    type Error = TDigestError;
+
+    /// Fails with [`TDigestError::NonIntegralWeight`] at the first centroid or buffered
+    /// value whose weight is not a whole count.
+    fn try_from(digest: TDigest) -> Result<Self, TDigestError> {
+        let weights = digest.centroids.iter().chain(digest.buffer.iter());
+        if let Some(index) = weights
+            .map(|c| c.weight())
+            .position(|w| !(0.0..=u64::MAX as f64).contains(&w) || w.fract() != 0.0)
+        {
+            return Err(TDigestError::NonIntegralWeight { index });
+        }
+        let weights = digest.centroids.iter().chain(digest.buffer.iter());
+        let count = weights.map(|c| c.weight() as u64).sum();
+        Ok(CountedTDigest { digest, count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_counts_stay_integral() {
+        let mut counted = CountedTDigest::new(20);
+        for (i, v) in crate::lcg::lcg_values(5_000, 9).into_iter().enumerate() {
+            counted.insert_n(v, i as u64 % 3 + 1);
+        }
+        counted.insert_n(f64::NAN, 10);
+        counted.insert_n(1.0, 0);
+        let expected: u64 = (0..5_000u64).map(|i| i % 3 + 1).sum();
+        assert_eq!(counted.count(), expected);
+        assert_eq!(counted.digest().count(), expected as f64);
+
+        let total: u64 = counted.centroids().map(|(_, n)| n).sum();
+        assert_eq!(total, expected);
+
+        let round_trip = TDigest::from_bytes(&counted.digest().to_bytes()).unwrap();
+        let restored = CountedTDigest::try_from(round_trip).unwrap();
+        assert_eq!(restored.count(), expected);
+
+        let mut other = counted.clone();
+        other.merge(&counted);
+        assert_eq!(other.count(), 2 * expected);
+        assert!(other
+            .digest()
+            .centroids()
+            .all(|c| c.weight().fract() == 0.0));
+
+        let mut fractional = TDigest::new_with_size(20);
+        fractional.extend([(1.0, 1.0), (2.0, 0.5)]);
+        assert_eq!(
+            CountedTDigest::try_from(fractional).map(|_| ()),
+            Err(TDigestError::NonIntegralWeight { index: 1 })
+        );
+        let centroids: Vec<(f64, u64)> = CountedTDigest::new(5).centroids().collect();
+        assert!(centroids.is_empty());
+    }
+}
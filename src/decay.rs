@@ -0,0 +1,265 @@
+use crate::centroid::{compensated_sum, Moments};
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::TDigest;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Centroids lighter than this after a decay step are dropped unless configured otherwise.
+const DEFAULT_PRUNE_WEIGHT: f64 = 1e-3;
+
+/// A [`TDigest`] whose observations fade out over time, for "last few minutes" estimates.
+///
+/// Every decay step multiplies all centroid weights by a factor in `(0, 1]` and drops the
+/// centroids whose weight falls below a pruning threshold. Steps are applied automatically
+/// every `interval` insertions (the count schedule), and on demand through
+/// [`ExponentialDecayTDigest::decay`] or [`ExponentialDecayTDigest::decay_elapsed`] for a
+/// time schedule driven by the caller's clock.
+///
+/// `count` and `sum` are the decayed totals, so `mean` is the exponentially weighted mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialDecayTDigest {
+    digest: TDigest,
+    factor: f64,
+    interval: usize,
+    since_decay: usize,
+    prune_weight: f64,
+}
+
+impl ExponentialDecayTDigest {
+    /// Creates a digest of `max_size` centroids whose weights are multiplied by `factor`
+    /// after every `interval` insertions. An `interval` of zero disables the count
+    /// schedule, leaving only explicit calls to `decay`.
+    pub fn new(max_size: usize, factor: f64, interval: usize) -> Self {
+        assert!(
+            factor > 0.0 && factor <= 1.0,
+            "decay factor must be in (0, 1]"
+        );
+        ExponentialDecayTDigest {
+            digest: TDigest::new_with_size(max_size),
+            factor,
+            interval,
+            since_decay: 0,
+            prune_weight: DEFAULT_PRUNE_WEIGHT,
+        }
+    }
+
+    /// Sets the weight below which decayed centroids are dropped. Defaults to `1e-3`.
+    pub fn with_prune_weight(mut self, prune_weight: f64) -> Self {
+        self.prune_weight = prune_weight;
+        self
+    }
+
+    /// The decay factor applied by the count schedule.
+    #[inline]
+    pub fn factor(&self) -> f64 {
+        self.factor
+    }
+
+    /// Adds one observation with weight 1, decaying first if the count schedule is due.
+    pub fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds one observation with the given weight, decaying first if the count schedule
+    /// is due. Observations the digest rejects, e.g. NaN, do not count towards the
+    /// schedule.
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        if self.interval > 0 && self.since_decay >= self.interval {
+            self.decay(self.factor());
+        }
+        if self.digest.ingest(value, weight) {
+            self.since_decay += 1;
+        }
+    }
+
+    /// Multiplies every weight, including buffered ones, by `factor` and prunes
+    /// negligible centroids. Restarts the count schedule.
+    pub fn decay(&mut self, factor: f64) {
+        self.since_decay = 0;
+        self.digest.decay_weights(factor, self.prune_weight);
+    }
+
+    /// Decays by `0.5^(elapsed / half_life)`, for time-based fading: call it with the time
+    /// since the previous call.
+    pub fn decay_elapsed(&mut self, elapsed: Duration, half_life: Duration) {
+        let halvings = elapsed.as_secs_f64() / half_life.as_secs_f64();
+        self.decay(0.5f64.powf(halvings));
+    }
+
+    /// Compresses buffered observations so the queries below see them.
+    pub fn flush(&mut self) {
+        self.digest.flush();
+    }
+
+    /// The underlying digest, holding the decayed weights.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// Consumes the wrapper, returning the decayed digest with buffered values flushed.
+    pub fn into_digest(mut self) -> TDigest {
+        self.digest.flush();
+        self.digest
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.digest.count()
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.digest.sum()
+    }
+
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        self.digest.mean()
+    }
+
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.digest.min()
+    }
+
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.digest.max()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.digest.is_empty()
+    }
+
+    /// See [`TDigest::estimate_quantile`].
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.digest.estimate_quantile(q)
+    }
+
+    /// See [`TDigest::estimate_quantiles`].
+    pub fn estimate_quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        self.digest.estimate_quantiles(qs)
+    }
+
+    /// See [`TDigest::cdf`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.digest.cdf(x)
+    }
+
+    /// See [`TDigest::rank`].
+    pub fn rank(&self, x: f64) -> f64 {
+        self.digest.rank(x)
+    }
+}
+
+impl TDigest {
+    /// Scales every weight by `factor`, dropping centroids left lighter than `prune_weight`.
+    /// `min`/`max` move inwards to the surviving extreme centroids when a tail is pruned.
+    fn decay_weights(&mut self, factor: f64, prune_weight: f64) {
+        self.flush();
+
+        let centroids = core::mem::take(&mut self.centroids);
+        let first_mean = centroids.first().map(|c| c.mean);
+        let last_mean = centroids.last().map(|c| c.mean);
+        // Pruned centroids take their share of the second moment along, as if all their
+        // values sat at the mean.
+        let before = self.moments().map(|m| Moments {
+            count: m.count * factor,
+            m2: m.m2 * factor,
+            ..m
+        });
+        let mut pruned = Moments::default();
+        self.centroids = centroids
+            .into_iter()
+            .filter_map(|mut c| {
+                c.weight = c.weight() * factor;
+                if c.weight() < prune_weight {
+                    pruned.add(c.mean(), c.weight());
+                }
+                (c.weight() >= prune_weight).then_some(c)
+            })
+            .collect();
+        self.m2 = before.map(|before| before.remove(pruned).m2);
+
+        if self.centroids.is_empty() {
+            self.reset();
+            return;
+        }
+
+        if first_mean != self.centroids.first().map(|c| c.mean) {
+            self.min = self.centroids[0].mean;
+        }
+        if last_mean != self.centroids.last().map(|c| c.mean) {
+            self.max = self.centroids[self.centroids.len() - 1].mean;
+        }
+        self.count = compensated_sum(self.centroids.iter().map(|c| c.weight()));
+        self.sum = compensated_sum(self.centroids.iter().map(|c| c.mean() * c.weight()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_schedule_fades_old_values() {
+        let mut t = ExponentialDecayTDigest::new(100, 0.5, 100);
+        for _ in 0..1_000 {
+            t.insert(10.0);
+        }
+        for _ in 0..1_000 {
+            t.insert(20.0);
+        }
+        t.flush();
+
+        // The 10s have decayed through ten halvings and contribute almost nothing.
+        assert!(t.count() < 200.0);
+        assert_eq!(t.estimate_quantile(0.5), 20.0);
+        assert!(t.mean() > 19.9);
+    }
+
+    #[test]
+    fn test_decay_prunes_and_moves_min() {
+        let mut t = ExponentialDecayTDigest::new(100, 1.0, 0);
+        t.insert(1.0);
+        t.decay(0.01);
+        for v in [5.0, 6.0, 7.0] {
+            t.insert(v);
+        }
+        t.decay(0.5);
+
+        // 1.0 now weighs 0.005 and survives the default threshold ...
+        assert_eq!(t.min(), 1.0);
+        // ... until the next step.
+        t.decay(0.1);
+        assert_eq!(t.min(), 5.0);
+        assert!((t.count() - 0.15).abs() < 1e-12);
+
+        t.decay_elapsed(Duration::from_secs(600), Duration::from_secs(60));
+        assert!(t.is_empty());
+        assert!(t.min().is_nan());
+    }
+
+    #[test]
+    fn test_rejected_values_do_not_advance_the_schedule() {
+        let mut t = ExponentialDecayTDigest::new(100, 0.5, 2);
+        t.insert(1.0);
+        t.insert(f64::NAN);
+        t.insert_weighted(2.0, 0.0);
+        t.insert(3.0);
+        assert_eq!(t.count(), 2.0);
+
+        // Only the third accepted value finds the schedule due.
+        t.insert(4.0);
+        assert_eq!(t.count(), 2.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rejects_growing_factor() {
+        ExponentialDecayTDigest::new(100, 1.5, 10);
+    }
+}
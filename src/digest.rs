@@ -0,0 +1,2386 @@
+use crate::centroid::{checked_weight, compensated_sum, Moments, NeumaierSum};
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::merge::MergeSorted;
+use crate::scale::Scaling;
+use crate::{Centroid, ScaleFn, ScaleFunction, TDigestError, TailBias};
+use alloc::{borrow::Cow, format, string::String, sync::Arc, vec, vec::Vec};
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+/// What ingestion does with NaN and infinite values, which have no meaningful rank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum InvalidValuePolicy {
+    /// Drop them silently.
+    #[default]
+    Ignore,
+    /// Reject them with [`TDigestError::InvalidValue`] from [`TDigest::try_insert`]; the
+    /// infallible entry points, which cannot report it, drop them.
+    Error,
+    /// Panic on the first one.
+    Panic,
+}
+
+/// T-Digest to be operated on.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub struct TDigest {
+    pub(crate) centroids: Vec<Centroid>,
+    pub(crate) max_size: usize,
+    pub(crate) sum: f64,
+    pub(crate) count: f64,
+    #[cfg_attr(feature = "use_serde", serde(with = "crate::nan_as_none"))]
+    pub(crate) max: f64,
+    #[cfg_attr(feature = "use_serde", serde(with = "crate::nan_as_none"))]
+    pub(crate) min: f64,
+    /// Values added by `insert` that have not been compressed into `centroids` yet. The
+    /// totals below already include them.
+    pub(crate) buffer: Vec<Centroid>,
+    /// Always empty; its allocation receives the next compression's output.
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    pub(crate) spare: Vec<Centroid>,
+    /// Always empty between calls; holds sorted batches for the in-place merges, e.g.
+    /// [`TDigest::extend_unsorted`].
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    pub(crate) scratch: Vec<Centroid>,
+    pub(crate) buffer_size: usize,
+    pub(crate) scale: ScaleFunction,
+    /// Compresses in place of `scale` if set; see [`TDigest::with_scale_fn`].
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    pub(crate) custom_scale: Option<Arc<dyn ScaleFn>>,
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub(crate) tail_bias: TailBias,
+    pub(crate) invalid_values: InvalidValuePolicy,
+    /// Sum of `weight * (value - mean)^2` over every ingested value, if second-moment
+    /// tracking is on; see [`TDigestBuilder::second_moment`].
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub(crate) m2: Option<f64>,
+    /// Whether merges put their inputs in a canonical order first and keep the result
+    /// canonical; see [`TDigestBuilder::deterministic`].
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub(crate) deterministic: bool,
+    /// Compression passes run, see [`TDigest::stats`].
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    pub(crate) compressions: u64,
+    /// Values added to `buffer` so far, see [`TDigest::stats`].
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    pub(crate) values_buffered: u64,
+}
+
+/// Digests are equal when their configuration, centroids, buffered values and totals are,
+/// regardless of spare and scratch capacity and [`TDigest::stats`]. Floats compare under
+/// `f64::total_cmp` like [`Centroid`]s, so the NaN `min`/`max` of empty digests are equal
+/// and `Eq` holds.
+impl PartialEq for TDigest {
+    fn eq(&self, other: &TDigest) -> bool {
+        let same = |a: f64, b: f64| a.total_cmp(&b) == Ordering::Equal;
+        self.centroids == other.centroids
+            && self.max_size == other.max_size
+            && same(self.sum, other.sum)
+            && same(self.count, other.count)
+            && same(self.max, other.max)
+            && same(self.min, other.min)
+            && self.buffer == other.buffer
+            && self.buffer_size == other.buffer_size
+            && self.same_scale(other)
+            && self.invalid_values == other.invalid_values
+            && self.deterministic == other.deterministic
+            && match (self.m2, other.m2) {
+                (Some(a), Some(b)) => same(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+}
+
+impl Eq for TDigest {}
+
+/// Hashes what `==` compares, floats by their bit patterns, so digests can key a
+/// `HashMap`, e.g. to deduplicate them by content.
+impl Hash for TDigest {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.centroids.hash(state);
+        self.max_size.hash(state);
+        for value in [self.sum, self.count, self.max, self.min] {
+            value.to_bits().hash(state);
+        }
+        self.buffer.hash(state);
+        self.buffer_size.hash(state);
+        self.scale.hash(state);
+        self.tail_bias.hash(state);
+        if let Some(custom) = &self.custom_scale {
+            Arc::as_ptr(custom).cast::<()>().hash(state);
+        }
+        self.invalid_values.hash(state);
+        self.deterministic.hash(state);
+        self.m2.map(f64::to_bits).hash(state);
+    }
+}
+
+impl TDigest {
+    pub fn new_with_size(max_size: usize) -> Self {
+        TDigest {
+            centroids: Vec::new(),
+            max_size,
+            sum: 0.0,
+            count: 0.0,
+            max: f64::NAN,
+            min: f64::NAN,
+            buffer: Vec::new(),
+            spare: Vec::new(),
+            scratch: Vec::new(),
+            buffer_size: Self::default_buffer_size(max_size),
+            scale: ScaleFunction::default(),
+            custom_scale: None,
+            tail_bias: TailBias::Symmetric,
+            compressions: 0,
+            values_buffered: 0,
+            invalid_values: InvalidValuePolicy::default(),
+            m2: None,
+            deterministic: false,
+        }
+    }
+
+    /// Like `new_with_size`, but compressing with the given scale function instead of
+    /// the default `K1`.
+    pub fn new_with_scale(max_size: usize, scale: ScaleFunction) -> Self {
+        let mut digest = TDigest::new_with_size(max_size);
+        digest.scale = scale;
+        digest
+    }
+
+    /// Like `new_with_size`, but preallocates the centroids for `max_size` and the push
+    /// buffer for `expected_points` inserts, so a known-volume warmup does not
+    /// reallocate. The hint only sizes allocations: the buffer is never preallocated
+    /// beyond the flush threshold, which keeps its default.
+    pub fn with_size_and_hint(max_size: usize, expected_points: usize) -> Self {
+        let mut digest = TDigest::new_with_size(max_size);
+        digest.centroids = Vec::with_capacity(max_size);
+        digest.buffer = Vec::with_capacity(expected_points.min(digest.buffer_size));
+        digest
+    }
+
+    /// Digest holding exactly one point, `value`, with a weight of 1.0.
+    pub fn singleton(value: f64, max_size: usize) -> Self {
+        let mut digest = TDigest::new_with_size(max_size);
+        digest.centroids = vec![Centroid::new(value, 1.0)];
+        digest.sum = value;
+        digest.count = 1.0;
+        digest.max = value;
+        digest.min = value;
+        digest
+    }
+
+    /// Like [`TDigest::new`], but checks the parts instead of trusting them: the centroids
+    /// must be sorted by mean with finite means and non-negative weights, there may be at
+    /// most `max_size` of them, `count` and `sum` must match the centroids up to rounding,
+    /// and `min`/`max` must enclose the centroid means. An empty digest may have any
+    /// `min`/`max`, conventionally NaN.
+    pub fn try_new(
+        centroids: Vec<Centroid>,
+        max_size: usize,
+        sum: f64,
+        count: f64,
+        max: f64,
+        min: f64,
+    ) -> Result<Self, TDigestError> {
+        let mut digest = TDigest::new_with_size(max_size);
+        digest.centroids = centroids;
+        digest.sum = sum;
+        digest.count = count;
+        digest.max = max;
+        digest.min = min;
+        digest.validate()?;
+        Ok(digest)
+    }
+
+    /// A digest of the default 100 centroids from a fixed-bucket histogram, where bucket
+    /// `i` holds the `counts[i]` values in `[bounds[i], bounds[i + 1])`, so there is one
+    /// more bound than there are counts.
+    ///
+    /// Each bucket becomes one centroid at its midpoint weighted by its count, and `min`
+    /// and `max` are the outer bounds of the first and last non-empty buckets, so
+    /// estimates interpolate as if values were spread evenly within buckets. The outer
+    /// bounds may be infinite, like the `+Inf` bucket of a Prometheus histogram, in
+    /// which case that bucket's values are placed at its finite bound.
+    ///
+    /// Fails with [`TDigestError::InvalidHistogram`] unless there is one more bound than
+    /// counts, the bounds are ascending and only the outer ones are infinite. A histogram
+    /// of no buckets gives an empty digest.
+    pub fn from_histogram(bounds: &[f64], counts: &[u64]) -> Result<TDigest, TDigestError> {
+        let invalid = |reason| TDigestError::InvalidHistogram { reason };
+        if bounds.len() != counts.len() + 1 {
+            return Err(invalid("expected one more bound than counts"));
+        }
+        if bounds.iter().any(|b| b.is_nan()) || bounds.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid("bounds are not ascending"));
+        }
+        if counts.is_empty() {
+            return Ok(TDigest::default());
+        }
+        let inner = &bounds[1..bounds.len() - 1];
+        if inner.iter().any(|b| !b.is_finite()) || bounds.iter().all(|b| !b.is_finite()) {
+            return Err(invalid("only the outer bounds may be infinite"));
+        }
+
+        let buckets: Vec<(f64, f64)> = bounds
+            .windows(2)
+            .zip(counts)
+            .filter(|(_, &count)| count > 0)
+            .map(|(w, &count)| {
+                let value = match (w[0].is_finite(), w[1].is_finite()) {
+                    (true, true) => w[0] + (w[1] - w[0]) / 2.0,
+                    (true, false) => w[0],
+                    _ => w[1],
+                };
+                (value, count as f64)
+            })
+            .collect();
+        let (Some(first), Some(last)) = (buckets.first(), buckets.last()) else {
+            return Ok(TDigest::default());
+        };
+        let lo = bounds[counts.iter().position(|&c| c > 0).unwrap_or(0)];
+        let hi = bounds[counts.iter().rposition(|&c| c > 0).unwrap_or(0) + 1];
+        let (min, max) = (
+            if lo.is_finite() { lo } else { first.0 },
+            if hi.is_finite() { hi } else { last.0 },
+        );
+
+        let mut digest = TDigest::default().merge_weighted(buckets);
+        digest.min = min;
+        digest.max = max;
+        Ok(digest)
+    }
+
+    pub(crate) fn default_buffer_size(max_size: usize) -> usize {
+        max_size.saturating_mul(5).max(1)
+    }
+
+    pub fn new(
+        centroids: Vec<Centroid>,
+        max_size: usize,
+        sum: f64,
+        count: f64,
+        max: f64,
+        min: f64,
+    ) -> Self {
+        let (min, max) = enclosing_bounds(&centroids, min, max);
+        if centroids.len() <= max_size {
+            TDigest {
+                centroids,
+                max_size,
+                sum,
+                count,
+                max,
+                min,
+                buffer: Vec::new(),
+                spare: Vec::new(),
+                scratch: Vec::new(),
+                buffer_size: Self::default_buffer_size(max_size),
+                scale: ScaleFunction::default(),
+                custom_scale: None,
+                tail_bias: TailBias::Symmetric,
+                compressions: 0,
+                values_buffered: 0,
+                invalid_values: InvalidValuePolicy::default(),
+                m2: None,
+                deterministic: false,
+            }
+        } else {
+            let sz = centroids.len();
+            let digests: Vec<TDigest> = vec![
+                TDigest::new_with_size(100),
+                TDigest::new(centroids, sz, sum, count, max, min),
+            ];
+            Self::merge_digests(digests)
+        }
+    }
+
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        let count_: f64 = self.count;
+        let sum_: f64 = self.sum;
+
+        if count_ > 0.0 {
+            sum_ / count_
+        } else {
+            f64::NAN
+        }
+    }
+
+    /// Population variance of the values. With second-moment tracking (see
+    /// [`TDigestBuilder::second_moment`]) it is computed from the tracked squared
+    /// deviations; otherwise it is the spread of the centroid means, which misses the
+    /// spread within each centroid and so slightly underestimates unless the digest is
+    /// exact. Returns NaN for an empty digest.
+    pub fn variance(&self) -> f64 {
+        if self.count <= 0.0 {
+            return f64::NAN;
+        }
+
+        let mean = self.mean();
+        let variance = match self.m2 {
+            Some(m2) => m2 / self.count,
+            None => {
+                compensated_sum(
+                    self.view()
+                        .iter()
+                        .map(|c| c.weight() * (c.mean() - mean) * (c.mean() - mean)),
+                ) / self.count
+            }
+        };
+        variance.max(0.0)
+    }
+
+    /// Standard deviation of the values, the square root of [`TDigest::variance`].
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Whether the second moment is tracked, see [`TDigestBuilder::second_moment`].
+    #[inline]
+    pub fn tracks_second_moment(&self) -> bool {
+        self.m2.is_some()
+    }
+
+    /// The tracked moments, if second-moment tracking is on.
+    pub(crate) fn moments(&self) -> Option<Moments> {
+        self.m2.map(|m2| Moments {
+            count: self.count,
+            mean: if self.count > 0.0 {
+                self.sum / self.count
+            } else {
+                0.0
+            },
+            m2,
+        })
+    }
+
+    /// Mean of the values between the `lo_q` and `hi_q` quantiles, computed from the
+    /// centroid weights: a centroid straddling a cut-off contributes the overlapping
+    /// fraction of its weight at its mean. E.g. `trimmed_mean(0.05, 0.95)` drops the
+    /// 5% tails. Returns NaN for an empty digest or unless `0 <= lo_q < hi_q <= 1`.
+    pub fn trimmed_mean(&self, lo_q: f64, hi_q: f64) -> f64 {
+        let (sum, weight) = self.truncated_moments(lo_q, hi_q);
+        sum / weight
+    }
+
+    /// Sum of the values between the `lo_q` and `hi_q` quantiles, apportioned like
+    /// [`TDigest::trimmed_mean`]. Returns NaN under the same conditions.
+    pub fn truncated_sum(&self, lo_q: f64, hi_q: f64) -> f64 {
+        self.truncated_moments(lo_q, hi_q).0
+    }
+
+    /// Weighted sum and total weight of the rank range `[lo_q, hi_q] * count`.
+    fn truncated_moments(&self, lo_q: f64, hi_q: f64) -> (f64, f64) {
+        if self.is_empty()
+            || !(0.0..=1.0).contains(&lo_q)
+            || !(0.0..=1.0).contains(&hi_q)
+            || lo_q >= hi_q
+        {
+            return (f64::NAN, f64::NAN);
+        }
+
+        let lo_rank = lo_q * self.count();
+        let hi_rank = hi_q * self.count();
+
+        let mut sum = NeumaierSum::default();
+        let mut weight = NeumaierSum::default();
+        let mut t = 0.0;
+        for centroid in self.view().iter() {
+            let start = t;
+            t += centroid.weight();
+            if t <= lo_rank {
+                continue;
+            }
+            if start >= hi_rank {
+                break;
+            }
+
+            let overlap = t.min(hi_rank) - start.max(lo_rank);
+            sum.add(overlap * centroid.mean());
+            weight.add(overlap);
+        }
+
+        (sum.value(), weight.value())
+    }
+
+    /// Splits the digest at the sorted quantiles `qs` into `qs.len() + 1` digests of the
+    /// rank ranges `[0, q1)`, `[q1, q2)`, ..., `[qk, 1]`, e.g. `split_at_quantiles(&[0.9])`
+    /// for the bottom nine deciles and the top decile, to model each band on its own.
+    ///
+    /// Weights are apportioned like [`TDigest::trimmed_mean`]: a centroid straddling a cut
+    /// goes to both sides, each getting the overlapping fraction of its weight at its mean,
+    /// so the counts of the bands add up to `count()`. Each band is bounded by the quantile
+    /// estimates at its cuts, widened to enclose its centroid means, and keeps the
+    /// configuration of `self`. Bands of an empty rank range, or of an empty digest, are
+    /// empty. A tracked second moment takes each centroid as a point mass, so it
+    /// understates the variance within a band.
+    pub fn split_at_quantiles(&self, qs: &[f64]) -> Vec<TDigest> {
+        assert!(
+            qs.windows(2).all(|w| w[0] <= w[1]) && qs.iter().all(|q| (0.0..=1.0).contains(q)),
+            "qs must be sorted quantiles in [0, 1]"
+        );
+
+        let mut empty = self.clone();
+        empty.flush();
+        let cuts = empty.estimate_quantiles(qs);
+        let centroids = core::mem::take(&mut empty.centroids);
+        let count = empty.count;
+        let (min, max) = (empty.min, empty.max);
+        empty.reset();
+
+        let mut bands = Vec::with_capacity(qs.len() + 1);
+        // The first centroid not wholly taken by earlier bands, and its starting rank.
+        let (mut next, mut start) = (0, 0.0);
+        for i in 0..=qs.len() {
+            let lo_rank = if i == 0 { 0.0 } else { qs[i - 1] * count };
+            let hi_rank = qs.get(i).map_or(f64::INFINITY, |q| q * count);
+
+            let mut band = empty.clone();
+            let mut moments = Moments::default();
+            let mut t = start;
+            for centroid in centroids[next..].iter() {
+                if t >= hi_rank {
+                    break;
+                }
+                let end = t + centroid.weight();
+                let overlap = end.min(hi_rank) - t.max(lo_rank);
+                if overlap > 0.0 {
+                    band.centroids.push(Centroid::new(centroid.mean(), overlap));
+                    moments.add(centroid.mean(), overlap);
+                }
+                if end > hi_rank {
+                    break;
+                }
+                t = end;
+                next += 1;
+                start = t;
+            }
+
+            band.count = compensated_sum(band.centroids.iter().map(|c| c.weight()));
+            band.sum = compensated_sum(band.centroids.iter().map(|c| c.mean() * c.weight()));
+            let lo = if i == 0 { min } else { cuts[i - 1] };
+            let hi = cuts.get(i).copied().unwrap_or(max);
+            (band.min, band.max) = enclosing_bounds(&band.centroids, lo, hi);
+            band.m2 = band.m2.map(|_| moments.m2);
+            bands.push(band);
+        }
+        bands
+    }
+
+    /// Geometric mean, `exp(sum(weight * ln(mean)) / count)`, over the centroids.
+    ///
+    /// Only defined for positive data: returns NaN if the digest is empty or any centroid
+    /// mean is zero or negative.
+    pub fn geometric_mean(&self) -> f64 {
+        let centroids = self.view();
+        if centroids.is_empty() || centroids.iter().any(|c| c.mean() <= 0.0) {
+            return f64::NAN;
+        }
+
+        let log_sum = compensated_sum(centroids.iter().map(|c| c.weight() * c.mean().ln()));
+        (log_sum / self.count()).exp()
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    /// The largest value ingested, or NaN for an empty digest; see [`TDigest::try_max`].
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// The smallest value ingested, or NaN for an empty digest; see [`TDigest::try_min`].
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// The largest value ingested, or `None` for an empty digest. The bounds of a digest
+    /// with values are never NaN: every constructor and decoder replaces a NaN bound by
+    /// the extreme centroid mean, and merges take the larger bound of the non-empty
+    /// inputs, which empty ones do not contribute to.
+    #[inline]
+    pub fn try_max(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.max)
+    }
+
+    /// The smallest value ingested, or `None` for an empty digest; see
+    /// [`TDigest::try_max`].
+    #[inline]
+    pub fn try_min(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.min)
+    }
+
+    /// Whether no value has been ingested, buffered values included.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty() && self.buffer.is_empty()
+    }
+
+    #[inline]
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The built-in scale function, which a custom [`TDigest::scale_fn`] overrides.
+    #[inline]
+    pub fn scale_function(&self) -> ScaleFunction {
+        self.scale
+    }
+
+    /// The scale function compression uses: the custom one if set, see
+    /// [`TDigest::with_scale_fn`], and otherwise [`TDigest::scale_function`], under the
+    /// [`TDigest::tail_bias`].
+    pub fn scale_fn(&self) -> impl ScaleFn + '_ {
+        self.scaling()
+    }
+
+    pub(crate) fn scaling(&self) -> Scaling<'_> {
+        Scaling {
+            scale: self.scale,
+            custom: self.custom_scale.as_deref(),
+            bias: self.tail_bias,
+        }
+    }
+
+    /// Which tail compression favours, see [`TDigestBuilder::tail_bias`].
+    #[inline]
+    pub fn tail_bias(&self) -> TailBias {
+        self.tail_bias
+    }
+
+    /// Whether `self` and `other` compress under the same scale function, custom ones
+    /// being the same if they are shared.
+    pub(crate) fn same_scale(&self, other: &TDigest) -> bool {
+        self.scale == other.scale
+            && self.tail_bias == other.tail_bias
+            && match (&self.custom_scale, &other.custom_scale) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+
+    /// Number of centroids, not counting buffered values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Number of inserted values waiting in the buffer for the next compression.
+    #[inline]
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Memory held by the digest in bytes: the struct itself plus the allocated capacity
+    /// of the centroid list, the insert buffer, the spare compression buffer and the
+    /// scratch buffer of in-place merges.
+    pub fn byte_size(&self) -> usize {
+        let capacity = self.centroids.capacity()
+            + self.buffer.capacity()
+            + self.spare.capacity()
+            + self.scratch.capacity();
+        core::mem::size_of::<Self>() + capacity * core::mem::size_of::<Centroid>()
+    }
+
+    /// Counters of the work the digest has done and the memory it holds, e.g. to see how
+    /// often a workload compresses, or to compare configurations alongside the criterion
+    /// benchmarks under `benches/`. Digests from [`TDigest::merge_digests`] carry the
+    /// counts of their inputs; clones carry them over, and decoded digests start at zero.
+    pub fn stats(&self) -> DigestStats {
+        DigestStats {
+            compressions: self.compressions,
+            values_buffered: self.values_buffered,
+            centroids_allocated: self.centroids.capacity()
+                + self.buffer.capacity()
+                + self.spare.capacity()
+                + self.scratch.capacity(),
+        }
+    }
+
+    /// Resets the counters of [`TDigest::stats`] to zero, e.g. after a warmup.
+    pub fn reset_stats(&mut self) {
+        self.compressions = 0;
+        self.values_buffered = 0;
+    }
+
+    /// Compresses buffered values and releases unused capacity of the centroid list and
+    /// the buffers, e.g. before keeping many idle digests around. Later inserts and
+    /// merges regrow the buffers.
+    pub fn shrink_to_fit(&mut self) {
+        self.flush();
+        self.centroids.shrink_to_fit();
+        self.buffer.shrink_to_fit();
+        self.spare.shrink_to_fit();
+        self.scratch.shrink_to_fit();
+    }
+
+    /// The centroids in ascending order of mean. Unlike the queries, this shows the
+    /// compressed structure only: values still in the insert buffer are not included
+    /// until [`TDigest::flush`].
+    pub fn centroids(&self) -> impl Iterator<Item = &Centroid> {
+        self.centroids.iter()
+    }
+
+    /// The centroids with any buffered values merged in, each as its own uncompressed
+    /// centroid, which is what queries run on. Borrows the centroids while nothing is
+    /// buffered.
+    pub(crate) fn view(&self) -> Cow<'_, [Centroid]> {
+        if self.buffer.is_empty() {
+            return Cow::Borrowed(&self.centroids);
+        }
+
+        let mut buffered = self.buffer.clone();
+        buffered.sort_unstable();
+        Cow::Owned(MergeSorted::new(self.centroids.iter().copied(), buffered.into_iter()).collect())
+    }
+
+    /// Consumes the digest, returning its centroids in ascending order of mean with any
+    /// buffered values compressed in.
+    pub fn into_centroids(mut self) -> Vec<Centroid> {
+        self.flush();
+        self.centroids
+    }
+
+    /// Yields `(mean, cumulative_weight)` for every centroid, where `cumulative_weight`
+    /// is the total weight of that centroid and all before it, i.e. a step approximation
+    /// of the unnormalised CDF. Like [`TDigest::centroids`] it excludes buffered values.
+    pub fn cumulative(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.centroids.iter().scan(0.0, |t, c| {
+            *t += c.weight();
+            Some((c.mean(), *t))
+        })
+    }
+}
+
+impl TDigest {
+    /// Number of inserted values buffered before they are compressed into the centroids.
+    #[inline]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Sets how many inserted values are buffered before compressing; values are
+    /// compressed immediately if more than that are already buffered. Larger buffers
+    /// amortise compression better at the cost of memory.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.buffer_size = buffer_size.max(1);
+        if self.buffer.len() >= self.buffer_size {
+            self.flush();
+        }
+    }
+
+    /// Adds a single value. Values are buffered and compressed into the centroids once
+    /// the buffer fills up. `count`, `sum`, `min` and `max` include them right away, and
+    /// queries merge them into a temporary copy of the centroids; call
+    /// [`TDigest::flush`] first when running several queries on a digest with buffered
+    /// values.
+    pub fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times, buffered like `insert`. A
+    /// weight that is not positive and finite, or that would overflow `count`, is
+    /// ignored; see [`TDigest::try_insert_weighted`].
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        self.ingest(value, weight);
+    }
+
+    /// Like [`TDigest::insert_weighted`], but adds nothing and fails with
+    /// [`TDigestError::InvalidWeight`] or [`TDigestError::WeightOverflow`] for a weight it
+    /// would ignore, and with [`TDigestError::InvalidValue`] as [`TDigest::try_insert`]
+    /// does.
+    pub fn try_insert_weighted(&mut self, value: f64, weight: f64) -> Result<(), TDigestError> {
+        checked_weight(self.count, weight)?;
+        if !value.is_finite() && self.invalid_values == InvalidValuePolicy::Error {
+            return Err(TDigestError::InvalidValue { value });
+        }
+
+        self.ingest(value, weight);
+        Ok(())
+    }
+
+    /// [`TDigest::insert_weighted`], reporting whether the value was accepted.
+    pub(crate) fn ingest(&mut self, value: f64, weight: f64) -> bool {
+        let Ok(count) = checked_weight(self.count, weight) else {
+            return false;
+        };
+        if !self.accepts(value) {
+            return false;
+        }
+
+        self.m2 = self.moments().map(|mut moments| {
+            moments.add(value, weight);
+            moments.m2
+        });
+        if self.is_empty() {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count = count;
+        self.sum += value * weight;
+
+        self.buffer.push(Centroid::new(value, weight));
+        self.values_buffered += 1;
+        if self.buffer.len() >= self.buffer_size {
+            self.flush();
+        }
+        true
+    }
+
+    /// Like [`TDigest::insert`], but reports a NaN or infinite `value` as
+    /// [`TDigestError::InvalidValue`] under [`InvalidValuePolicy::Error`] instead of
+    /// dropping it.
+    pub fn try_insert(&mut self, value: f64) -> Result<(), TDigestError> {
+        if !value.is_finite() && self.invalid_values == InvalidValuePolicy::Error {
+            return Err(TDigestError::InvalidValue { value });
+        }
+
+        self.insert(value);
+        Ok(())
+    }
+
+    /// Adds an unsigned integer, e.g. a nanosecond timestamp or a byte count, converted
+    /// to the nearest `f64`. Integers up to 2^53 convert exactly; larger ones lose their
+    /// low bits, by up to half the spacing of `f64`s at that magnitude, e.g. 1024 around
+    /// 2^63. Use [`TDigest::try_insert_u64`] to refuse those instead.
+    ///
+    /// Exactness only concerns the conversion: centroids holding several values keep
+    /// their mean as an `f64`, and quantiles interpolate between them either way.
+    pub fn insert_u64(&mut self, value: u64) {
+        self.insert(value as f64);
+    }
+
+    /// Signed counterpart of [`TDigest::insert_u64`]: magnitudes up to 2^53 convert
+    /// exactly.
+    pub fn insert_i64(&mut self, value: i64) {
+        self.insert(value as f64);
+    }
+
+    /// Like [`TDigest::insert_u64`], but fails with [`TDigestError::InexactInteger`] and
+    /// adds nothing if `value` has no exact `f64` representation. Large values that are
+    /// multiples of a high enough power of two, e.g. 2^60, are still exact.
+    pub fn try_insert_u64(&mut self, value: u64) -> Result<(), TDigestError> {
+        let converted = value as f64;
+        // `u64::MAX` rounds up to 2^64, which saturates back to `u64::MAX` on the way
+        // back; anything that rounds to 2^64 is inexact.
+        if converted >= 18_446_744_073_709_551_616.0 || converted as u64 != value {
+            return Err(TDigestError::InexactInteger {
+                value: i128::from(value),
+            });
+        }
+        self.insert(converted);
+        Ok(())
+    }
+
+    /// Signed counterpart of [`TDigest::try_insert_u64`].
+    pub fn try_insert_i64(&mut self, value: i64) -> Result<(), TDigestError> {
+        let converted = value as f64;
+        // As for `u64`, values rounding up to 2^63 saturate back to `i64::MAX`.
+        if converted >= 9_223_372_036_854_775_808.0 || converted as i64 != value {
+            return Err(TDigestError::InexactInteger {
+                value: i128::from(value),
+            });
+        }
+        self.insert(converted);
+        Ok(())
+    }
+
+    /// How NaN and infinite values are handled on ingestion.
+    #[inline]
+    pub fn invalid_value_policy(&self) -> InvalidValuePolicy {
+        self.invalid_values
+    }
+
+    /// Changes how subsequently ingested NaN and infinite values are handled.
+    pub fn set_invalid_value_policy(&mut self, policy: InvalidValuePolicy) {
+        self.invalid_values = policy;
+    }
+
+    /// Whether `value` may be ingested, panicking on it under [`InvalidValuePolicy::Panic`].
+    pub(crate) fn accepts(&self, value: f64) -> bool {
+        if value.is_finite() {
+            return true;
+        }
+
+        match self.invalid_values {
+            InvalidValuePolicy::Ignore | InvalidValuePolicy::Error => false,
+            InvalidValuePolicy::Panic => panic!("cannot insert {} into a digest", value),
+        }
+    }
+
+    /// Compresses any buffered values into the centroids, keeping the buffer's allocation.
+    /// The totals already include the buffered values; `count` and `sum` are recomputed
+    /// from the centroids to shed the rounding of the per-insert updates.
+    pub fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let mut buffer = core::mem::take(&mut self.buffer);
+        buffer.sort_unstable();
+        self.sum = self.compress_with(buffer.iter().copied(), self.count);
+        self.count = compensated_sum(self.centroids.iter().map(|c| c.weight()));
+        buffer.clear();
+        self.buffer = buffer;
+    }
+
+    /// A copy with the buffered values compressed into the centroids, leaving `self`
+    /// untouched, e.g. to hand to readers while writes continue.
+    pub fn snapshot(&self) -> TDigest {
+        let mut snapshot = self.clone();
+        snapshot.flush();
+        snapshot
+    }
+
+    pub(crate) fn clamp(v: f64, lo: f64, hi: f64) -> f64 {
+        if v > hi {
+            hi
+        } else if v < lo {
+            lo
+        } else {
+            v
+        }
+    }
+}
+
+/// `min` and `max` extended to enclose the means of the sorted `centroids`, which also
+/// replaces a NaN bound, or NaN for no centroids.
+pub(crate) fn enclosing_bounds(centroids: &[Centroid], min: f64, max: f64) -> (f64, f64) {
+    match (centroids.first(), centroids.last()) {
+        (Some(first), Some(last)) => (min.min(first.mean()), max.max(last.mean())),
+        _ => (f64::NAN, f64::NAN),
+    }
+}
+
+/// Whether a tracked total is NaN or further from the `expected` one recomputed from the
+/// centroids than rounding can explain; tracked totals are accumulated from raw values.
+pub(crate) fn drifted(tracked: f64, expected: f64) -> bool {
+    tracked.is_nan() || (tracked - expected).abs() > 1e-9 * expected.abs().max(1.0)
+}
+
+/// Formats `value` rounded to `digits` significant figures.
+fn format_significant(value: f64, digits: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{}", value);
+    }
+
+    let digits = digits.max(1) as i32;
+    let magnitude = value.abs().log10().floor() as i32;
+    let decimals = digits - 1 - magnitude;
+
+    if decimals >= 0 {
+        format!("{:.*}", decimals as usize, value)
+    } else {
+        let scale = 10f64.powi(-decimals);
+        format!("{:.0}", (value / scale).round() * scale)
+    }
+}
+
+/// Significant figures of [`TDigest::summary`] and of `Display` without a precision.
+const SUMMARY_DIGITS: usize = 4;
+
+impl TDigest {
+    /// Renders a one-line summary (count, min, common quantiles, max and mean) with every
+    /// statistic rounded to `digits` significant figures.
+    pub fn format_summary(&self, digits: usize) -> String {
+        let parts: Vec<String> = self
+            .summary_rows(digits)
+            .into_iter()
+            .map(|(label, value)| format!("{}={}", label, value))
+            .collect();
+        parts.join(" ")
+    }
+
+    /// Renders the statistics of [`TDigest::format_summary`] as a table, one per line
+    /// with the values aligned, for debugging output:
+    ///
+    /// ```text
+    /// count  1000
+    /// min    0.5
+    /// p25    250.5
+    /// ...
+    /// ```
+    pub fn summary(&self) -> String {
+        let rows = self.summary_rows(SUMMARY_DIGITS);
+        let mut out = String::new();
+        for (label, value) in rows.iter() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("{:<6} {}", label, value));
+        }
+        out
+    }
+
+    /// Labels and formatted values of the summary; only the count for an empty digest.
+    fn summary_rows(&self, digits: usize) -> Vec<(&'static str, String)> {
+        let mut rows = vec![("count", format!("{}", self.count()))];
+        if self.is_empty() {
+            return rows;
+        }
+
+        rows.push(("min", format_significant(self.min(), digits)));
+        let labels = ["p25", "p50", "p75", "p95", "p99"];
+        let values = self.estimate_quantiles(&[0.25, 0.5, 0.75, 0.95, 0.99]);
+        for (&label, value) in labels.iter().zip(values) {
+            rows.push((label, format_significant(value, digits)));
+        }
+        rows.push(("max", format_significant(self.max(), digits)));
+        rows.push(("mean", format_significant(self.mean(), digits)));
+        rows
+    }
+}
+
+/// The one-line [`TDigest::format_summary`], to the precision given as significant
+/// figures (`{:.6}`) or 4 by default; the alternate form (`{:#}`) is the multi-line
+/// [`TDigest::summary`] table.
+impl core::fmt::Display for TDigest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let digits = f.precision().unwrap_or(SUMMARY_DIGITS);
+        if f.alternate() {
+            let rows = self.summary_rows(digits);
+            for (i, (label, value)) in rows.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "{:<6} {}", label, value)?;
+            }
+            Ok(())
+        } else {
+            f.write_str(&self.format_summary(digits))
+        }
+    }
+}
+
+impl TDigest {
+    /// Rebuilds the centroids for a new `max_size`, e.g. to shrink a digest before
+    /// archival or to let it keep more centroids from now on. `count`, `sum`, `min` and
+    /// `max` are preserved; growing `max_size` cannot restore detail already merged away,
+    /// it only benefits values added afterwards.
+    ///
+    /// A buffer size left at its default follows the new `max_size`.
+    pub fn recompress(mut self, new_max_size: usize) -> TDigest {
+        self.set_max_size(new_max_size);
+        self.rebuild()
+    }
+
+    /// Rebuilds the centroids under `scale`, which is then used for all later compression.
+    /// `count`, `sum`, `min` and `max` are preserved.
+    pub fn with_scale_function(mut self, scale: ScaleFunction) -> TDigest {
+        self.scale = scale;
+        self.custom_scale = None;
+        self.rebuild()
+    }
+
+    /// Rebuilds the centroids under `bias`, which is then used for all later compression.
+    /// `count`, `sum`, `min` and `max` are preserved.
+    pub fn with_tail_bias(mut self, bias: TailBias) -> TDigest {
+        self.tail_bias = bias;
+        self.rebuild()
+    }
+
+    /// Rebuilds the centroids under the custom `scale`, which is then used for all later
+    /// compression, including of digests merged from this one by
+    /// [`TDigest::merge_digests`]. `count`, `sum`, `min` and `max` are preserved.
+    ///
+    /// A custom scale function is not part of the binary or serde encodings: decoded
+    /// digests compress under their [`TDigest::scale_function`] again. Digests are only
+    /// equal if they share the same custom scale function, e.g. through clones.
+    pub fn with_scale_fn<S: ScaleFn + 'static>(mut self, scale: S) -> TDigest {
+        self.custom_scale = Some(Arc::new(scale));
+        self.rebuild()
+    }
+
+    /// Recompresses the centroids, including buffered values, under the current
+    /// parameters.
+    fn rebuild(mut self) -> TDigest {
+        self.flush();
+        if !self.centroids.is_empty() {
+            let centroids = core::mem::take(&mut self.centroids);
+            let count = self.count();
+            self.compress_with(centroids.into_iter(), count);
+        }
+        self
+    }
+
+    /// Folds every centroid lighter than `min_weight` into its nearest neighbour by mean,
+    /// shrinking digests with many small tail centroids at the cost of some tail accuracy.
+    ///
+    /// `count`, `min` and `max` are unchanged; `sum` is recomputed from the centroids.
+    /// Buffered values are compressed first.
+    pub fn coalesce_below_weight(&mut self, min_weight: f64) {
+        self.flush();
+        let mut i = 0;
+        while i < self.centroids.len() && self.centroids.len() > 1 {
+            if self.centroids[i].weight() >= min_weight {
+                i += 1;
+                continue;
+            }
+
+            let mean = self.centroids[i].mean();
+            let closer_to_left = i + 1 == self.centroids.len()
+                || (i > 0
+                    && mean - self.centroids[i - 1].mean() <= self.centroids[i + 1].mean() - mean);
+            let target = if closer_to_left { i - 1 } else { i + 1 };
+
+            let light = self.centroids.remove(i);
+            let target = if target > i { target - 1 } else { target };
+            self.centroids[target].update(light.mean() * light.weight(), light.weight());
+
+            // The merged centroid may itself still be below the floor.
+            i = target;
+        }
+
+        let sum = compensated_sum(self.centroids.iter().map(|c| c.mean() * c.weight()));
+        self.sum = sum;
+    }
+}
+
+/// Counters from [`TDigest::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DigestStats {
+    /// Compression passes run by flushes, merges and rebuilds.
+    pub compressions: u64,
+    /// Values added to the insert buffer.
+    pub values_buffered: u64,
+    /// Centroids the centroid list and the buffers have room for, whose memory
+    /// [`TDigest::byte_size`] includes.
+    pub centroids_allocated: usize,
+}
+
+/// Fixes applied by [`TDigest::repair`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RepairReport {
+    /// The centroids were not sorted by mean and had to be re-sorted.
+    pub resorted: bool,
+    /// Number of centroids folded into a neighbour with an equal mean.
+    pub coalesced: usize,
+    /// The tracked `sum` did not match the centroids.
+    pub sum_fixed: bool,
+    /// The tracked `count` did not match the centroids.
+    pub count_fixed: bool,
+    /// The tracked `min` was NaN or above the smallest centroid mean.
+    pub min_fixed: bool,
+    /// The tracked `max` was NaN or below the largest centroid mean.
+    pub max_fixed: bool,
+    /// Number of centroids and buffered values dropped for a non-finite mean or a
+    /// negative or NaN weight.
+    pub dropped: usize,
+    /// There were more than `max_size` centroids, which were recompressed.
+    pub recompressed: bool,
+}
+
+impl RepairReport {
+    /// Returns true if `repair` did not have to change anything.
+    pub fn is_clean(&self) -> bool {
+        *self == RepairReport::default()
+    }
+}
+
+/// Whether a centroid can be part of a valid digest.
+fn is_valid_centroid(c: &Centroid) -> bool {
+    c.mean().is_finite() && !c.weight().is_nan() && c.weight() >= 0.0
+}
+
+impl TDigest {
+    /// Every violation of the digest's invariants, e.g. to log why a digest received
+    /// over the network was quarantined. Checks, in this order, that there are at most
+    /// `max_size` centroids, that every centroid and buffered value has a finite mean
+    /// and a non-negative weight ([`TDigestError::InvalidCentroid`], with buffered values
+    /// indexed after the centroids), that the centroids are sorted by mean, that `count`
+    /// and `sum` match them and the buffered values up to rounding, and that `min`/`max`
+    /// enclose their means. [`TDigest::try_new`] accepts exactly the digests without
+    /// violations.
+    pub fn violations(&self) -> Vec<TDigestError> {
+        let mut found = Vec::new();
+        if self.centroids.len() > self.max_size {
+            found.push(TDigestError::TooManyCentroids {
+                centroids: self.centroids.len(),
+                limit: self.max_size,
+            });
+        }
+        let values = || self.centroids.iter().chain(self.buffer.iter());
+        for (index, _) in values().enumerate().filter(|(_, c)| !is_valid_centroid(c)) {
+            found.push(TDigestError::InvalidCentroid { index });
+        }
+        for (index, w) in self.centroids.windows(2).enumerate() {
+            if w[0].mean > w[1].mean {
+                found.push(TDigestError::UnsortedCentroids { index: index + 1 });
+            }
+        }
+
+        let expected_count = compensated_sum(values().map(|c| c.weight()));
+        let expected_sum = compensated_sum(values().map(|c| c.mean() * c.weight()));
+        for (field, tracked, expected) in [
+            ("count", self.count, expected_count),
+            ("sum", self.sum, expected_sum),
+        ] {
+            if drifted(tracked, expected) {
+                found.push(TDigestError::InconsistentTotal {
+                    field,
+                    tracked,
+                    expected,
+                });
+            }
+        }
+
+        if values().next().is_some() {
+            let lowest = values().map(|c| c.mean()).fold(f64::INFINITY, f64::min);
+            let highest = values().map(|c| c.mean()).fold(f64::NEG_INFINITY, f64::max);
+            // Written to also reject NaN bounds.
+            if !(self.min <= lowest && self.max >= highest) {
+                found.push(TDigestError::InconsistentBounds {
+                    min: self.min,
+                    max: self.max,
+                });
+            }
+        }
+        found
+    }
+
+    /// The first of the [`TDigest::violations`], if any.
+    pub fn validate(&self) -> Result<(), TDigestError> {
+        match self.violations().into_iter().next() {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    /// Fixes recoverable inconsistencies in place, e.g. after reading a digest from
+    /// slightly-corrupted storage.
+    ///
+    /// Centroids and buffered values with a non-finite mean or a negative or NaN weight
+    /// are dropped, the centroids are re-sorted by mean, centroids sharing a mean are
+    /// coalesced, and `sum`/`count` are recomputed from the centroids and buffered values.
+    /// `min`/`max` are only reset to the outermost means when they are NaN or contradict
+    /// them, since the tracked extremes are normally further out than any centroid mean.
+    /// More than `max_size` centroids are recompressed last. Afterwards
+    /// [`TDigest::validate`] passes.
+    pub fn repair(&mut self) -> RepairReport {
+        let mut report = RepairReport::default();
+
+        let before = self.centroids.len() + self.buffer.len();
+        self.centroids.retain(is_valid_centroid);
+        self.buffer.retain(is_valid_centroid);
+        report.dropped = before - self.centroids.len() - self.buffer.len();
+
+        if self.centroids.windows(2).any(|w| w[0] > w[1]) {
+            self.centroids.sort();
+            report.resorted = true;
+        }
+
+        let before = self.centroids.len();
+        self.centroids.dedup_by(|next, prev| {
+            if next.mean == prev.mean {
+                prev.weight = prev.weight() + next.weight();
+                true
+            } else {
+                false
+            }
+        });
+        report.coalesced = before - self.centroids.len();
+
+        let values = || self.centroids.iter().chain(self.buffer.iter());
+        let count = compensated_sum(values().map(|c| c.weight()));
+        let sum = compensated_sum(values().map(|c| c.mean() * c.weight()));
+
+        if drifted(self.count(), count) {
+            self.count = count;
+            report.count_fixed = true;
+        }
+
+        if drifted(self.sum(), sum) {
+            self.sum = sum;
+            report.sum_fixed = true;
+        }
+
+        let extremes = {
+            let view = self.view();
+            view.first()
+                .zip(view.last())
+                .map(|(a, b)| (a.mean(), b.mean()))
+        };
+        match extremes {
+            Some((first, last)) => {
+                if self.min().is_nan() || self.min() > first {
+                    self.min = first;
+                    report.min_fixed = true;
+                }
+                if self.max().is_nan() || self.max() < last {
+                    self.max = last;
+                    report.max_fixed = true;
+                }
+            }
+            None => {
+                if !self.min().is_nan() {
+                    self.min = f64::NAN;
+                    report.min_fixed = true;
+                }
+                if !self.max().is_nan() {
+                    self.max = f64::NAN;
+                    report.max_fixed = true;
+                }
+            }
+        }
+
+        if self.centroids.len() > self.max_size {
+            *self = core::mem::take(self).rebuild();
+            report.recompressed = true;
+        }
+
+        report
+    }
+
+    /// Consuming [`TDigest::repair`], for ingestion pipelines that fix what they cannot
+    /// reject: re-sorts the centroids, drops invalid ones and recomputes the aggregates,
+    /// e.g. `TDigest::from_bytes(&bytes)?.repaired()`.
+    ///
+    /// This is the `repair(self) -> TDigest` of the validation API; the name is taken by
+    /// the in-place form, which also reports what it fixed. Unlike a clamp, centroids of
+    /// negative weight are dropped rather than kept at zero weight: they hold no values
+    /// either way, and dropping them keeps empty centroids out of the interpolation.
+    pub fn repaired(mut self) -> TDigest {
+        self.repair();
+        self
+    }
+}
+
+/// Builder for [`TDigest`] configurations, e.g.
+/// `TDigest::builder().max_size(200).scale_function(ScaleFunction::K2).build()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TDigestBuilder {
+    max_size: usize,
+    scale: ScaleFunction,
+    tail_bias: TailBias,
+    buffer_size: Option<usize>,
+    invalid_values: InvalidValuePolicy,
+    second_moment: bool,
+    deterministic: bool,
+}
+
+impl TDigestBuilder {
+    pub fn new() -> Self {
+        TDigestBuilder {
+            max_size: 100,
+            scale: ScaleFunction::default(),
+            tail_bias: TailBias::Symmetric,
+            buffer_size: None,
+            invalid_values: InvalidValuePolicy::default(),
+            second_moment: false,
+            deterministic: false,
+        }
+    }
+
+    /// Compression parameter, roughly the maximum number of centroids. Defaults to 100.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Scale function used when compressing. Defaults to `K1`.
+    pub fn scale_function(mut self, scale: ScaleFunction) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Which tail the scale function refines, e.g. `TailBias::Upper` for digests only
+    /// queried at high quantiles, see [`TailBias`]. Defaults to `Symmetric`.
+    pub fn tail_bias(mut self, bias: TailBias) -> Self {
+        self.tail_bias = bias;
+        self
+    }
+
+    /// Number of inserted values buffered before compressing. Defaults to `5 * max_size`.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Handling of NaN and infinite values. Defaults to `Ignore`.
+    pub fn invalid_value_policy(mut self, policy: InvalidValuePolicy) -> Self {
+        self.invalid_values = policy;
+        self
+    }
+
+    /// Whether to track the second moment of the values, making [`TDigest::variance`]
+    /// exact rather than estimated from the centroids. Costs a few arithmetic operations
+    /// per value; merges keep it only while every merged digest tracks it. Defaults to
+    /// off.
+    pub fn second_moment(mut self, track: bool) -> Self {
+        self.second_moment = track;
+        self
+    }
+
+    /// Whether merges are reproducible: [`TDigest::merge_digests`] then gives bitwise the
+    /// same digest, and so the same [`TDigest::to_bytes`], for any order of the same
+    /// inputs, and merged digests are kept [canonical](TDigest::canonicalize). Costs a
+    /// sort of the inputs per merge. Defaults to off.
+    ///
+    /// Chains of pairwise [`TDigest::merge`]s still depend on their order, since every
+    /// step compresses; merge a set of digests with `merge_digests` to reproduce it.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn build(&self) -> TDigest {
+        let mut digest = TDigest::new_with_scale(self.max_size, self.scale);
+        digest.tail_bias = self.tail_bias;
+        digest.invalid_values = self.invalid_values;
+        digest.m2 = self.second_moment.then_some(0.0);
+        digest.deterministic = self.deterministic;
+        if let Some(buffer_size) = self.buffer_size {
+            digest.set_buffer_size(buffer_size);
+        }
+        digest
+    }
+}
+
+impl Default for TDigestBuilder {
+    fn default() -> Self {
+        TDigestBuilder::new()
+    }
+}
+
+impl TDigest {
+    pub fn builder() -> TDigestBuilder {
+        TDigestBuilder::new()
+    }
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        TDigest {
+            centroids: Vec::new(),
+            max_size: 100,
+            sum: 0.0,
+            count: 0.0,
+            max: f64::NAN,
+            min: f64::NAN,
+            buffer: Vec::new(),
+            spare: Vec::new(),
+            scratch: Vec::new(),
+            buffer_size: Self::default_buffer_size(100),
+            scale: ScaleFunction::default(),
+            custom_scale: None,
+            tail_bias: TailBias::Symmetric,
+            compressions: 0,
+            values_buffered: 0,
+            invalid_values: InvalidValuePolicy::default(),
+            m2: None,
+            deterministic: false,
+        }
+    }
+}
+
+impl Extend<f64> for TDigest {
+    /// Inserts every value through the buffer, see [`TDigest::insert`].
+    fn extend<I: IntoIterator<Item = f64>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl Extend<(f64, f64)> for TDigest {
+    /// Inserts every `(value, weight)` pair through the buffer, see
+    /// [`TDigest::insert_weighted`].
+    fn extend<I: IntoIterator<Item = (f64, f64)>>(&mut self, iter: I) {
+        for (value, weight) in iter {
+            self.insert_weighted(value, weight);
+        }
+    }
+}
+
+impl FromIterator<f64> for TDigest {
+    /// Collects into a default-sized digest, flushed once the iterator is exhausted.
+    fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Self {
+        let mut digest = TDigest::default();
+        digest.extend(iter);
+        digest.flush();
+        digest
+    }
+}
+
+impl FromIterator<(f64, f64)> for TDigest {
+    /// Collects `(value, weight)` pairs into a default-sized digest, flushed once the
+    /// iterator is exhausted.
+    fn from_iter<I: IntoIterator<Item = (f64, f64)>>(iter: I) -> Self {
+        let mut digest = TDigest::default();
+        digest.extend(iter);
+        digest.flush();
+        digest
+    }
+}
+
+/// Operations shared by the digest structures, so code can be written once and the
+/// structure picked to match the workload: [`TDigest`] buffers values and compresses them
+/// in batches, which is fastest overall, while [`AvlTreeDigest`] folds every value into
+/// its centroids as it arrives, for low-latency point inserts.
+pub trait Digest {
+    /// Adds a single value.
+    fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times.
+    fn insert_weighted(&mut self, value: f64, weight: f64);
+
+    /// Compresses any buffered values. Queries see buffered values either way, so this
+    /// only makes repeated queries cheaper; a no-op for structures that do not buffer.
+    fn flush(&mut self) {}
+
+    fn count(&self) -> f64;
+
+    fn min(&self) -> f64;
+
+    fn max(&self) -> f64;
+
+    /// Estimates the value at quantile `q`, see [`TDigest::estimate_quantile`].
+    fn estimate_quantile(&self, q: f64) -> f64;
+
+    /// Estimates the fraction of values at most `x`, see [`TDigest::cdf`].
+    fn cdf(&self, x: f64) -> f64;
+
+    /// The contents as a [`TDigest`], e.g. for merging or serialization.
+    fn to_digest(&self) -> TDigest;
+}
+
+impl Digest for TDigest {
+    fn insert_weighted(&mut self, value: f64, weight: f64) {
+        TDigest::insert_weighted(self, value, weight);
+    }
+
+    fn flush(&mut self) {
+        TDigest::flush(self);
+    }
+
+    fn count(&self) -> f64 {
+        TDigest::count(self)
+    }
+
+    fn min(&self) -> f64 {
+        TDigest::min(self)
+    }
+
+    fn max(&self) -> f64 {
+        TDigest::max(self)
+    }
+
+    fn estimate_quantile(&self, q: f64) -> f64 {
+        TDigest::estimate_quantile(self, q)
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        TDigest::cdf(self, x)
+    }
+
+    /// A flushed copy of the digest.
+    fn to_digest(&self) -> TDigest {
+        let mut digest = self.clone();
+        digest.flush();
+        digest
+    }
+}
+
+/// A [`Digest`] that can absorb another of its kind, for code that is generic over the
+/// implementation, e.g. an aggregation pipeline that merges per-shard sketches.
+pub trait QuantileSketch: Digest {
+    /// Folds everything recorded in `other` into `self`.
+    fn merge(&mut self, other: &Self);
+
+    /// Estimates the value at quantile `q`, the same as [`Digest::estimate_quantile`].
+    fn quantile(&self, q: f64) -> f64 {
+        self.estimate_quantile(q)
+    }
+}
+
+impl QuantileSketch for TDigest {
+    fn merge(&mut self, other: &TDigest) {
+        TDigest::merge(self, other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+    use crate::AvlTreeDigest;
+
+    #[test]
+    fn test_repair_unsorted_digest() {
+        let mut t = TDigest::new_with_size(100);
+        t.centroids = vec![
+            Centroid::new(3.0, 1.0),
+            Centroid::new(1.0, 2.0),
+            Centroid::new(2.0, 1.0),
+            Centroid::new(1.0, 1.0),
+        ];
+        t.sum = 42.0;
+        t.count = 1.0;
+
+        let report = t.repair();
+        assert!(report.resorted);
+        assert_eq!(report.coalesced, 1);
+        assert!(report.sum_fixed);
+        assert!(report.count_fixed);
+        assert!(report.min_fixed);
+        assert!(report.max_fixed);
+
+        assert_eq!(
+            t.centroids,
+            vec![
+                Centroid::new(1.0, 3.0),
+                Centroid::new(2.0, 1.0),
+                Centroid::new(3.0, 1.0)
+            ]
+        );
+        assert_eq!(t.count(), 5.0);
+        assert_eq!(t.sum(), 8.0);
+        assert_eq!(t.min(), 1.0);
+        assert_eq!(t.max(), 3.0);
+
+        assert!(t.repair().is_clean());
+    }
+
+    #[test]
+    fn test_violations_and_repaired() {
+        let good = TDigest::new_with_size(10).merge_unsorted(lcg_values(1_000, 8));
+        assert_eq!(good.violations(), vec![]);
+        assert!(good.validate().is_ok());
+
+        let mut bad = good.clone();
+        bad.centroids.swap(1, 2);
+        bad.centroids[4].weight = -1.0;
+        bad.centroids.extend(
+            [Centroid {
+                mean: f64::NAN,
+                weight: 1.0,
+            }; 2],
+        );
+        bad.buffer.push(Centroid::new(5.0, 1.0));
+        bad.max = f64::NAN;
+        let violations = bad.violations();
+        assert_eq!(
+            violations[0],
+            TDigestError::TooManyCentroids {
+                centroids: 12,
+                limit: 10
+            }
+        );
+        assert_eq!(
+            violations[1..4],
+            [4, 10, 11].map(|index| TDigestError::InvalidCentroid { index })
+        );
+        assert_eq!(violations[4], TDigestError::UnsortedCentroids { index: 2 });
+        assert!(matches!(
+            violations.last(),
+            Some(TDigestError::InconsistentBounds { .. })
+        ));
+        assert_eq!(bad.validate(), Err(violations[0].clone()));
+
+        let repaired = bad.repaired();
+        assert_eq!(repaired.violations(), vec![]);
+        assert_eq!(repaired.max(), 5.0);
+        assert_eq!(repaired.count(), 1_000.0 - good.centroids[4].weight() + 1.0);
+
+        let mut crowded = TDigest::new_with_size(3);
+        crowded.centroids = (0..6).map(|i| Centroid::new(f64::from(i), 1.0)).collect();
+        let report = crowded.repair();
+        assert!(report.recompressed && report.dropped == 0);
+        assert!(crowded.len() <= 3 && crowded.validate().is_ok());
+    }
+
+    #[test]
+    fn test_repair_keeps_consistent_extremes() {
+        let mut t = TDigest::new(vec![Centroid::new(5.0, 2.0)], 100, 10.0, 2.0, 6.0, 4.0);
+        assert!(t.repair().is_clean());
+        assert_eq!(t.min(), 4.0);
+        assert_eq!(t.max(), 6.0);
+    }
+
+    #[test]
+    fn test_integer_inserts() {
+        let mut t = TDigest::new_with_size(100);
+        t.insert_u64(1 << 53);
+        t.insert_i64(-(1 << 53));
+        assert_eq!(
+            (t.min(), t.max()),
+            (-9_007_199_254_740_992.0, 9_007_199_254_740_992.0)
+        );
+
+        // 2^53 + 1 rounds to 2^53; 2^60 and i64::MIN are exact.
+        t.insert_u64((1 << 53) + 1);
+        assert_eq!(t.count(), 3.0);
+        assert_eq!(
+            t.try_insert_u64((1 << 53) + 1),
+            Err(TDigestError::InexactInteger {
+                value: (1 << 53) + 1
+            })
+        );
+        assert!(t.try_insert_u64(u64::MAX).is_err());
+        assert!(t.try_insert_i64(i64::MAX).is_err());
+        assert_eq!(t.count(), 3.0);
+        assert_eq!(t.try_insert_u64(1 << 60), Ok(()));
+        assert_eq!(t.try_insert_i64(i64::MIN), Ok(()));
+        assert_eq!((t.min(), t.max()), (i64::MIN as f64, (1u64 << 60) as f64));
+    }
+
+    #[test]
+    fn test_format_significant() {
+        assert_eq!(format_significant(1234.5678, 2), "1200");
+        assert_eq!(format_significant(1234.5678, 6), "1234.57");
+        assert_eq!(format_significant(0.012345, 2), "0.012");
+        assert_eq!(format_significant(-3.0, 3), "-3.00");
+        assert_eq!(format_significant(0.0, 3), "0");
+    }
+
+    #[test]
+    fn test_format_summary_precision() {
+        let t = TDigest::new_with_size(100).merge_unsorted(vec![1.0 / 3.0, 2.0 / 3.0, 1.0]);
+        let short = t.format_summary(2);
+        let long = t.format_summary(6);
+        assert_ne!(short, long);
+        assert!(short.starts_with("count=3 min=0.33 "));
+        assert!(long.starts_with("count=3 min=0.333333 "));
+        assert!(short.ends_with("max=1.0 mean=0.67"));
+
+        assert_eq!(TDigest::default().format_summary(3), "count=0");
+    }
+
+    #[test]
+    fn test_display_and_summary() {
+        let t: TDigest = (1..=1000).map(f64::from).collect();
+        assert_eq!(format!("{}", t), t.format_summary(4));
+        assert_eq!(format!("{:.2}", t), t.format_summary(2));
+        assert_eq!(format!("{:#}", t), t.summary());
+
+        let summary = t.summary();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "count  1000");
+        assert_eq!(lines[1], "min    1.000");
+        assert_eq!(lines[8], "mean   500.5");
+        assert_eq!(TDigest::default().summary(), "count  0");
+    }
+
+    #[test]
+    fn test_geometric_mean() {
+        let t = TDigest::new_with_size(100).merge_unsorted(vec![1.0, 2.0, 4.0, 8.0]);
+        // (1 * 2 * 4 * 8)^(1/4) = 64^(1/4)
+        assert!((t.geometric_mean() - 64f64.powf(0.25)).abs() < 1e-12);
+
+        let negative = TDigest::new_with_size(100).merge_unsorted(vec![-1.0, 2.0]);
+        assert!(negative.geometric_mean().is_nan());
+        assert!(TDigest::default().geometric_mean().is_nan());
+    }
+
+    #[test]
+    fn test_bounds_of_empty_and_merged_digests() {
+        let empty = TDigest::new_with_size(50);
+        assert_eq!((empty.try_min(), empty.try_max()), (None, None));
+        assert!(empty.min().is_nan() && empty.max().is_nan());
+
+        let low = TDigest::new_with_size(50).merge_unsorted(vec![-5.0, 1.0]);
+        let high = TDigest::new_with_size(50).merge_unsorted(vec![0.0, 10.0]);
+        let bounds = |t: &TDigest| (t.try_min(), t.try_max());
+        for merged in [
+            TDigest::merge_digests(vec![empty.clone(), low.clone(), empty.clone()]),
+            empty.clone() + &low,
+            low.clone() + &empty,
+        ] {
+            assert_eq!(bounds(&merged), (Some(-5.0), Some(1.0)));
+        }
+        let mut drained = low.clone();
+        let mut dest = empty.clone();
+        drained.drain_into(&mut dest);
+        assert_eq!(bounds(&dest), (Some(-5.0), Some(1.0)));
+        assert_eq!(bounds(&drained), (None, None));
+
+        assert_eq!(
+            bounds(&TDigest::merge_digests(vec![
+                high.clone(),
+                empty.clone(),
+                low.clone()
+            ])),
+            (Some(-5.0), Some(10.0))
+        );
+        let mut buffered = TDigest::new_with_size(50);
+        buffered.insert(3.0);
+        assert_eq!(bounds(&(buffered.clone() + &empty)), (Some(3.0), Some(3.0)));
+        assert_eq!(bounds(&(high + &buffered)), (Some(0.0), Some(10.0)));
+        assert!(TDigest::merge_digests(vec![empty.clone(), empty.clone()])
+            .try_min()
+            .is_none());
+
+        // NaN bounds handed in from outside become the extreme means.
+        let parts = TDigest::new(
+            vec![Centroid::new(2.0, 1.0), Centroid::new(4.0, 1.0)],
+            50,
+            6.0,
+            2.0,
+            f64::NAN,
+            f64::NAN,
+        );
+        assert_eq!(bounds(&parts), (Some(2.0), Some(4.0)));
+        let none = TDigest::new(Vec::new(), 50, 0.0, 0.0, 1.0, -1.0);
+        assert!(none.try_min().is_none() && none.min().is_nan());
+        let mut decoded = low.clone();
+        decoded.min = f64::NAN;
+        let decoded = TDigest::from_bytes(&decoded.to_bytes()).unwrap();
+        assert_eq!(bounds(&decoded), (Some(-5.0), Some(1.0)));
+    }
+
+    #[test]
+    fn test_singleton() {
+        let t = TDigest::singleton(42.0, 100);
+        assert_eq!(t.count(), 1.0);
+        assert_eq!(t.min(), 42.0);
+        assert_eq!(t.max(), 42.0);
+        assert_eq!(t.mean(), 42.0);
+        for q in [0.0, 0.01, 0.25, 0.5, 0.75, 0.99, 1.0] {
+            assert_eq!(t.estimate_quantile(q), 42.0);
+        }
+        assert_eq!(t, TDigest::new_with_size(100).merge_unsorted(vec![42.0]));
+    }
+
+    #[test]
+    fn test_coalesce_below_weight() {
+        let mut t =
+            TDigest::new_with_size(100).merge_unsorted((0..10_000).map(f64::from).collect());
+        let count = t.count();
+        let len = t.centroids.len();
+
+        t.coalesce_below_weight(50.0);
+
+        assert!(t.centroids.len() < len);
+        assert!(t.centroids.iter().all(|c| c.weight() >= 50.0));
+        assert!(t.centroids.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(t.count(), count);
+        let weight: f64 = t.centroids.iter().map(|c| c.weight()).sum();
+        assert_eq!(weight, count);
+        assert!((t.mean() - 4999.5).abs() < 1e-6);
+
+        let mut single = TDigest::singleton(1.0, 100);
+        single.coalesce_below_weight(10.0);
+        assert_eq!(single.centroids, vec![Centroid::new(1.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_with_size_and_hint() {
+        let mut huge = TDigest::new_with_size(usize::MAX);
+        assert_eq!(huge.buffer_size(), usize::MAX);
+        huge.insert(1.0);
+        assert_eq!(huge.estimate_quantile(0.5), 1.0);
+
+        let mut t = TDigest::with_size_and_hint(100, 1000);
+        assert!(t.centroids.capacity() >= 100);
+        assert_eq!(t.buffer_size(), TDigest::new_with_size(100).buffer_size());
+        assert!(t.buffer.capacity() >= t.buffer_size());
+
+        let buffer_ptr = t.buffer.as_ptr();
+        let buffer_capacity = t.buffer.capacity();
+        for i in 0..1000 {
+            t.insert(f64::from(i));
+        }
+        assert_eq!(t.buffer.as_ptr(), buffer_ptr);
+        assert_eq!(t.buffer.capacity(), buffer_capacity);
+
+        // The hinted volume fills the default-sized buffer exactly twice.
+        assert!(t.buffer.is_empty());
+        assert_eq!(t.count(), 1000.0);
+        assert_eq!(t.min(), 0.0);
+        assert_eq!(t.max(), 999.0);
+
+        // A huge hint neither delays compression nor preallocates beyond the buffer, and a
+        // tiny one does not force a compression every few inserts.
+        let huge = TDigest::with_size_and_hint(100, 1_000_000);
+        assert_eq!(huge.buffer.capacity(), huge.buffer_size());
+        let mut tiny = TDigest::with_size_and_hint(100, 3);
+        assert_eq!(tiny.buffer_size(), huge.buffer_size());
+        for i in 0..10 {
+            tiny.insert(f64::from(i));
+        }
+        assert_eq!(tiny.buffered_len(), 10);
+    }
+
+    #[test]
+    fn test_insert_buffers_until_threshold() {
+        let mut t = TDigest::new_with_size(100);
+        assert_eq!(t.buffer_size(), 500);
+        t.set_buffer_size(10);
+
+        for i in 0..9 {
+            t.insert(f64::from(i));
+        }
+        assert_eq!(t.buffer.len(), 9);
+        assert!(t.centroids.is_empty());
+        // Buffered values are visible to the totals and queries all the same.
+        assert!(!t.is_empty());
+        assert_eq!(t.count(), 9.0);
+        assert_eq!((t.min(), t.max()), (0.0, 8.0));
+        assert_eq!(t.estimate_quantile(0.5), 4.0);
+        assert_eq!(t.cdf(-1.0), 0.0);
+
+        t.insert(9.0);
+        assert_eq!(t.count(), 10.0);
+        assert!(t.buffer.is_empty());
+
+        t.insert(20.0);
+        t.insert(-1.0);
+        t.flush();
+        assert_eq!(t.count(), 12.0);
+        assert_eq!(t.min(), -1.0);
+        assert_eq!(t.max(), 20.0);
+
+        // Shrinking the threshold below the buffered amount compresses right away.
+        t.insert(5.0);
+        t.insert(6.0);
+        t.set_buffer_size(1);
+        assert_eq!(t.count(), 14.0);
+    }
+
+    #[test]
+    fn test_insert_matches_batch_merge() {
+        let values: Vec<f64> = (0..10_000)
+            .map(|i| f64::from((i * 7919) % 10_000))
+            .collect();
+        let mut t = TDigest::new_with_size(100);
+        for &v in &values {
+            t.insert(v);
+        }
+        t.flush();
+
+        let batch = TDigest::new_with_size(100).merge_unsorted(values);
+        assert_eq!(t.count(), batch.count());
+        assert_eq!(t.min(), batch.min());
+        assert_eq!(t.max(), batch.max());
+        assert!(t.centroids.len() <= 100);
+        for &q in &[0.01, 0.1, 0.5, 0.9, 0.99] {
+            let expected = q * 10_000.0;
+            assert!((t.estimate_quantile(q) - expected).abs() < 100.0);
+        }
+    }
+
+    #[test]
+    fn test_from_histogram() {
+        let t = TDigest::from_histogram(&[0.0, 10.0, 20.0, 30.0], &[5, 0, 15]).unwrap();
+        assert_eq!((t.count(), t.min(), t.max()), (20.0, 0.0, 30.0));
+        let means: Vec<f64> = t.centroids().map(|c| c.mean()).collect();
+        assert_eq!(means, vec![5.0, 25.0]);
+        assert_eq!(t.estimate_quantile(0.25), 10.0);
+        assert_eq!(t.sum(), 5.0 * 5.0 + 15.0 * 25.0);
+
+        // Prometheus-style buckets with an open top, merged with raw values.
+        let open = TDigest::from_histogram(&[0.0, 1.0, 2.0, f64::INFINITY], &[1, 1, 2]).unwrap();
+        assert_eq!((open.min(), open.max()), (0.0, 2.0));
+        let mut merged = TDigest::new_with_size(100).merge_unsorted(vec![3.0]);
+        merged.merge(&open);
+        assert_eq!((merged.count(), merged.max()), (5.0, 3.0));
+
+        assert!(TDigest::from_histogram(&[0.0, 1.0], &[0])
+            .unwrap()
+            .is_empty());
+        assert!(TDigest::from_histogram(&[1.0], &[]).unwrap().is_empty());
+        for (bounds, counts) in [
+            (&[0.0, 1.0][..], &[1, 2][..]),
+            (&[1.0, 0.0], &[1]),
+            (&[0.0, f64::NAN], &[1]),
+            (&[0.0, f64::INFINITY, 2.0], &[1, 1]),
+            (&[f64::NEG_INFINITY, f64::INFINITY], &[1]),
+        ] {
+            assert!(matches!(
+                TDigest::from_histogram(bounds, counts),
+                Err(TDigestError::InvalidHistogram { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let t = TDigest::builder()
+            .max_size(50)
+            .scale_function(ScaleFunction::K3)
+            .buffer_size(7)
+            .build();
+        assert_eq!(t.max_size(), 50);
+        assert_eq!(t.scale_function(), ScaleFunction::K3);
+        assert_eq!(t.buffer_size(), 7);
+        assert!(t.is_empty());
+
+        assert_eq!(TDigest::builder().build(), TDigest::default());
+        assert_eq!(
+            TDigestBuilder::default().max_size(20).build(),
+            TDigest::new_with_size(20)
+        );
+    }
+
+    #[test]
+    fn test_insert_weighted() {
+        let mut t = TDigest::new_with_size(100);
+        for i in 0..1000 {
+            t.insert_weighted(f64::from(i), if i < 500 { 1.0 } else { 3.0 });
+        }
+        t.insert_weighted(5000.0, -1.0);
+        t.insert_weighted(5000.0, f64::NAN);
+        t.insert_weighted(5000.0, f64::INFINITY);
+        t.extend_weighted(&[(5000.0, 0.0), (5000.0, f64::INFINITY)]);
+        t.flush();
+
+        assert_eq!(t.count(), 2000.0);
+        assert_eq!(t.max(), 999.0);
+        assert!(t.centroids.len() <= 100);
+        // A quarter of the weight lies below 500.
+        assert!((t.cdf(499.5) - 0.25).abs() < 0.01);
+
+        assert_eq!(
+            t.try_insert_weighted(1.0, 0.0),
+            Err(TDigestError::InvalidWeight { weight: 0.0 })
+        );
+        t.set_invalid_value_policy(InvalidValuePolicy::Error);
+        assert!(matches!(
+            t.try_insert_weighted(f64::NAN, 1.0),
+            Err(TDigestError::InvalidValue { .. })
+        ));
+        assert_eq!(t.try_insert_weighted(1000.0, 2.0), Ok(()));
+        assert_eq!(t.count(), 2002.0);
+
+        // Weights that would overflow the count are refused rather than poisoning it.
+        let mut heavy = TDigest::new_with_size(100);
+        heavy.insert_weighted(1.0, f64::MAX);
+        heavy.insert_weighted(2.0, f64::MAX);
+        assert_eq!(heavy.count(), f64::MAX);
+        let mut batch = TDigest::new_with_size(100);
+        batch.extend_weighted(&[(1.0, f64::MAX), (2.0, f64::MAX), (3.0, 1.0)]);
+        assert_eq!((batch.count(), batch.max()), (f64::MAX, 3.0));
+        heavy.extend_weighted(&[(3.0, f64::MAX)]);
+        assert_eq!((heavy.count(), heavy.max()), (f64::MAX, 1.0));
+        assert_eq!(
+            heavy.try_insert_weighted(2.0, f64::MAX),
+            Err(TDigestError::WeightOverflow {
+                total: f64::MAX,
+                weight: f64::MAX
+            })
+        );
+        let mut avl = crate::AvlTreeDigest::new(100);
+        avl.insert_weighted(1.0, f64::MAX);
+        avl.insert_weighted(2.0, f64::MAX);
+        avl.insert_weighted(2.0, f64::INFINITY);
+        assert_eq!(avl.count(), f64::MAX);
+        let mut fixed = crate::ConstTDigest::<8>::new();
+        fixed.insert_weighted(1.0, f64::MAX);
+        fixed.insert_weighted(2.0, f64::MAX);
+        assert_eq!(fixed.count(), f64::MAX);
+    }
+
+    #[test]
+    fn test_trimmed_mean_and_truncated_sum() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=10).map(f64::from).collect());
+        assert_eq!(t.trimmed_mean(0.0, 1.0), 5.5);
+        assert_eq!(t.truncated_sum(0.0, 1.0), 55.0);
+        // Drops the smallest and largest value.
+        assert_eq!(t.trimmed_mean(0.1, 0.9), 5.5);
+        assert_eq!(t.truncated_sum(0.1, 0.9), 44.0);
+        assert_eq!(t.truncated_sum(0.0, 0.3), 6.0);
+        // Half of the value 2.0 and all of 3.0.
+        assert_eq!(t.truncated_sum(0.15, 0.3), 4.0);
+
+        let mut values: Vec<f64> = (0..10_000).map(f64::from).collect();
+        values.push(1e9);
+        let outlier = TDigest::new_with_size(100).merge_unsorted(values);
+        assert!(outlier.mean() > 100_000.0);
+        assert!((outlier.trimmed_mean(0.01, 0.99) - 5000.0).abs() < 10.0);
+
+        assert!(t.trimmed_mean(0.5, 0.5).is_nan());
+        assert!(t.trimmed_mean(-0.1, 0.5).is_nan());
+        assert!(TDigest::default().truncated_sum(0.0, 1.0).is_nan());
+    }
+
+    #[test]
+    fn test_split_at_quantiles() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=10).map(f64::from).collect());
+        let bands = t.split_at_quantiles(&[0.15, 0.3]);
+        assert_eq!(bands.len(), 3);
+        // The value 2.0 straddles the first cut, half of it going to each side.
+        assert_eq!(bands[0].count(), 1.5);
+        assert_eq!(bands[0].sum(), 2.0);
+        assert_eq!(bands[1].count(), 1.5);
+        assert_eq!(bands[1].sum(), t.truncated_sum(0.15, 0.3));
+        assert_eq!(bands[2].count(), 7.0);
+        assert_eq!(
+            (bands[2].min(), bands[2].max()),
+            (t.estimate_quantile(0.3), 10.0)
+        );
+        assert_eq!(bands[0].min(), 1.0);
+
+        let values = crate::lcg::lcg_values(20_000, 11);
+        let mut t = TDigest::builder().max_size(100).second_moment(true).build();
+        t.extend(values.iter().copied());
+        let bands = t.split_at_quantiles(&[0.5, 0.9]);
+        let total: f64 = bands.iter().map(|b| b.count()).sum();
+        assert!((total - t.count()).abs() < 1e-9);
+        assert!((bands[2].count() - 2_000.0).abs() < 1e-6);
+        assert!((bands[2].estimate_quantile(0.5) - t.estimate_quantile(0.95)).abs() < 0.01);
+        // Only the centroid straddling the cut is shared.
+        let last = bands[1].centroids().last().unwrap().mean();
+        assert_eq!(bands[2].centroids().next().unwrap().mean(), last);
+        assert!(bands[2].centroids().nth(1).unwrap().mean() > last);
+        assert!(bands
+            .iter()
+            .all(|b| b.tracks_second_moment() && b.max_size() == 100));
+        assert!(bands.iter().all(|b| b.validate().is_ok()));
+
+        let empty = t.split_at_quantiles(&[0.5, 0.5]);
+        assert!(empty[1].is_empty() && empty[1].min().is_nan());
+        assert!(TDigest::default()
+            .split_at_quantiles(&[0.5])
+            .iter()
+            .all(TDigest::is_empty));
+        let whole = t.split_at_quantiles(&[]);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].centroids().count(), t.view().len());
+    }
+
+    #[test]
+    fn test_from_iterator_and_extend() {
+        let values = lcg_values(20_000, 5);
+        let collected: TDigest = values.iter().copied().collect();
+        assert_eq!(collected.count(), 20_000.0);
+        assert!(collected.buffer.is_empty());
+        assert!((collected.estimate_quantile(0.5) - 0.5).abs() < 0.02);
+
+        let mut extended = TDigest::default();
+        extended.extend(values.iter().copied());
+        extended.flush();
+        assert_eq!(extended, collected);
+
+        let weighted: TDigest = vec![(1.0, 3.0), (2.0, 1.0), (5.0, 0.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(weighted.count(), 4.0);
+        assert_eq!(weighted.sum(), 5.0);
+
+        let mut extended = weighted.clone();
+        extended.extend(vec![(4.0, 2.0)]);
+        extended.flush();
+        assert_eq!(extended.count(), 6.0);
+    }
+
+    #[test]
+    fn test_centroid_iteration() {
+        let t = TDigest::new_with_size(100).merge_unsorted(vec![3.0, 1.0, 2.0, 2.0]);
+        let means: Vec<f64> = t.centroids().map(|c| c.mean()).collect();
+        assert_eq!(means, vec![1.0, 2.0, 2.0, 3.0]);
+
+        let cumulative: Vec<(f64, f64)> = t.cumulative().collect();
+        assert_eq!(
+            cumulative,
+            vec![(1.0, 1.0), (2.0, 2.0), (2.0, 3.0), (3.0, 4.0)]
+        );
+
+        let mut buffered = t.clone();
+        buffered.insert(0.5);
+        assert_eq!(buffered.centroids().count(), 4);
+        let owned = buffered.into_centroids();
+        assert_eq!(owned.len(), 5);
+        assert_eq!(owned[0].mean(), 0.5);
+
+        let large = TDigest::new_with_size(50).merge_unsorted(lcg_values(10_000, 2));
+        assert_eq!(large.cumulative().last().unwrap().1, large.count());
+    }
+
+    #[test]
+    fn test_invalid_value_policy() {
+        let values = vec![1.0, f64::NAN, 2.0, f64::INFINITY, 3.0, f64::NEG_INFINITY];
+
+        let t = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        assert_eq!(t.count(), 3.0);
+        assert_eq!((t.min(), t.max()), (1.0, 3.0));
+        let t = TDigest::new_with_size(100).merge_sorted(vec![f64::NEG_INFINITY, 1.0, f64::NAN]);
+        assert_eq!(t.count(), 1.0);
+        let t = TDigest::new_with_size(100).merge_weighted(vec![(f64::NAN, 2.0), (1.0, 2.0)]);
+        assert_eq!(t.count(), 2.0);
+
+        let mut t = TDigest::builder()
+            .invalid_value_policy(InvalidValuePolicy::Error)
+            .build();
+        assert_eq!(t.invalid_value_policy(), InvalidValuePolicy::Error);
+        assert_eq!(t.try_insert(1.0), Ok(()));
+        assert_eq!(
+            t.try_insert(f64::INFINITY),
+            Err(TDigestError::InvalidValue {
+                value: f64::INFINITY
+            })
+        );
+        assert!(t.try_insert(f64::NAN).is_err());
+        t.insert(f64::NAN);
+        t.flush();
+        assert_eq!(t.count(), 1.0);
+
+        let mut ignoring = TDigest::new_with_size(100);
+        assert_eq!(ignoring.try_insert(f64::NAN), Ok(()));
+        ignoring.flush();
+        assert!(ignoring.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot insert NaN")]
+    fn test_invalid_value_policy_panic() {
+        let mut t = TDigest::new_with_size(100);
+        t.set_invalid_value_policy(InvalidValuePolicy::Panic);
+        t.insert(f64::NAN);
+    }
+
+    #[test]
+    fn test_try_new() {
+        let centroids = vec![Centroid::new(1.0, 2.0), Centroid::new(4.0, 1.0)];
+        let t = TDigest::try_new(centroids.clone(), 10, 6.0, 3.0, 5.0, 0.5).unwrap();
+        assert_eq!(t.mean(), 2.0);
+        assert_eq!(t.estimate_quantile(1.0), 5.0);
+        assert!(TDigest::try_new(Vec::new(), 10, 0.0, 0.0, f64::NAN, f64::NAN).is_ok());
+
+        assert_eq!(
+            TDigest::try_new(centroids.clone(), 1, 6.0, 3.0, 5.0, 0.5),
+            Err(TDigestError::TooManyCentroids {
+                centroids: 2,
+                limit: 1
+            })
+        );
+        let unsorted = vec![centroids[1], centroids[0]];
+        assert_eq!(
+            TDigest::try_new(unsorted, 10, 6.0, 3.0, 5.0, 0.5),
+            Err(TDigestError::UnsortedCentroids { index: 1 })
+        );
+        let mut negative = centroids.clone();
+        negative[0].weight = -1.0;
+        assert_eq!(
+            TDigest::try_new(negative, 10, 6.0, 3.0, 5.0, 0.5),
+            Err(TDigestError::InvalidCentroid { index: 0 })
+        );
+        assert!(matches!(
+            TDigest::try_new(centroids.clone(), 10, 6.0, 4.0, 5.0, 0.5),
+            Err(TDigestError::InconsistentTotal { field: "count", .. })
+        ));
+        assert!(matches!(
+            TDigest::try_new(centroids.clone(), 10, 7.0, 3.0, 5.0, 0.5),
+            Err(TDigestError::InconsistentTotal { field: "sum", .. })
+        ));
+        assert!(matches!(
+            TDigest::try_new(centroids.clone(), 10, 6.0, 3.0, 3.0, 0.5),
+            Err(TDigestError::InconsistentBounds { .. })
+        ));
+        assert!(TDigest::try_new(centroids, 10, 6.0, 3.0, 5.0, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_recompress() {
+        let t = TDigest::new_with_size(200).merge_unsorted(lcg_values(20_000, 4));
+        assert!(t.centroids.len() > 100);
+
+        let small = t.clone().recompress(20);
+        assert_eq!(small.max_size(), 20);
+        assert_eq!(small.buffer_size(), 100);
+        assert!(small.centroids.len() <= 20);
+        assert_eq!(
+            (small.count(), small.sum(), small.min(), small.max()),
+            (t.count(), t.sum(), t.min(), t.max())
+        );
+        assert!((small.estimate_quantile(0.5) - 0.5).abs() < 0.05);
+
+        let mut custom = TDigest::new_with_size(100);
+        custom.set_buffer_size(7);
+        custom.insert(1.0);
+        let grown = custom.recompress(1_000);
+        assert_eq!(grown.buffer_size(), 7);
+        assert_eq!(grown.count(), 1.0);
+
+        let k3 = t.clone().with_scale_function(ScaleFunction::K3);
+        assert_eq!(k3.scale_function(), ScaleFunction::K3);
+        assert_eq!(k3.count(), t.count());
+        assert!(k3.centroids.len() <= 200);
+    }
+
+    #[test]
+    fn test_memory_introspection() {
+        let mut t = TDigest::with_size_and_hint(100, 1_000);
+        let base = core::mem::size_of::<TDigest>();
+        assert_eq!(t.len(), 0);
+        assert_eq!(t.byte_size(), base + 600 * core::mem::size_of::<Centroid>());
+
+        for v in lcg_values(10, 1) {
+            t.insert(v);
+        }
+        assert_eq!(t.buffered_len(), 10);
+        assert_eq!(t.len(), 0);
+
+        t.shrink_to_fit();
+        assert_eq!(t.buffered_len(), 0);
+        assert_eq!(t.len(), 10);
+        assert_eq!(t.byte_size(), base + 10 * core::mem::size_of::<Centroid>());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut t = TDigest::new_with_size(100);
+        t.extend(lcg_values(1_200, 2));
+        assert_eq!(t.stats().compressions, 2);
+        assert_eq!(t.stats().values_buffered, 1_200);
+        t.flush();
+        t.flush();
+        assert_eq!(t.stats().compressions, 3);
+        let allocated = t.stats().centroids_allocated * core::mem::size_of::<Centroid>();
+        assert_eq!(allocated, t.byte_size() - core::mem::size_of::<TDigest>());
+
+        let merged = TDigest::merge_digests(vec![t.clone(), t.clone()]);
+        assert_eq!(merged.stats().compressions, 7);
+        assert_eq!(merged.stats().values_buffered, 2_400);
+
+        t.merge(&merged);
+        assert_eq!(t.stats().compressions, 4);
+        let before = t.clone();
+        t.reset_stats();
+        assert_eq!((t.stats().compressions, t.stats().values_buffered), (0, 0));
+        assert_eq!(t, before);
+        let decoded = TDigest::from_bytes(&merged.to_bytes()).unwrap();
+        assert_eq!(decoded.stats().compressions, 0);
+    }
+
+    #[test]
+    fn test_hash_and_approx_eq() {
+        let hash = |digest: &TDigest| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            digest.hash(&mut hasher);
+            hasher.finish()
+        };
+        let a = TDigest::new_with_size(100).merge_unsorted(lcg_values(10_000, 1));
+        let mut cache = std::collections::HashMap::new();
+        cache.insert(a.clone(), "a");
+        assert_eq!(cache.get(&a), Some(&"a"));
+        assert_eq!(hash(&TDigest::default()), hash(&TDigest::default()));
+        assert_ne!(hash(&a), hash(&TDigest::default()));
+
+        let b = TDigest::new_with_size(200).merge_unsorted(lcg_values(10_000, 1));
+        assert!(a != b && a.approx_eq(&b, 0.01));
+        assert!(!a.approx_eq(&b, 1e-9));
+        assert!(!a.approx_eq(&TDigest::default(), 1.0));
+        assert!(TDigest::default().approx_eq(&TDigest::new_with_size(10), 0.0));
+    }
+
+    #[test]
+    fn test_flush_reuses_centroid_buffers() {
+        let mut t = TDigest::new_with_size(100);
+        t.extend(lcg_values(10_000, 1));
+
+        let mut seen = std::collections::HashSet::new();
+        for v in lcg_values(100_000, 2) {
+            t.insert(v);
+            seen.insert(t.centroids.as_ptr());
+        }
+        // The two allocations trade places on every compression.
+        assert_eq!(seen.len(), 2);
+        assert!(t.spare.is_empty());
+    }
+
+    /// Splits 0..10_000 across two sketches, merges them and checks the median.
+    fn merged_median<S: QuantileSketch>(mut a: S, mut b: S) -> (f64, f64, f64) {
+        for i in 0..10_000 {
+            let sketch = if i % 2 == 0 { &mut a } else { &mut b };
+            sketch.insert(i as f64);
+        }
+        a.merge(&b);
+        (a.count(), a.quantile(0.5), a.cdf(2_500.0))
+    }
+
+    #[test]
+    fn test_quantile_sketch_is_generic() {
+        for (count, median, cdf) in [
+            merged_median(TDigest::new_with_size(100), TDigest::new_with_size(100)),
+            merged_median(AvlTreeDigest::new(100), AvlTreeDigest::new(100)),
+        ] {
+            assert_eq!(count, 10_000.0);
+            assert!((median - 5_000.0).abs() < 50.0, "{}", median);
+            assert!((cdf - 0.25).abs() < 0.005, "{}", cdf);
+        }
+
+        // Buffered values are visible without a flush.
+        let mut t = TDigest::new_with_size(100);
+        Digest::insert(&mut t, 1.0);
+        assert_eq!(Digest::count(&t), 1.0);
+        assert_eq!(t.quantile(0.5), 1.0);
+    }
+
+    #[test]
+    fn test_variance() {
+        let values = lcg_values(100_000, 3);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let exact = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / 100_000.0;
+
+        let estimated = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        assert!(!estimated.tracks_second_moment());
+        assert!((estimated.variance() - exact).abs() < 1e-3 * exact);
+
+        let mut tracked = TDigest::builder().second_moment(true).build();
+        tracked.extend(values.iter().copied());
+        tracked.flush();
+        assert!((tracked.variance() - exact).abs() < 1e-9);
+        assert!((tracked.stddev() - exact.sqrt()).abs() < 1e-9);
+
+        // Merges keep tracking only while every side has it.
+        let mut merged = tracked.clone();
+        merged.merge(&tracked);
+        assert!((merged.variance() - exact).abs() < 1e-9);
+        merged.merge(&estimated);
+        assert!(!merged.tracks_second_moment());
+        let all = TDigest::merge_digests(vec![tracked.clone(), tracked.clone()]);
+        assert!((all.variance() - exact).abs() < 1e-9);
+
+        let small = TDigest::new_with_size(100).merge_unsorted(vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(small.variance(), 1.25);
+        assert!(TDigest::default().variance().is_nan());
+    }
+
+    #[test]
+    fn test_variance_far_from_zero() {
+        // Values 1e9 + [0, 1), whose variance of about 1/12 would be lost to cancellation
+        // in a raw sum of squares.
+        let values: Vec<f64> = lcg_values(100_000, 4)
+            .into_iter()
+            .map(|v| 1e9 + v)
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let exact = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / 100_000.0;
+        assert!((exact - 1.0 / 12.0).abs() < 1e-3);
+
+        let (head, tail) = values.split_at(30_000);
+        let mut a = TDigest::builder().second_moment(true).build();
+        a.extend(head.iter().copied());
+        a.flush();
+        let mut b = TDigest::builder().second_moment(true).build();
+        b.extend(tail.iter().copied());
+        b.flush();
+
+        let merged = TDigest::merge_digests(vec![a.clone(), b.clone()]);
+        assert!((merged.variance() - exact).abs() < 1e-6);
+        let mut absorbed = a.clone();
+        absorbed.merge(&b);
+        assert!((absorbed.variance() - exact).abs() < 1e-6);
+        assert!((absorbed.subtract(&b).unwrap().variance() - a.variance()).abs() < 1e-6);
+    }
+}
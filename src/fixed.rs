@@ -0,0 +1,301 @@
+use crate::centroid::checked_weight;
+use crate::query::{Interpolation, QuantileCursor};
+use crate::scale::ScaleMerger;
+use crate::{Centroid, ScaleFunction};
+use alloc::borrow::Cow;
+
+/// A digest of at most `MAX` centroids stored inline, for hard real-time and embedded use:
+/// it never allocates, and being `Copy` it can be placed in a `static` or copied into
+/// shared memory as a whole.
+///
+/// Inserted values fill the free slots of the array; once it is full the slots are sorted
+/// and compressed in place to about `MAX / 2` centroids, leaving the other half free for
+/// the next values. The compression target halves again in the rare case the scale
+/// function leaves no slot free.
+///
+/// Queries compress a copy of the digest, on the stack, while values are pending; call
+/// [`ConstTDigest::flush`] first when running several.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstTDigest<const MAX: usize> {
+    centroids: [Centroid; MAX],
+    len: usize,
+    /// Length of the compressed, sorted prefix of `centroids`.
+    compressed: usize,
+    scale: ScaleFunction,
+    sum: f64,
+    count: f64,
+    max: f64,
+    min: f64,
+}
+
+impl<const MAX: usize> ConstTDigest<MAX> {
+    /// Creates an empty digest using [`ScaleFunction::K1`], the default.
+    pub const fn new() -> Self {
+        Self::with_scale(ScaleFunction::K1)
+    }
+
+    pub const fn with_scale(scale: ScaleFunction) -> Self {
+        assert!(MAX >= 2, "a fixed-capacity digest needs at least two slots");
+        ConstTDigest {
+            centroids: [Centroid {
+                mean: 0.0,
+                weight: 0.0,
+            }; MAX],
+            len: 0,
+            compressed: 0,
+            scale,
+            sum: 0.0,
+            count: 0.0,
+            max: f64::NAN,
+            min: f64::NAN,
+        }
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of occupied slots, centroids and pending values alike.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds a single value, see [`ConstTDigest::insert_weighted`].
+    pub fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times, compressing first if every
+    /// slot is taken. NaN and infinite values, and weights that are not positive and
+    /// finite or that would overflow the count, are ignored.
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        let Ok(count) = checked_weight(self.count, weight) else {
+            return;
+        };
+        if !value.is_finite() {
+            return;
+        }
+
+        if self.len == MAX {
+            self.compress();
+        }
+        self.centroids[self.len] = Centroid::new(value, weight);
+        self.len += 1;
+
+        self.count = count;
+        self.sum += value * weight;
+        if self.min.is_nan() || value < self.min {
+            self.min = value;
+        }
+        if self.max.is_nan() || value > self.max {
+            self.max = value;
+        }
+    }
+
+    /// Sorts and merges any pending values into the centroids.
+    pub fn flush(&mut self) {
+        if self.compressed < self.len {
+            self.compress();
+        }
+    }
+
+    /// Compresses every slot in place, halving the target until a slot is free. Scale
+    /// functions that keep more centroids than a tiny `MAX` allows, e.g. the tails of K2
+    /// and K3, fall back to merging the two closest neighbours, so a slot is always free
+    /// afterwards.
+    fn compress(&mut self) {
+        self.centroids[..self.len].sort_unstable();
+
+        let mut target = MAX / 2;
+        loop {
+            self.merge_in_place(target);
+            if self.len < MAX || target <= 1 {
+                break;
+            }
+            target /= 2;
+        }
+        if self.len == MAX {
+            self.merge_closest_pair();
+        }
+        self.compressed = self.len;
+    }
+
+    /// Merges the two adjacent centroids whose means are closest into one.
+    fn merge_closest_pair(&mut self) {
+        let slots = &self.centroids[..self.len];
+        let Some(i) = (0..slots.len() - 1).min_by(|&a, &b| {
+            let gap = |i: usize| slots[i + 1].mean() - slots[i].mean();
+            gap(a).total_cmp(&gap(b))
+        }) else {
+            return;
+        };
+        let next = self.centroids[i + 1];
+        self.centroids[i].update(next.mean() * next.weight(), next.weight());
+        self.centroids.copy_within(i + 2..self.len, i + 1);
+        self.len -= 1;
+    }
+
+    fn merge_in_place(&mut self, max_size: usize) {
+        if self.len <= max_size {
+            return;
+        }
+
+        let mut merger = ScaleMerger::new(self.count, max_size, &self.scale);
+        let mut written = 0;
+        for i in 0..self.len {
+            // Outputs trail the inputs they were merged from, so slot `written` has been
+            // read already.
+            if let Some(done) = merger.push(self.centroids[i]) {
+                self.centroids[written] = done;
+                written += 1;
+            }
+        }
+        if let Some(last) = merger.finish() {
+            self.centroids[written] = last;
+            written += 1;
+        }
+        self.len = written;
+    }
+
+    /// The compressed centroids in ascending order of mean; empty while values are
+    /// pending.
+    pub fn centroids(&self) -> &[Centroid] {
+        if self.compressed == self.len {
+            &self.centroids[..self.len]
+        } else {
+            &[]
+        }
+    }
+
+    fn query<T>(&self, f: impl FnOnce(Interpolation<'_>) -> T) -> T {
+        let flushed;
+        let digest = if self.compressed == self.len {
+            self
+        } else {
+            let mut copy = *self;
+            copy.flush();
+            flushed = copy;
+            &flushed
+        };
+
+        f(Interpolation {
+            centroids: Cow::Borrowed(&digest.centroids[..digest.len]),
+            count: digest.count,
+            min: digest.min,
+            max: digest.max,
+        })
+    }
+
+    /// Estimates quantile `q`, see
+    /// [`TDigest::estimate_quantile`](crate::TDigest::estimate_quantile).
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.query(|points| points.quantile(q, &mut QuantileCursor::default()))
+    }
+
+    /// Estimates the fraction of values at most `x`, see
+    /// [`TDigest::cdf`](crate::TDigest::cdf).
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.query(|points| points.rank(x, &mut QuantileCursor::default()) / points.count)
+    }
+}
+
+impl<const MAX: usize> Default for ConstTDigest<MAX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    static EMPTY: ConstTDigest<64> = ConstTDigest::new();
+
+    #[test]
+    fn test_const_digest_compresses_in_place() {
+        let mut t = EMPTY;
+        assert!(t.is_empty());
+        assert!(t.estimate_quantile(0.5).is_nan());
+
+        for i in 0..100_000 {
+            t.insert(((i * 7919) % 100_000) as f64);
+        }
+        assert!(t.len() <= 64);
+        assert!(t.centroids().is_empty());
+
+        let copy = t;
+        t.flush();
+        assert!(t.len() <= 32);
+        let means: Vec<f64> = t.centroids().iter().map(|c| c.mean()).collect();
+        assert!(means.windows(2).all(|w| w[0] <= w[1]));
+
+        assert_eq!((t.count(), t.min(), t.max()), (100_000.0, 0.0, 99_999.0));
+        for q in [0.01, 0.1, 0.5, 0.9, 0.99] {
+            let expected = q * 100_000.0;
+            assert!(
+                (t.estimate_quantile(q) - expected).abs() < 1_000.0,
+                "q = {}",
+                q
+            );
+            assert_eq!(copy.estimate_quantile(q), t.estimate_quantile(q));
+        }
+        assert!((t.cdf(25_000.0) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_const_digest_is_exact_while_small() {
+        let mut t = ConstTDigest::<8>::default();
+        for v in [3.0, 1.0, 2.0, f64::NAN] {
+            t.insert(v);
+        }
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.estimate_quantile(0.5), 2.0);
+        assert_eq!(t.cdf(0.5), 0.0);
+    }
+
+    #[test]
+    fn test_tiny_const_digests_always_free_a_slot() {
+        fn fill<const MAX: usize>(scale: ScaleFunction) {
+            let mut t = ConstTDigest::<MAX>::with_scale(scale);
+            for v in crate::lcg::lcg_values(20_000, 2) {
+                t.insert(v);
+            }
+            t.flush();
+            assert_eq!(t.count(), 20_000.0);
+            assert!(t.len() < MAX);
+            assert!((0.0..1.0).contains(&t.estimate_quantile(0.5)));
+        }
+        for scale in [
+            ScaleFunction::K0,
+            ScaleFunction::K1,
+            ScaleFunction::K2,
+            ScaleFunction::K3,
+        ] {
+            fill::<2>(scale);
+            fill::<3>(scale);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::TDigest;
+use hdrhistogram::{CreationError, Histogram};
+use std::vec::Vec;
+
+/// Takes each recorded bucket of the histogram as one value at the middle of the bucket,
+/// weighted by its count, into a digest of the default 100 centroids. `min` and `max`
+/// are the histogram's, i.e. the bounds of its lowest and highest buckets.
+impl From<&Histogram<u64>> for TDigest {
+    fn from(histogram: &Histogram<u64>) -> TDigest {
+        let buckets: Vec<(f64, f64)> = histogram
+            .iter_recorded()
+            .map(|bucket| {
+                let value = histogram.median_equivalent(bucket.value_iterated_to());
+                (value as f64, bucket.count_at_value() as f64)
+            })
+            .collect();
+        let mut digest = TDigest::default().merge_weighted(buckets);
+        if !digest.is_empty() {
+            digest.min = histogram.min() as f64;
+            digest.max = histogram.max() as f64;
+        }
+        digest
+    }
+}
+
+impl TDigest {
+    /// An auto-resizing HDR histogram of `sigfigs` significant decimal digits, see
+    /// [`Histogram::new`], with each centroid recorded at its mean.
+    ///
+    /// HDR histograms hold non-negative integer counts of non-negative integer values, so
+    /// means are rounded, negative ones becoming 0, and weights are rounded such that
+    /// the histogram's total is the rounded `count()`. Centroids of many values land in a
+    /// single bucket, so the histogram only has the digest's resolution; values recorded
+    /// one per centroid, as by small digests, carry over exactly.
+    pub fn to_hdr(&self, sigfigs: u8) -> Result<Histogram<u64>, CreationError> {
+        let mut histogram = Histogram::new(sigfigs)?;
+        let mut recorded = 0.0;
+        let mut cumulative = 0.0;
+        for centroid in self.view().iter() {
+            cumulative += centroid.weight();
+            let count = (cumulative.round() - recorded) as u64;
+            recorded = cumulative.round();
+            if count == 0 {
+                continue;
+            }
+            let value = centroid.mean().round().max(0.0) as u64;
+            if histogram.record_n(value, count).is_err() {
+                // Beyond what the histogram can grow to; clamp to its range.
+                histogram.saturating_record_n(value, count);
+            }
+        }
+        Ok(histogram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hdr_round_trip() {
+        let mut histogram = Histogram::<u64>::new(3).unwrap();
+        for value in 1..=50u64 {
+            histogram.record_n(value * 1_000, value).unwrap();
+        }
+        let digest = TDigest::from(&histogram);
+        assert_eq!(digest.count(), histogram.len() as f64);
+        assert_eq!(digest.min(), histogram.min() as f64);
+        assert_eq!(digest.max(), histogram.max() as f64);
+        let p90 = histogram.value_at_quantile(0.9) as f64;
+        assert!((digest.estimate_quantile(0.9) - p90).abs() / p90 < 0.02);
+
+        let back = digest.to_hdr(3).unwrap();
+        assert_eq!(back.len(), histogram.len());
+        assert!(back.equivalent(
+            back.value_at_quantile(0.5),
+            histogram.value_at_quantile(0.5)
+        ));
+
+        // Exact digests carry over value for value, with fractional weights rounded.
+        let mut small = TDigest::default();
+        small.insert_weighted(-3.0, 1.5);
+        small.insert_weighted(7.4, 1.0);
+        small.insert_weighted(12.0, 0.6);
+        let hdr = small.to_hdr(2).unwrap();
+        assert_eq!(hdr.len(), 3);
+        assert_eq!(
+            (hdr.count_at(0), hdr.count_at(7), hdr.count_at(12)),
+            (2, 1, 0)
+        );
+        assert!(TDigest::from(&Histogram::<u64>::new(2).unwrap()).is_empty());
+        assert!(TDigest::default().to_hdr(6).is_err());
+    }
+}
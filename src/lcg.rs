@@ -0,0 +1,17 @@
+//! Deterministic pseudo-random input shared by the unit tests and the benchmarks, which
+//! include this file by path.
+
+use std::vec::Vec;
+
+/// Deterministic pseudo-random values in `[0, 1)` from a linear congruential generator.
+pub fn lcg_values(n: usize, seed: u64) -> Vec<f64> {
+    let mut state = seed;
+    (0..n)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        })
+        .collect()
+}
@@ -1,232 +1,205 @@
-use ordered_float::OrderedFloat;
-use std::cmp::Ordering;
-
-// Implementing the Centroid data structure
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-pub struct Centroid {
-    mean: OrderedFloat<f64>,
-    weight: OrderedFloat<f64>,
-}
-
-impl PartialOrd for Centroid {
-    fn partial_cmp(&self, other: &Centroid) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for Centroid {
-    fn cmp(&self, other: &Centroid) -> Ordering {
-        self.mean.cmp(&other.mean)
-    }
-}
-
-impl Centroid {
-    pub fn new(mean: f64, weight: f64) -> Self {
-        Centroid {
-            mean: OrderedFloat::from(mean),
-            weight: OrderedFloat::from(weight),
-        }
-    }
-
-    #[inline]
-    pub fn mean(&self) -> f64 {
-        self.mean.into_inner()
-    }
-
-    #[inline]
-    pub fn weight(&self) -> f64 {
-        self.weight.into_inner()
-    }
-
-    pub fn update(&mut self, value: f64, weight: f64) -> (f64, f64) {
-        let _weight: f64 = self.weight();
-        let _mean: f64 = self.mean();
-
-        self.weight = OrderedFloat::from(weight + _weight);
-        self.mean = OrderedFloat::from((_mean * _weight + value) / self.weight());
-
-        (self.mean(), self.weight())
-    }
-}
-
-impl Default for Centroid {
-    fn default() -> Self {
-        Centroid::new(0.0, 1.0)
-    }
-}
-
-/// T-Digest to be operated on.
-#[derive(Debug, PartialEq, Eq, Clone)]
-#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-pub struct TDigest {
-    centroids: Vec<Centroid>,
-    max_size: usize,
-    sum: OrderedFloat<f64>,
-    count: OrderedFloat<f64>,
-    max: OrderedFloat<f64>,
-    min: OrderedFloat<f64>,
+//! T-Digest quantile sketches.
+//!
+//! The crate is `no_std` with `alloc` when the default `std` feature is disabled; the
+//! `libm` feature then supplies the floating-point functions, and the types that need
+//! threads ([`ConcurrentTDigest`], parallel merges) are unavailable. On x86_64, the
+//! `simd` feature scans the cumulative weights behind quantile and rank queries four
+//! centroids at a time.
+//!
+//! The crate builds for `wasm32-unknown-unknown`, where the `wasm-bindgen` feature
+//! exports `WasmTDigest` to JavaScript as `TDigest`.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("tdigest needs either the `std` or the `libm` feature for its float math");
+
+#[cfg(feature = "std")]
+mod aggregator;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod avl;
+mod cached;
+mod centroid;
+mod codec;
+mod compact;
+#[cfg(feature = "std")]
+mod concurrent;
+mod counted;
+mod decay;
+mod digest;
+mod fixed;
+#[cfg(feature = "hdrhistogram")]
+mod hdr;
+#[cfg(test)]
+mod lcg;
+mod map;
+mod math;
+mod merge;
+/// Serializes the NaN `min`/`max` of an empty digest as a missing value, since formats
+/// such as JSON cannot represent NaN.
+#[cfg(feature = "use_serde")]
+mod nan_as_none;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "proto")]
+mod proto;
+mod query;
+#[cfg(feature = "metrics")]
+mod recorder;
+#[cfg(feature = "rand")]
+mod sample;
+mod scale;
+mod series;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+#[cfg(feature = "std")]
+mod stream;
+mod tails;
+mod transform;
+#[cfg(feature = "wasm-bindgen")]
+mod wasm;
+mod window;
+
+#[cfg(feature = "std")]
+pub use aggregator::{Aggregator, AggregatorHandle, LocalRecorder};
+pub use avl::AvlTreeDigest;
+pub use cached::CachedTDigest;
+pub use centroid::Centroid;
+pub use codec::JavaEncoding;
+pub use compact::CompactTDigest;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentTDigest;
+pub use counted::CountedTDigest;
+pub use decay::ExponentialDecayTDigest;
+pub use digest::{
+    Digest, DigestStats, InvalidValuePolicy, QuantileSketch, RepairReport, TDigest, TDigestBuilder,
+};
+pub use fixed::ConstTDigest;
+pub use map::{TDigestMap, TDigestMapIntoIter};
+pub use merge::MergePolicy;
+#[cfg(feature = "proto")]
+pub use proto::TDigestProto;
+#[cfg(feature = "metrics")]
+pub use recorder::SummaryRecorder;
+pub use scale::{ScaleFn, ScaleFunction, TailBias};
+pub use series::DigestSeries;
+#[cfg(feature = "std")]
+pub use shared::{SharedTDigest, SharedTDigestWriter};
+#[cfg(feature = "std")]
+pub use stream::DigestLogReader;
+pub use tails::ExactTailsTDigest;
+pub use transform::{Transform, TransformedTDigest};
+#[cfg(feature = "wasm-bindgen")]
+pub use wasm::WasmTDigest;
+pub use window::WindowedTDigest;
+
+/// Errors reported by fallible digest operations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TDigestError {
+    /// More centroids than the allowed limit, e.g. in a merge before compression or in an
+    /// encoding with a fixed-width centroid count.
+    TooManyCentroids { centroids: usize, limit: usize },
+    /// `try_new` was given centroids that are not in ascending order of mean, starting at
+    /// this index.
+    UnsortedCentroids { index: usize },
+    /// `try_new` was given a centroid with a non-finite mean or a negative or NaN weight.
+    InvalidCentroid { index: usize },
+    /// A tracked total disagrees with the one implied by the centroids.
+    InconsistentTotal {
+        field: &'static str,
+        tracked: f64,
+        expected: f64,
+    },
+    /// The tracked `min`/`max` do not enclose the centroid means.
+    InconsistentBounds { min: f64, max: f64 },
+    /// A NaN or infinite value was inserted under [`InvalidValuePolicy::Error`].
+    InvalidValue { value: f64 },
+    /// Encoded bytes carry a format version this build cannot read.
+    UnsupportedVersion { version: u8 },
+    /// Encoded bytes are truncated or describe an invalid digest.
+    InvalidEncoding { reason: &'static str },
+    /// [`TDigest::subtract`] was asked to remove more weight than the digest holds.
+    SubtractionUnderflow { count: f64, subtracted: f64 },
+    /// An integer given to an exact insert, e.g. [`TDigest::try_insert_u64`], has no exact
+    /// `f64` representation.
+    InexactInteger { value: i128 },
+    /// Digests merged under [`MergePolicy::Strict`] differ in this setting.
+    MismatchedConfiguration { field: &'static str },
+    /// A weight at this index is not a whole count, e.g. converting to a
+    /// [`CountedTDigest`].
+    NonIntegralWeight { index: usize },
+    /// [`TDigest::from_histogram`] was given bucket bounds and counts that do not
+    /// describe a histogram.
+    InvalidHistogram { reason: &'static str },
+    /// A weight given to an insert or [`Centroid::try_update`] is not positive and finite.
+    InvalidWeight { weight: f64 },
+    /// Adding `weight` to a total weight of `total` overflows `f64`.
+    WeightOverflow { total: f64, weight: f64 },
 }
 
-impl TDigest {
-    pub fn new_with_size(max_size: usize) -> Self {
-        TDigest {
-            centroids: Vec::new(),
-            max_size,
-            sum: OrderedFloat::from(0.0),
-            count: OrderedFloat::from(0.0),
-            max: OrderedFloat::from(std::f64::NAN),
-            min: OrderedFloat::from(std::f64::NAN),
-        }
-    }
-
-    pub fn new(
-        centroids: Vec<Centroid>,
-        max_size: usize,
-        sum: f64,
-        count: f64,
-        max: f64,
-        min: f64,
-    ) -> Self {
-        if centroids.len() <= max_size {
-            TDigest {
-                centroids,
-                max_size,
-                sum: OrderedFloat::from(sum),
-                count: OrderedFloat::from(count),
-                max: OrderedFloat::from(max),
-                min: OrderedFloat::from(min),
+impl core::fmt::Display for TDigestError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TDigestError::TooManyCentroids { centroids, limit } => {
+                write!(f, "{} centroids exceed the limit of {}", centroids, limit)
+            }
+            TDigestError::UnsortedCentroids { index } => {
+                write!(f, "centroid {} is out of order", index)
+            }
+            TDigestError::InvalidCentroid { index } => {
+                write!(f, "centroid {} has an invalid mean or weight", index)
+            }
+            TDigestError::InconsistentTotal {
+                field,
+                tracked,
+                expected,
+            } => write!(
+                f,
+                "{} is {} but the centroids add up to {}",
+                field, tracked, expected
+            ),
+            TDigestError::InconsistentBounds { min, max } => write!(
+                f,
+                "min {} and max {} do not enclose the centroid means",
+                min, max
+            ),
+            TDigestError::InvalidValue { value } => {
+                write!(f, "cannot insert {} into a digest", value)
+            }
+            TDigestError::UnsupportedVersion { version } => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            TDigestError::InvalidEncoding { reason } => write!(f, "invalid encoding: {}", reason),
+            TDigestError::SubtractionUnderflow { count, subtracted } => write!(
+                f,
+                "cannot subtract a weight of {} from a digest of count {}",
+                subtracted, count
+            ),
+            TDigestError::InexactInteger { value } => {
+                write!(f, "{} cannot be represented exactly as f64", value)
+            }
+            TDigestError::MismatchedConfiguration { field } => {
+                write!(f, "cannot merge digests of different {}", field)
+            }
+            TDigestError::NonIntegralWeight { index } => {
+                write!(f, "weight {} is not a whole count", index)
+            }
+            TDigestError::InvalidHistogram { reason } => write!(f, "invalid histogram: {}", reason),
+            TDigestError::InvalidWeight { weight } => {
+                write!(f, "weight {} is not positive and finite", weight)
+            }
+            TDigestError::WeightOverflow { total, weight } => {
+                write!(f, "adding weight {} to {} overflows", weight, total)
             }
-        } else {
-            let sz = centroids.len();
-            let digests: Vec<TDigest> = vec![
-                TDigest::new_with_size(100),
-                TDigest::new(centroids, sz, sum, count, max, min),
-            ];
-            Self::merge_digests(digests)
-        }
-    }
-
-    #[inline]
-    pub fn mean(&self) -> f64 {
-        let count_: f64 = self.count.into_inner();
-        let sum_: f64 = self.sum.into_inner();
-
-        if count_ > 0.0 {
-            sum_ / count_
-        } else {
-            std::f64::NAN
-        }
-    }
-
-    #[inline]
-    pub fn sum(&self) -> f64 {
-        self.sum.into_inner()
-    }
-
-    #[inline]
-    pub fn count(&self) -> f64 {
-        self.count.into_inner()
-    }
-
-    #[inline]
-    pub fn max(&self) -> f64 {
-        self.max.into_inner()
-    }
-
-    #[inline]
-    pub fn min(&self) -> f64 {
-        self.min.into_inner()
-    }
-
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.centroids.is_empty()
-    }
-
-    #[inline]
-    pub fn max_size(&self) -> usize {
-        self.max_size
-    }
-}
-
-impl Default for TDigest {
-    fn default() -> Self {
-        TDigest {
-            centroids: Vec::new(),
-            max_size: 100,
-            sum: OrderedFloat::from(0.0),
-            count: OrderedFloat::from(0.0),
-            max: OrderedFloat::from(std::f64::NAN),
-            min: OrderedFloat::from(std::f64::NAN),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_new_centroid() {
-        let c = Centroid::new(5.0, 1.0);
-        assert_eq!(c.mean(), 5.0);
-        assert_eq!(c.weight(), 1.0);
-    }
-
-    #[test]
-    fn test_update_centroid() {
-        let mut c = Centroid::new(5.0, 1.0);
-        let (new_mean, new_weight) = c.update(7.0, 2.0);
-        assert_eq!(new_mean, (5.0 * 1.0 + 7.0) / 3.0); // New mean
-        assert_eq!(new_weight, 3.0); // New weight
-    }
-
-    #[test]
-    fn test_partial_cmp() {
-        let c1 = Centroid::new(5.0, 1.0);
-        let c2 = Centroid::new(7.0, 1.0);
-        assert!(c1 < c2);
-
-        let c3 = Centroid::new(3.0, 5.0);
-        let c4 = Centroid::new(5.0, 3.0);
-        assert!(c4 > c3);
-    }
-
-    #[test]
-    fn test_ord() {
-        let c1 = Centroid::new(5.0, 1.0);
-        let c2 = Centroid::new(7.0, 1.0);
-        assert_eq!(c1.cmp(&c2), Ordering::Less);
-        assert_eq!(c2.cmp(&c1), Ordering::Greater);
-        let c3 = Centroid::new(5.0, 2.0);
-        assert_eq!(c1.cmp(&c3), Ordering::Equal);
-    }
-
-    #[test]
-    fn test_clone_copy() {
-        let c1 = Centroid::new(5.0, 1.0);
-        let c2 = c1; // Copy
-        assert_eq!(c1, c2);
-        let c3 = c1;
-        assert_eq!(c1, c3);
-    }
-
-    #[test]
-    fn test_debug() {
-        let c = Centroid::new(5.0, 1.0);
-        let debug_str = format!("{:?}", c);
-        let actual_str = "Centroid { mean: OrderedFloat(5.0), weight: OrderedFloat(1.0) }";
-        assert_eq!(debug_str, actual_str);
-    }
-
-    #[test]
-    fn test_eq() {
-        let c1 = Centroid::new(5.0, 1.0);
-        let c2 = Centroid::new(5.0, 1.0);
-        assert_eq!(c1, c2);
-        let c3 = Centroid::new(5.0, 2.0);
-        assert_ne!(c1, c3); // Centroids are only equal if they have the same mean and weight
-    }
-}
+impl core::error::Error for TDigestError {}
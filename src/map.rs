@@ -0,0 +1,196 @@
+use crate::{TDigest, TDigestBuilder};
+use alloc::collections::btree_map::{self, BTreeMap};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    digest: TDigest,
+    /// Whether the key was recorded into since the last [`TDigestMap::evict_idle`].
+    active: bool,
+}
+
+/// One digest per key sharing a configuration, e.g. per endpoint or per customer, for
+/// group-by aggregation.
+///
+/// Digests are created from the configuration on a key's first value. Idle keys are
+/// evicted by sweeping: [`TDigestMap::evict_idle`] removes every key nothing was recorded
+/// into since the previous sweep, so calling it once per reporting interval keeps only
+/// the keys seen during that interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TDigestMap<K> {
+    config: TDigestBuilder,
+    digests: BTreeMap<K, Entry>,
+}
+
+impl<K: Ord> TDigestMap<K> {
+    /// Creates an empty map whose digests are built from `config`.
+    pub fn new(config: TDigestBuilder) -> Self {
+        TDigestMap {
+            config,
+            digests: BTreeMap::new(),
+        }
+    }
+
+    /// Records `value` for `key`, see [`TDigest::insert`].
+    pub fn record(&mut self, key: K, value: f64) {
+        self.record_weighted(key, value, 1.0);
+    }
+
+    /// Records `value` for `key` as if it had been seen `weight` times, see
+    /// [`TDigest::insert_weighted`].
+    pub fn record_weighted(&mut self, key: K, value: f64, weight: f64) {
+        self.entry(key).insert_weighted(value, weight);
+    }
+
+    /// The digest of `key`, created from the configuration if there is none yet, and
+    /// marked as active.
+    pub fn entry(&mut self, key: K) -> &mut TDigest {
+        let config = &self.config;
+        let entry = self.digests.entry(key).or_insert_with(|| Entry {
+            digest: config.build(),
+            active: true,
+        });
+        entry.active = true;
+        &mut entry.digest
+    }
+
+    /// The digest of `key`, if it has one.
+    pub fn get<Q>(&self, key: &Q) -> Option<&TDigest>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.digests.get(key).map(|entry| &entry.digest)
+    }
+
+    /// Removes `key`, returning its digest.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<TDigest>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.digests.remove(key).map(|entry| entry.digest)
+    }
+
+    /// Number of keys.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// The keys and their digests, in ascending order of key.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &TDigest)> {
+        self.digests.iter().map(|(key, entry)| (key, &entry.digest))
+    }
+
+    /// Folds every digest of `other` into the digest of the same key in `self`, creating
+    /// the ones `self` lacks. Merged keys count as active.
+    pub fn merge(&mut self, other: &TDigestMap<K>)
+    where
+        K: Clone,
+    {
+        for (key, entry) in other.digests.iter() {
+            match self.digests.get_mut(key) {
+                Some(ours) => {
+                    ours.digest.merge(&entry.digest);
+                    ours.active = true;
+                }
+                None => {
+                    self.entry(key.clone()).merge(&entry.digest);
+                }
+            }
+        }
+    }
+
+    /// Removes and returns, in ascending order of key, every key nothing was recorded
+    /// into since the previous call, and starts a new sweep for the rest.
+    pub fn evict_idle(&mut self) -> Vec<(K, TDigest)> {
+        let mut evicted = Vec::new();
+        let digests = core::mem::take(&mut self.digests);
+        for (key, mut entry) in digests {
+            if entry.active {
+                entry.active = false;
+                self.digests.insert(key, entry);
+            } else {
+                evicted.push((key, entry.digest));
+            }
+        }
+        evicted
+    }
+}
+
+/// Owning iterator over the keys and digests of a [`TDigestMap`], in ascending order of
+/// key.
+#[derive(Debug)]
+pub struct TDigestMapIntoIter<K>(btree_map::IntoIter<K, Entry>);
+
+impl<K> Iterator for TDigestMapIntoIter<K> {
+    type Item = (K, TDigest);
+
+    fn next(&mut self) -> Option<(K, TDigest)> {
+        self.0.next().map(|(key, entry)| (key, entry.digest))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K> IntoIterator for TDigestMap<K> {
+    type Item = (K, TDigest);
+    type IntoIter = TDigestMapIntoIter<K>;
+
+    fn into_iter(self) -> TDigestMapIntoIter<K> {
+        TDigestMapIntoIter(self.digests.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_record_and_merge() {
+        let config = TDigest::builder().max_size(50);
+        let mut a = TDigestMap::new(config.clone());
+        let mut b = TDigestMap::new(config);
+        for i in 0..1_000 {
+            a.record("/home", f64::from(i));
+            b.record(if i % 2 == 0 { "/home" } else { "/search" }, f64::from(i));
+        }
+        assert_eq!(a.get("/home").unwrap().max_size(), 50);
+
+        a.merge(&b);
+        assert_eq!(a.len(), 2);
+        assert_eq!(a.get("/home").unwrap().count(), 1_500.0);
+        assert_eq!(a.get("/search").unwrap().count(), 500.0);
+        assert!(a.get("/missing").is_none());
+        assert_eq!(
+            a.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec!["/home", "/search"]
+        );
+    }
+
+    #[test]
+    fn test_evict_idle() {
+        let mut map = TDigestMap::new(TDigest::builder());
+        map.record(1, 1.0);
+        map.record(2, 2.0);
+        assert!(map.evict_idle().is_empty());
+
+        map.record(2, 3.0);
+        let evicted = map.evict_idle();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!((evicted[0].0, evicted[0].1.count()), (1, 1.0));
+
+        let remaining: Vec<(i32, f64)> = map.into_iter().map(|(k, d)| (k, d.count())).collect();
+        assert_eq!(remaining, vec![(2, 2.0)]);
+    }
+}
@@ -0,0 +1,75 @@
+//! The `f64` methods `core` lacks, implemented with `libm` for `no_std` builds. With `std`
+//! (and in tests, which link it) the inherent methods are used and this module is empty.
+//!
+//! The inherent methods also take precedence whenever any crate in the build links `std`,
+//! e.g. a dependency whose `std` feature is enabled by a dev-dependency under
+//! `--all-targets`. No `cfg` can tell, so the trait and its imports allow going unused.
+
+/// Brings the `std` float methods into scope under their usual names.
+#[cfg(not(any(feature = "std", test)))]
+#[allow(dead_code)]
+pub(crate) trait Float {
+    fn asin(self) -> f64;
+    fn ceil(self) -> f64;
+    fn exp(self) -> f64;
+    fn floor(self) -> f64;
+    fn fract(self) -> f64;
+    fn ln(self) -> f64;
+    fn log10(self) -> f64;
+    fn powf(self, n: f64) -> f64;
+    fn powi(self, n: i32) -> f64;
+    fn round(self) -> f64;
+    fn sin(self) -> f64;
+    fn sqrt(self) -> f64;
+}
+
+#[cfg(not(any(feature = "std", test)))]
+impl Float for f64 {
+    fn asin(self) -> f64 {
+        libm::asin(self)
+    }
+
+    fn ceil(self) -> f64 {
+        libm::ceil(self)
+    }
+
+    fn exp(self) -> f64 {
+        libm::exp(self)
+    }
+
+    fn floor(self) -> f64 {
+        libm::floor(self)
+    }
+
+    fn fract(self) -> f64 {
+        self - libm::trunc(self)
+    }
+
+    fn ln(self) -> f64 {
+        libm::log(self)
+    }
+
+    fn log10(self) -> f64 {
+        libm::log10(self)
+    }
+
+    fn powf(self, n: f64) -> f64 {
+        libm::pow(self, n)
+    }
+
+    fn powi(self, n: i32) -> f64 {
+        libm::pow(self, f64::from(n))
+    }
+
+    fn round(self) -> f64 {
+        libm::round(self)
+    }
+
+    fn sin(self) -> f64 {
+        libm::sin(self)
+    }
+
+    fn sqrt(self) -> f64 {
+        libm::sqrt(self)
+    }
+}
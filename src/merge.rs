@@ -0,0 +1,1285 @@
+use crate::centroid::{checked_weight, compensated_sum, Moments, NeumaierSum};
+use crate::digest::drifted;
+use crate::scale::{ScaleMerger, Scaling};
+use crate::{Centroid, ScaleFn, TDigest, TDigestError};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// How [`TDigest::merge_with`] and [`TDigest::merge_digests_with`] reconcile digests of
+/// different compressions or scale functions. Other settings, e.g. the buffer size, are
+/// always those of the receiving or first digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MergePolicy {
+    /// Keep the compression and scale function of the receiving or first digest, like
+    /// [`TDigest::merge`] and [`TDigest::merge_digests`].
+    #[default]
+    KeepFirst,
+    /// Compress to the largest `max_size` among the digests, so no input loses
+    /// resolution, under the scale function of the receiving or first digest.
+    LargestCompression,
+    /// Compress to this `max_size`, under the scale function of the receiving or first
+    /// digest.
+    Compression(usize),
+    /// Fail with [`TDigestError::MismatchedConfiguration`] unless every digest has the
+    /// same `max_size` and scale function.
+    Strict,
+}
+
+/// Merges two iterators of sorted centroids into one sorted sequence.
+pub(crate) struct MergeSorted<A: Iterator<Item = Centroid>, B: Iterator<Item = Centroid>> {
+    a: core::iter::Peekable<A>,
+    b: core::iter::Peekable<B>,
+}
+
+impl<A: Iterator<Item = Centroid>, B: Iterator<Item = Centroid>> MergeSorted<A, B> {
+    pub(crate) fn new(a: A, b: B) -> Self {
+        MergeSorted {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<A: Iterator<Item = Centroid>, B: Iterator<Item = Centroid>> Iterator for MergeSorted<A, B> {
+    type Item = Centroid;
+
+    fn next(&mut self) -> Option<Centroid> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) if x <= y => self.a.next(),
+            (Some(_), Some(_)) => self.b.next(),
+            (Some(_), None) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+impl TDigest {
+    /// Merges the sorted runs `[first, middle)` and `[middle, last)` of `centroids` in place.
+    fn external_merge(centroids: &mut [Centroid], first: usize, middle: usize, last: usize) {
+        let mut result: Vec<Centroid> = Vec::with_capacity(last - first);
+
+        let mut i = first;
+        let mut j = middle;
+
+        while i < middle && j < last {
+            if centroids[i] <= centroids[j] {
+                result.push(centroids[i]);
+                i += 1;
+            } else {
+                result.push(centroids[j]);
+                j += 1;
+            }
+        }
+
+        result.extend_from_slice(&centroids[i..middle]);
+        result.extend_from_slice(&centroids[j..last]);
+
+        centroids[first..last].copy_from_slice(&result);
+    }
+
+    /// Compresses sorted centroids with a combined weight of `count` into roughly
+    /// `max_size` centroids, sized by `scale`, writing them to `compressed` (which is
+    /// cleared first, keeping its allocation). Returns their total sum.
+    ///
+    /// While `count` and the number of centroids both stay within `max_size` nothing is
+    /// merged, so small digests keep every value exactly (see [`TDigest::is_exact`]).
+    pub(crate) fn compress_sorted<I, S>(
+        centroids: I,
+        count: f64,
+        max_size: usize,
+        scale: &S,
+        compressed: &mut Vec<Centroid>,
+    ) -> f64
+    where
+        I: IntoIterator<Item = Centroid>,
+        S: ScaleFn + ?Sized,
+    {
+        compressed.clear();
+        let mut iter_centroids = centroids.into_iter();
+        if count > max_size as f64 {
+            return Self::compress_to_scale(iter_centroids, count, max_size, scale, compressed);
+        }
+
+        compressed.extend(iter_centroids.by_ref().take(max_size + 1));
+        if compressed.len() <= max_size {
+            return compensated_sum(compressed.iter().map(|c| c.mean() * c.weight()));
+        }
+
+        // Many fractional weights, e.g. after decay: compress after all.
+        let head = core::mem::take(compressed);
+        Self::compress_to_scale(
+            head.into_iter().chain(iter_centroids),
+            count,
+            max_size,
+            scale,
+            compressed,
+        )
+    }
+
+    /// The merging pass of [`TDigest::compress_sorted`], appending to `compressed`.
+    fn compress_to_scale<I, S>(
+        centroids: I,
+        count: f64,
+        max_size: usize,
+        scale: &S,
+        compressed: &mut Vec<Centroid>,
+    ) -> f64
+    where
+        I: IntoIterator<Item = Centroid>,
+        S: ScaleFn + ?Sized,
+    {
+        let mut sum = NeumaierSum::default();
+        let mut merger = ScaleMerger::new(count, max_size, scale);
+        for centroid in centroids {
+            if let Some(done) = merger.push(centroid) {
+                sum.add(done.mean() * done.weight());
+                compressed.push(done);
+            }
+        }
+        if let Some(last) = merger.finish() {
+            sum.add(last.mean() * last.weight());
+            compressed.push(last);
+        }
+
+        sum.value()
+    }
+
+    /// Merges several digests into one, compressing to the `max_size` of the first digest.
+    ///
+    /// The centroid lists are combined with a k-way merge of the already-sorted inputs
+    /// (pairwise, in `log2(digests.len())` rounds) and then recompressed in a single pass.
+    /// `count` and `sum` are the (compensated) totals of the inputs' `count()` and `sum()`,
+    /// and `min`/`max` are taken across all non-empty inputs.
+    /// Merging no digests gives `TDigest::default()`. The other inputs' configurations do
+    /// not matter; see [`TDigest::merge_digests_with`] to pick the compression or reject
+    /// mismatches instead.
+    ///
+    /// If any input is [deterministic](TDigestBuilder::deterministic), the inputs are
+    /// sorted into a canonical order first and the result is deterministic and canonical.
+    pub fn merge_digests(mut digests: Vec<TDigest>) -> TDigest {
+        // The totals of the inputs as they were, before flushing rounds their sums.
+        let mut totals: Vec<(f64, f64)> = digests.iter().map(|d| (d.count, d.sum)).collect();
+        digests.iter_mut().for_each(TDigest::flush);
+        let deterministic = digests.iter().any(|d| d.deterministic);
+        if deterministic {
+            let mut inputs: Vec<((f64, f64), TDigest)> = totals.into_iter().zip(digests).collect();
+            inputs.sort_by(|(a_totals, a), (b_totals, b)| {
+                a_totals
+                    .0
+                    .total_cmp(&b_totals.0)
+                    .then(a_totals.1.total_cmp(&b_totals.1))
+                    .then_with(|| a.canonical_cmp(b))
+            });
+            (totals, digests) = inputs.into_iter().unzip();
+        }
+        let mut total = NeumaierSum::default();
+        let mut total_sum = NeumaierSum::default();
+        for &(count, sum) in totals.iter().filter(|(count, _)| *count > 0.0) {
+            total.add(count);
+            total_sum.add(sum);
+        }
+
+        let Some(first) = digests.first() else {
+            return TDigest::default();
+        };
+        let (max_size, buffer_size, scale, tail_bias, invalid_values) = (
+            first.max_size,
+            first.buffer_size,
+            first.scale,
+            first.tail_bias,
+            first.invalid_values,
+        );
+        let custom_scale = first.custom_scale.clone();
+        // Tracked only if the first digest asks for it and every digest with data has it.
+        let m2 = digests[0].m2.and_then(|_| {
+            digests
+                .iter()
+                .filter(|d| d.count() > 0.0)
+                .try_fold(Moments::default(), |all, d| Some(all.merge(d.moments()?)))
+                .map(|all| all.m2)
+        });
+
+        let compressions = digests.iter().map(|d| d.compressions).sum::<u64>();
+        let values_buffered = digests.iter().map(|d| d.values_buffered).sum();
+
+        let n_centroids: usize = digests.iter().map(|d| d.centroids.len()).sum();
+        if n_centroids == 0 {
+            let mut result = TDigest::new_with_scale(max_size, scale);
+            result.compressions = compressions;
+            result.values_buffered = values_buffered;
+            result.buffer_size = buffer_size;
+            result.custom_scale = custom_scale;
+            result.tail_bias = tail_bias;
+            result.invalid_values = invalid_values;
+            result.m2 = m2;
+            result.deterministic = deterministic;
+            return result;
+        }
+
+        let mut centroids: Vec<Centroid> = Vec::with_capacity(n_centroids);
+        let mut starts: Vec<usize> = Vec::with_capacity(digests.len());
+
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+
+        for digest in digests.into_iter() {
+            if digest.count() > 0.0 {
+                starts.push(centroids.len());
+                min = min.min(digest.min);
+                max = max.max(digest.max);
+                centroids.extend(digest.centroids);
+            }
+        }
+
+        let count = total.value();
+
+        let mut digests_per_block: usize = 1;
+        while digests_per_block < starts.len() {
+            for i in (0..starts.len()).step_by(digests_per_block * 2) {
+                if i + digests_per_block < starts.len() {
+                    let first = starts[i];
+                    let middle = starts[i + digests_per_block];
+                    let last = if i + 2 * digests_per_block < starts.len() {
+                        starts[i + 2 * digests_per_block]
+                    } else {
+                        centroids.len()
+                    };
+
+                    debug_assert!(first <= middle && middle <= last);
+                    Self::external_merge(&mut centroids, first, middle, last);
+                }
+            }
+
+            digests_per_block *= 2;
+        }
+
+        let mut compressed = Vec::with_capacity(max_size);
+        let scaling = Scaling {
+            scale,
+            custom: custom_scale.as_deref(),
+            bias: tail_bias,
+        };
+        scaling.compress_sorted(centroids, count, max_size, &mut compressed);
+        let sum = total_sum.value();
+
+        let mut result = TDigest::new_with_scale(max_size, scale);
+        result.compressions = compressions + 1;
+        result.values_buffered = values_buffered;
+        result.buffer_size = buffer_size;
+        result.custom_scale = custom_scale;
+        result.tail_bias = tail_bias;
+        result.invalid_values = invalid_values;
+        result.m2 = m2;
+        result.centroids = compressed;
+        result.sum = sum;
+        result.count = count;
+        result.min = min;
+        result.max = max;
+        result.deterministic = deterministic;
+        if deterministic {
+            result.canonicalize();
+        }
+        result
+    }
+
+    /// Orders flushed digests by their totals, extremes, centroids and compression, so
+    /// that only digests alike in all of them tie.
+    fn canonical_cmp(&self, other: &TDigest) -> Ordering {
+        [
+            (self.count, other.count),
+            (self.sum, other.sum),
+            (self.min, other.min),
+            (self.max, other.max),
+        ]
+        .iter()
+        .fold(Ordering::Equal, |order, (a, b)| order.then(a.total_cmp(b)))
+        .then_with(|| self.centroids.cmp(&other.centroids))
+        .then(self.max_size.cmp(&other.max_size))
+    }
+
+    /// Brings the digest into a canonical form, so that digests of equal centroids and
+    /// totals serialize identically: buffered values are compressed, and negative zeros,
+    /// which compare equal to positive ones, become positive ones. Centroids are already
+    /// kept in the total order of [`Centroid`], which breaks ties between equal means by
+    /// weight.
+    pub fn canonicalize(&mut self) {
+        self.flush();
+        // Adding zero turns -0.0 into 0.0 and leaves every other value alone.
+        for centroid in self.centroids.iter_mut() {
+            centroid.mean += 0.0;
+        }
+        self.sum += 0.0;
+        self.min += 0.0;
+        self.max += 0.0;
+    }
+
+    /// Merges `other` into `self`, recompressing to `self`'s `max_size`. Values still
+    /// buffered in either digest are included. The merged `count` and `sum` are exactly
+    /// the sums of those of the two digests before the merge, and `min`/`max` the
+    /// smaller and larger bounds of the two, those of an empty digest aside. A
+    /// [deterministic](TDigestBuilder::deterministic) `self` is left canonical.
+    ///
+    /// `other` is only read, and once `self`'s buffers have grown to size the merge
+    /// allocates nothing.
+    ///
+    /// `self` keeps its configuration whatever `other` uses; see [`TDigest::merge_with`]
+    /// to pick the compression or reject mismatches instead.
+    pub fn merge(&mut self, other: &TDigest) {
+        // `other`'s buffered values are sorted into the scratch buffer and streamed in
+        // along with its centroids, rather than flushing a copy of `other`.
+        let mut scratch = core::mem::take(&mut self.scratch);
+        scratch.extend_from_slice(&other.buffer);
+        scratch.sort_unstable();
+        self.absorb(
+            MergeSorted::new(other.centroids.iter().copied(), scratch.drain(..)),
+            other.count,
+            other.sum,
+            other.min,
+            other.max,
+            other.moments(),
+        );
+        self.scratch = scratch;
+        if self.deterministic {
+            self.canonicalize();
+        }
+    }
+
+    /// Compresses the sorted `centroids` of another digest with the given `count`, `sum`,
+    /// `min`, `max` and second moment into `self`. The totals are added to `self`'s as
+    /// they were before flushing, so the flush's rounding does not reach them.
+    fn absorb<I>(
+        &mut self,
+        centroids: I,
+        count: f64,
+        sum: f64,
+        min: f64,
+        max: f64,
+        moments: Option<Moments>,
+    ) where
+        I: IntoIterator<Item = Centroid>,
+    {
+        if count <= 0.0 {
+            self.flush();
+            return;
+        }
+        let (total, sum) = (self.count + count, self.sum + sum);
+        self.flush();
+        self.m2 = self.moments().zip(moments).map(|(a, b)| a.merge(b).m2);
+
+        self.compress_with(centroids.into_iter(), total);
+
+        if self.count() > 0.0 {
+            self.min = self.min.min(min);
+            self.max = self.max.max(max);
+        } else {
+            self.min = min;
+            self.max = max;
+        }
+        self.sum = sum;
+        self.count = total;
+    }
+
+    /// Best-effort difference `self - other`, e.g. a daily digest from today's and
+    /// yesterday's cumulative ones. Each centroid of `other` takes its weight from the
+    /// centroids of `self` nearest to its mean, moving outwards once they are used up;
+    /// centroids left without weight are dropped, and the result keeps `self`'s
+    /// configuration.
+    ///
+    /// Means are left where they are, so the result is only as accurate as the two
+    /// digests' centroids line up: it is exact while both are exact (see
+    /// [`TDigest::is_exact`]) and `other`'s values are among `self`'s, and degrades where
+    /// `other` holds values `self` was compressed around differently. Both digests'
+    /// buffered values are included, and the second moment is kept if both track it.
+    ///
+    /// Fails with [`TDigestError::SubtractionUnderflow`] if `other` holds more weight
+    /// than `self`, which would leave negative counts.
+    pub fn subtract(&self, other: &TDigest) -> Result<TDigest, TDigestError> {
+        let mut result = self.clone();
+        result.flush();
+
+        let flushed;
+        let other = if other.buffer.is_empty() {
+            other
+        } else {
+            let mut copy = other.clone();
+            copy.flush();
+            flushed = copy;
+            &flushed
+        };
+
+        if other.count() > result.count() && drifted(result.count(), other.count()) {
+            return Err(TDigestError::SubtractionUnderflow {
+                count: result.count(),
+                subtracted: other.count(),
+            });
+        }
+        if other.is_empty() {
+            return Ok(result);
+        }
+
+        let before = result.moments();
+        let centroids = &mut result.centroids;
+        let n = centroids.len();
+        for removed in other.centroids.iter() {
+            let mut remaining = removed.weight();
+            let at = centroids.partition_point(|c| c.mean() < removed.mean());
+            let (mut lo, mut hi) = (at, at);
+            while remaining > 0.0 {
+                while lo > 0 && centroids[lo - 1].weight() <= 0.0 {
+                    lo -= 1;
+                }
+                while hi < n && centroids[hi].weight() <= 0.0 {
+                    hi += 1;
+                }
+                let nearest = match (lo > 0, hi < n) {
+                    (true, true) => {
+                        let below = removed.mean() - centroids[lo - 1].mean();
+                        let above = centroids[hi].mean() - removed.mean();
+                        if below <= above {
+                            lo - 1
+                        } else {
+                            hi
+                        }
+                    }
+                    (true, false) => lo - 1,
+                    (false, true) => hi,
+                    (false, false) => break,
+                };
+
+                let taken = remaining.min(centroids[nearest].weight());
+                centroids[nearest].weight -= taken;
+                remaining -= taken;
+            }
+        }
+
+        let first_mean = centroids.first().map(|c| c.mean);
+        let last_mean = centroids.last().map(|c| c.mean);
+        centroids.retain(|c| c.weight() > 0.0);
+        if centroids.is_empty() {
+            result.reset();
+            return Ok(result);
+        }
+
+        if first_mean != centroids.first().map(|c| c.mean) {
+            result.min = centroids[0].mean;
+        }
+        if last_mean != centroids.last().map(|c| c.mean) {
+            result.max = centroids[centroids.len() - 1].mean;
+        }
+        result.count = compensated_sum(centroids.iter().map(|c| c.weight()));
+        result.sum = compensated_sum(centroids.iter().map(|c| c.mean() * c.weight()));
+        result.m2 = before
+            .zip(other.moments())
+            .map(|(all, removed)| all.remove(removed).m2);
+        Ok(result)
+    }
+
+    /// Moves all of `self`'s data into `dest`, leaving `self` empty.
+    ///
+    /// The centroids are moved rather than cloned, and `self` keeps its allocation so it
+    /// can go on accumulating new data, e.g. between pipeline stages.
+    pub fn drain_into(&mut self, dest: &mut TDigest) {
+        let (count, sum) = (self.count, self.sum);
+        self.flush();
+        let (min, max, moments) = (self.min, self.max, self.moments());
+        dest.absorb(self.centroids.drain(..), count, sum, min, max, moments);
+        self.reset();
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.centroids.clear();
+        self.buffer.clear();
+        self.m2 = self.m2.map(|_| 0.0);
+        self.sum = 0.0;
+        self.count = 0.0;
+        self.max = f64::NAN;
+        self.min = f64::NAN;
+    }
+
+    /// Like [`TDigest::merge_digests`], but refuses to merge when the inputs together hold
+    /// more than `centroid_limit` centroids, guarding against the transient memory spike
+    /// of concatenating many large digests before compression. Buffered values count as
+    /// one centroid each, since they are concatenated along with the centroids.
+    pub fn merge_digests_checked(
+        digests: Vec<TDigest>,
+        centroid_limit: usize,
+    ) -> Result<TDigest, TDigestError> {
+        let n_centroids: usize = digests
+            .iter()
+            .map(|d| d.centroids.len() + d.buffer.len())
+            .sum();
+        if n_centroids > centroid_limit {
+            return Err(TDigestError::TooManyCentroids {
+                centroids: n_centroids,
+                limit: centroid_limit,
+            });
+        }
+
+        Ok(Self::merge_digests(digests))
+    }
+
+    /// The `max_size` a merge of `self` and `others` compresses to under `policy`.
+    fn merged_max_size<'a, I>(&self, others: I, policy: MergePolicy) -> Result<usize, TDigestError>
+    where
+        I: IntoIterator<Item = &'a TDigest>,
+    {
+        let mut max_size = self.max_size;
+        for other in others {
+            match policy {
+                MergePolicy::KeepFirst | MergePolicy::Compression(_) => {}
+                MergePolicy::LargestCompression => max_size = max_size.max(other.max_size),
+                MergePolicy::Strict => {
+                    let field = if other.max_size != self.max_size {
+                        "max_size"
+                    } else if !other.same_scale(self) {
+                        "scale function"
+                    } else {
+                        continue;
+                    };
+                    return Err(TDigestError::MismatchedConfiguration { field });
+                }
+            }
+        }
+        Ok(match policy {
+            MergePolicy::Compression(max_size) => max_size,
+            _ => max_size,
+        })
+    }
+
+    /// Switches to compressing to `max_size` from the next compression on, scaling a
+    /// default buffer size along.
+    pub(crate) fn set_max_size(&mut self, max_size: usize) {
+        if self.buffer_size == Self::default_buffer_size(self.max_size) {
+            self.buffer_size = Self::default_buffer_size(max_size);
+        }
+        self.max_size = max_size;
+    }
+
+    /// Like [`TDigest::merge`], but reconciling different configurations by `policy`:
+    /// the merge compresses to the `max_size` it picks, which `self` keeps afterwards.
+    /// Under [`MergePolicy::Strict`] a mismatch leaves `self` untouched.
+    pub fn merge_with(&mut self, other: &TDigest, policy: MergePolicy) -> Result<(), TDigestError> {
+        let max_size = self.merged_max_size([other], policy)?;
+        self.set_max_size(max_size);
+        self.merge(other);
+        Ok(())
+    }
+
+    /// Like [`TDigest::merge_digests`], but reconciling different configurations by
+    /// `policy`, see [`TDigest::merge_with`].
+    pub fn merge_digests_with(
+        mut digests: Vec<TDigest>,
+        policy: MergePolicy,
+    ) -> Result<TDigest, TDigestError> {
+        if let Some((first, rest)) = digests.split_first() {
+            let max_size = first.merged_max_size(rest, policy)?;
+            // On every digest, in case a deterministic merge reorders them.
+            digests.iter_mut().for_each(|d| d.set_max_size(max_size));
+        }
+        Ok(Self::merge_digests(digests))
+    }
+
+    /// Merges digests as probability distributions: every non-empty input is rescaled to
+    /// a total weight of 1.0 first, so each contributes equally regardless of its count.
+    ///
+    /// The result has a count equal to the number of non-empty inputs.
+    pub fn merge_normalized(digests: &[&TDigest]) -> TDigest {
+        let normalized: Vec<TDigest> = digests
+            .iter()
+            .map(|digest| {
+                let mut rescaled = (*digest).clone();
+                rescaled.flush();
+                if rescaled.count() > 0.0 {
+                    rescaled.scale_weight(1.0 / rescaled.count());
+                }
+                rescaled
+            })
+            .collect();
+
+        Self::merge_digests(normalized)
+    }
+
+    /// Merges digests after multiplying the weights of each by its factor, so sources of
+    /// different importance can be combined, e.g. `[(eu, 1.0), (us, 0.5)]`. The result
+    /// uses the first digest's configuration, see [`TDigest::merge_digests`].
+    pub fn weighted_merge(digests: &[(TDigest, f64)]) -> TDigest {
+        let scaled: Vec<TDigest> = digests
+            .iter()
+            .map(|(digest, factor)| {
+                let mut scaled = digest.clone();
+                scaled.scale_weight(*factor);
+                scaled
+            })
+            .collect();
+
+        Self::merge_digests(scaled)
+    }
+
+    /// Multiplies every weight, including buffered ones, and thereby `count`, `sum` and the
+    /// second moment, by `factor`. `min`, `max` and the quantiles are unchanged; a factor
+    /// of 0 empties the digest.
+    ///
+    /// # Panics
+    ///
+    /// If `factor` is negative, infinite or NaN.
+    pub fn scale_weight(&mut self, factor: f64) {
+        assert!(
+            factor >= 0.0 && factor.is_finite(),
+            "weight factor must be finite and non-negative"
+        );
+        if factor == 0.0 {
+            self.reset();
+            return;
+        }
+
+        for centroid in self.centroids.iter_mut().chain(self.buffer.iter_mut()) {
+            centroid.weight *= factor;
+        }
+        self.count *= factor;
+        self.sum *= factor;
+        self.m2 = self.m2.map(|m2| m2 * factor);
+    }
+
+    /// Merges a batch of values in any order; see [`TDigest::merge_sorted`].
+    pub fn merge_unsorted(mut self, unsorted_values: Vec<f64>) -> TDigest {
+        self.extend_unsorted(&unsorted_values);
+        self
+    }
+
+    /// Merges integer samples, e.g. metric counters, converting them to `f64`.
+    ///
+    /// Values with a magnitude above 2^53 cannot all be represented exactly as `f64`
+    /// and are rounded to the nearest representable value, see [`TDigest::insert_i64`].
+    pub fn merge_unsorted_ints(self, values: &[i64]) -> TDigest {
+        self.merge_unsorted(values.iter().map(|&v| v as f64).collect())
+    }
+
+    /// Unsigned counterpart of [`TDigest::merge_unsorted_ints`]; values above 2^53 are
+    /// rounded the same way.
+    pub fn merge_unsorted_uints(self, values: &[u64]) -> TDigest {
+        self.merge_unsorted(values.iter().map(|&v| v as f64).collect())
+    }
+
+    /// Merges values from a fallible source, e.g. parsed lines, stopping at the first
+    /// error. Nothing is merged unless every item is `Ok`.
+    pub fn try_merge<E, I>(self, iter: I) -> Result<TDigest, E>
+    where
+        I: IntoIterator<Item = Result<f64, E>>,
+    {
+        let values = iter.into_iter().collect::<Result<Vec<f64>, E>>()?;
+        Ok(self.merge_unsorted(values))
+    }
+
+    /// Merges a batch of values sorted in ascending order.
+    ///
+    /// The values are compressed together with the existing centroids so that the result
+    /// respects `max_size`, and `sum`, `count`, `min` and `max` are updated accordingly.
+    /// NaN and infinite values are handled by the [`InvalidValuePolicy`].
+    pub fn merge_sorted(mut self, sorted_values: Vec<f64>) -> TDigest {
+        self.extend_sorted(&sorted_values);
+        self
+    }
+
+    /// Merges pre-aggregated `(value, weight)` pairs in any order, e.g. from upstream
+    /// rollups, as if each value had been seen `weight` times. Pairs with a weight that
+    /// is not positive are ignored.
+    pub fn merge_weighted(mut self, weighted_values: Vec<(f64, f64)>) -> TDigest {
+        self.extend_weighted(&weighted_values);
+        self
+    }
+
+    /// In-place [`TDigest::merge_sorted`]: compresses the sorted values into the digest
+    /// in a single pass, allocating nothing once the digest's buffers have grown to size.
+    pub fn extend_sorted(&mut self, sorted_values: &[f64]) {
+        let weight = sorted_values.iter().filter(|&&v| self.accepts(v)).count() as f64;
+        // Having passed `accepts`, the finite values are exactly the accepted ones.
+        let accepted = sorted_values.iter().filter(|v| v.is_finite());
+        self.merge_sorted_in_place(accepted.map(|&v| Centroid::new(v, 1.0)), weight);
+    }
+
+    /// In-place [`TDigest::merge_unsorted`], sorting the values in a scratch buffer kept
+    /// on the digest rather than in a new allocation.
+    pub fn extend_unsorted(&mut self, unsorted_values: &[f64]) {
+        let mut scratch = core::mem::take(&mut self.scratch);
+        scratch.extend(
+            unsorted_values
+                .iter()
+                .filter(|&&v| self.accepts(v))
+                .map(|&v| Centroid::new(v, 1.0)),
+        );
+        scratch.sort_unstable();
+        let weight = scratch.len() as f64;
+        self.merge_sorted_in_place(scratch.drain(..), weight);
+        self.scratch = scratch;
+    }
+
+    /// In-place [`TDigest::merge_weighted`], sorting the pairs in the digest's scratch
+    /// buffer like [`TDigest::extend_unsorted`]. Pairs are checked like
+    /// [`TDigest::insert_weighted`] in order, so those whose weight would overflow the
+    /// count so far are dropped.
+    pub fn extend_weighted(&mut self, weighted_values: &[(f64, f64)]) {
+        let mut scratch = core::mem::take(&mut self.scratch);
+        let mut count = self.count;
+        for &(value, weight) in weighted_values {
+            if !self.accepts(value) {
+                continue;
+            }
+            if let Ok(total) = checked_weight(count, weight) {
+                count = total;
+                scratch.push(Centroid::new(value, weight));
+            }
+        }
+        scratch.sort_unstable();
+        let weight = compensated_sum(scratch.iter().map(|c| c.weight()));
+        self.merge_sorted_in_place(scratch.drain(..), weight);
+        self.scratch = scratch;
+    }
+
+    /// Streams the existing centroids and the sorted `centroids`, which weigh `weight` in
+    /// total, through the compressor in a single pass, without materialising the
+    /// combined, uncompressed list. The `centroids` are raw values, so they count towards
+    /// the second moment. Buffered values are compressed first.
+    fn merge_sorted_in_place<I>(&mut self, centroids: I, weight: f64)
+    where
+        I: Iterator<Item = Centroid>,
+    {
+        let mut values = centroids.peekable();
+        let maybe_min = match values.peek() {
+            Some(c) => c.mean,
+            None => return,
+        };
+        self.flush();
+        let mut maybe_max = maybe_min;
+
+        let count = self.count() + weight;
+        let mut added = Moments::default();
+        let tracking = self.m2.is_some();
+        let before = self.moments();
+        let sum = self.compress_with(
+            values.inspect(|c| {
+                maybe_max = c.mean;
+                if tracking {
+                    added.add(c.mean(), c.weight());
+                }
+            }),
+            count,
+        );
+        self.m2 = before.map(|before| before.merge(added).m2);
+
+        if self.count() > 0.0 {
+            self.min = self.min.min(maybe_min);
+            self.max = self.max.max(maybe_max);
+        } else {
+            self.min = maybe_min;
+            self.max = maybe_max;
+        }
+
+        self.sum = sum;
+        self.count = count;
+    }
+
+    /// Compresses the centroids together with the sorted `centroids` into the spare
+    /// vector, then swaps the two, like the reference `MergingDigest`'s ping-pong
+    /// buffers: once both have grown to size, merging allocates nothing. Returns the new
+    /// sum; `count` is the combined weight.
+    pub(crate) fn compress_with<I>(&mut self, centroids: I, count: f64) -> f64
+    where
+        I: Iterator<Item = Centroid>,
+    {
+        self.compressions += 1;
+        let mut spare = core::mem::take(&mut self.spare);
+        let merged = MergeSorted::new(self.centroids.iter().copied(), centroids);
+        let sum = self
+            .scaling()
+            .compress_sorted(merged, count, self.max_size, &mut spare);
+
+        core::mem::swap(&mut self.centroids, &mut spare);
+        spare.clear();
+        self.spare = spare;
+        sum
+    }
+}
+
+impl core::ops::AddAssign<&TDigest> for TDigest {
+    /// Merges `rhs` into `self`, see [`TDigest::merge`]. The result keeps `self`'s
+    /// `max_size`, buffer size and scale function whatever `rhs` uses.
+    fn add_assign(&mut self, rhs: &TDigest) {
+        self.merge(rhs);
+    }
+}
+
+impl core::ops::AddAssign for TDigest {
+    /// Merges `rhs` into `self`, keeping `self`'s configuration.
+    fn add_assign(&mut self, rhs: TDigest) {
+        self.merge(&rhs);
+    }
+}
+
+impl core::ops::Add<&TDigest> for TDigest {
+    type Output = TDigest;
+
+    /// The merge of both digests with the left operand's configuration, so `a + b` and
+    /// `b + a` differ when their `max_size` does.
+    fn add(mut self, rhs: &TDigest) -> TDigest {
+        self += rhs;
+        self
+    }
+}
+
+impl core::ops::Add for TDigest {
+    type Output = TDigest;
+
+    /// The merge of both digests with the left operand's configuration.
+    fn add(mut self, rhs: TDigest) -> TDigest {
+        self += &rhs;
+        self
+    }
+}
+
+impl core::iter::Sum for TDigest {
+    /// Merges all digests at once, see [`TDigest::merge_digests`]: the first digest's
+    /// configuration is used, and an empty iterator gives `TDigest::default()`.
+    fn sum<I: Iterator<Item = TDigest>>(iter: I) -> TDigest {
+        TDigest::merge_digests(iter.collect())
+    }
+}
+
+impl<'a> core::iter::Sum<&'a TDigest> for TDigest {
+    /// Like the owned `Sum`, cloning each digest.
+    fn sum<I: Iterator<Item = &'a TDigest>>(iter: I) -> TDigest {
+        iter.cloned().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+    use crate::ScaleFunction;
+    use alloc::vec;
+
+    #[test]
+    fn test_merge_normalized_gives_equal_influence() {
+        let small = TDigest::new_with_size(100).merge_unsorted((0..10).map(f64::from).collect());
+        let huge = TDigest::new_with_size(100).merge_unsorted(
+            (0..100_000)
+                .map(|i| 1000.0 + f64::from(i) / 100_000.0)
+                .collect(),
+        );
+
+        let weighted = TDigest::merge_digests(vec![small.clone(), huge.clone()]);
+        assert!(weighted.estimate_quantile(0.25) >= 1000.0);
+
+        let normalized = TDigest::merge_normalized(&[&small, &huge]);
+        assert_eq!(normalized.count(), 2.0);
+        assert!(normalized.estimate_quantile(0.25) <= 9.0);
+        assert!(normalized.estimate_quantile(0.75) >= 1000.0);
+
+        let median = normalized.estimate_quantile(0.5);
+        assert!((9.0..=1000.0).contains(&median));
+    }
+
+    #[test]
+    fn test_merge_unsorted_ints() {
+        let floats = TDigest::new_with_size(100).merge_unsorted(vec![3.0, 1.0, 2.0]);
+        let ints = TDigest::new_with_size(100).merge_unsorted_ints(&[3, 1, 2]);
+        let uints = TDigest::new_with_size(100).merge_unsorted_uints(&[3, 1, 2]);
+        assert_eq!(ints, floats);
+        assert_eq!(uints, floats);
+    }
+
+    #[test]
+    fn test_drain_into() {
+        let mut source =
+            TDigest::new_with_size(100).merge_unsorted((1..=1000).map(f64::from).collect());
+        let mut dest =
+            TDigest::new_with_size(100).merge_unsorted((1001..=1500).map(f64::from).collect());
+        let capacity = source.centroids.capacity();
+
+        source.drain_into(&mut dest);
+
+        assert!(source.is_empty());
+        assert_eq!(source.count(), 0.0);
+        assert_eq!(source.centroids.capacity(), capacity);
+
+        assert_eq!(dest.count(), 1500.0);
+        assert_eq!(dest.min(), 1.0);
+        assert_eq!(dest.max(), 1500.0);
+        assert!(dest.centroids.len() <= 100);
+        assert!(dest.centroids.windows(2).all(|w| w[0] <= w[1]));
+
+        let mut empty = TDigest::new_with_size(100);
+        source = TDigest::new_with_size(100).merge_unsorted(vec![1.0, 2.0]);
+        source.drain_into(&mut empty);
+        assert_eq!(empty.count(), 2.0);
+        assert_eq!(empty.min(), 1.0);
+        assert_eq!(empty.max(), 2.0);
+    }
+
+    #[test]
+    fn test_merge_digests_checked() {
+        let digests: Vec<TDigest> = (0..10)
+            .map(|i| {
+                TDigest::new_with_size(100)
+                    .merge_unsorted((0..50).map(|j| f64::from(i * 50 + j)).collect())
+            })
+            .collect();
+        let n_centroids: usize = digests.iter().map(|d| d.centroids.len()).sum();
+
+        let merged = TDigest::merge_digests_checked(digests.clone(), n_centroids).unwrap();
+        assert_eq!(merged, TDigest::merge_digests(digests.clone()));
+
+        let err = TDigest::merge_digests_checked(digests, n_centroids - 1).unwrap_err();
+        assert_eq!(
+            err,
+            TDigestError::TooManyCentroids {
+                centroids: n_centroids,
+                limit: n_centroids - 1
+            }
+        );
+
+        let mut buffered = TDigest::new_with_size(100);
+        for i in 0..400 {
+            buffered.insert(f64::from(i));
+        }
+        assert_eq!(
+            TDigest::merge_digests_checked(vec![buffered], 399),
+            Err(TDigestError::TooManyCentroids {
+                centroids: 400,
+                limit: 399
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_merge() {
+        let lines = ["3.0", "1.0", "2.0"];
+        let t = TDigest::new_with_size(100)
+            .try_merge(lines.iter().map(|l| l.parse::<f64>()))
+            .unwrap();
+        assert_eq!(
+            t,
+            TDigest::new_with_size(100).merge_unsorted(vec![3.0, 1.0, 2.0])
+        );
+
+        let lines = ["3.0", "oops", "2.0"];
+        let err = TDigest::new_with_size(100)
+            .try_merge(lines.iter().map(|l| l.parse::<f64>()))
+            .unwrap_err();
+        assert_eq!(err, "oops".parse::<f64>().unwrap_err());
+    }
+
+    #[test]
+    fn test_deterministic_merge_ignores_input_order() {
+        let config = TDigest::builder().second_moment(true).deterministic(true);
+        let digests: Vec<TDigest> = (0..5u64)
+            .map(|seed| {
+                let mut digest = config.clone().max_size(20 + seed as usize).build();
+                // Different compressions, with some values left buffered.
+                lcg_values(157 * (seed as usize + 1), seed)
+                    .iter()
+                    .for_each(|&v| digest.insert(v * 0.1 + 1e-3));
+                digest
+            })
+            .collect();
+
+        let expected = TDigest::merge_digests(digests.clone());
+        for rotation in 1..digests.len() {
+            let mut rotated = digests.clone();
+            rotated.rotate_left(rotation);
+            rotated.swap(0, 3);
+            let merged = TDigest::merge_digests(rotated);
+            assert!(merged.deterministic);
+            assert_eq!(merged, expected, "{}", rotation);
+            assert_eq!(merged.to_bytes(), expected.to_bytes());
+        }
+
+        let mut t = TDigest::new(
+            vec![Centroid::new(-0.0, 1.0), Centroid::new(2.0, 1.0)],
+            100,
+            2.0,
+            2.0,
+            2.0,
+            -0.0,
+        );
+        t.canonicalize();
+        assert!(t.centroids[0].mean().is_sign_positive() && t.min().is_sign_positive());
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let t = TDigest::new_with_size(100).merge_sorted((1..=1000).map(f64::from).collect());
+        assert_eq!(t.count(), 1000.0);
+        assert_eq!(t.sum(), 500_500.0);
+        assert_eq!(t.min(), 1.0);
+        assert_eq!(t.max(), 1000.0);
+        assert!(t.centroids.len() <= 100);
+        assert!(t.centroids.windows(2).all(|w| w[0] <= w[1]));
+
+        // Incremental batches are merged against the existing centroids.
+        let t = t
+            .merge_sorted((1001..=2000).map(f64::from).collect())
+            .merge_sorted(vec![-5.0, 0.0]);
+        assert_eq!(t.count(), 2002.0);
+        assert_eq!(t.min(), -5.0);
+        assert_eq!(t.max(), 2000.0);
+        assert!(t.centroids.len() <= 100);
+        assert!((t.mean() - 2_001_000.0 / 2002.0 + 5.0 / 2002.0).abs() < 1e-6);
+        assert!((t.estimate_quantile(0.5) - 1000.0).abs() < 10.0);
+
+        let unchanged = t.clone().merge_sorted(Vec::new());
+        assert_eq!(unchanged, t);
+    }
+
+    #[test]
+    fn test_merge_unsorted() {
+        let mut values: Vec<f64> = (0..1000).map(|i| f64::from((i * 7919) % 1000)).collect();
+        let t = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        values.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(t, TDigest::new_with_size(100).merge_sorted(values));
+    }
+
+    #[test]
+    fn test_merge_digests_against_exact_quantiles() {
+        let shards: Vec<Vec<f64>> = (0..10).map(|i| lcg_values(10_000, i)).collect();
+        let digests: Vec<TDigest> = shards
+            .iter()
+            .map(|values| TDigest::new_with_size(100).merge_unsorted(values.clone()))
+            .collect();
+
+        let merged = TDigest::merge_digests(digests);
+        let mut all: Vec<f64> = shards.concat();
+        all.sort_by(|a, b| a.total_cmp(b));
+
+        assert_eq!(merged.count(), 100_000.0);
+        assert_eq!(merged.min(), all[0]);
+        assert_eq!(merged.max(), all[all.len() - 1]);
+        assert!(merged.centroids.len() <= 100);
+        assert!((merged.sum() - all.iter().sum::<f64>()).abs() < 1e-6);
+
+        for &q in &[0.001, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999] {
+            let exact = all[(q * all.len() as f64) as usize];
+            let estimate = merged.estimate_quantile(q);
+            assert!(
+                (merged.cdf(estimate) - q).abs() < 0.01 && (estimate - exact).abs() < 0.01,
+                "q={} estimate={} exact={}",
+                q,
+                estimate,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_digests_edge_cases() {
+        assert_eq!(TDigest::merge_digests(Vec::new()), TDigest::default());
+
+        let empty = TDigest::merge_digests(vec![TDigest::new_with_size(20), TDigest::default()]);
+        assert_eq!(empty.max_size(), 20);
+        assert!(empty.is_empty());
+
+        let t = TDigest::new_with_size(20).merge_unsorted(vec![1.0, 2.0]);
+        let merged = TDigest::merge_digests(vec![TDigest::new_with_size(20), t.clone()]);
+        assert_eq!(merged, t);
+    }
+
+    #[test]
+    fn test_merge() {
+        let a_values = lcg_values(5_000, 1);
+        let b_values: Vec<f64> = lcg_values(5_000, 2).iter().map(|v| v + 1.0).collect();
+
+        let mut a = TDigest::new_with_size(100).merge_unsorted(a_values.clone());
+        let mut b = TDigest::new_with_size(100).merge_unsorted(b_values[..4_000].to_vec());
+        b.set_buffer_size(10_000);
+        for &v in &b_values[4_000..] {
+            b.insert(v);
+        }
+
+        a.merge(&b);
+        assert_eq!(a.count(), 10_000.0);
+        assert_eq!(b.count(), 5_000.0);
+        assert_eq!(b.buffered_len(), 1_000);
+        assert!(a.centroids.len() <= 100);
+        assert!((a.estimate_quantile(0.5) - 1.0).abs() < 0.02);
+        assert_eq!(
+            a.max(),
+            b_values.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+        );
+
+        let before = a.clone();
+        a.merge(&TDigest::new_with_size(100));
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn test_merge_weighted() {
+        let weighted = TDigest::new_with_size(100).merge_weighted(vec![
+            (3.0, 2.0),
+            (1.0, 1.0),
+            (2.0, 3.0),
+            (9.0, 0.0),
+        ]);
+        let expanded =
+            TDigest::new_with_size(100).merge_unsorted(vec![1.0, 2.0, 2.0, 2.0, 3.0, 3.0]);
+
+        assert_eq!(weighted.count(), 6.0);
+        assert_eq!(weighted.sum(), expanded.sum());
+        assert_eq!(weighted.min(), 1.0);
+        assert_eq!(weighted.max(), 3.0);
+        // Most of the weight sits at 2.0, so the median lands there or just above it.
+        let median = weighted.estimate_quantile(0.5);
+        assert!((2.0..2.5).contains(&median), "median={}", median);
+    }
+
+    #[test]
+    fn test_add_and_sum() {
+        let a = TDigest::new_with_size(100).merge_unsorted(lcg_values(1_000, 1));
+        let b = TDigest::new_with_size(20).merge_unsorted(lcg_values(1_000, 2));
+
+        let ab = a.clone() + &b;
+        assert_eq!(ab.count(), 2_000.0);
+        assert_eq!(ab.max_size(), 100);
+        let ba = b.clone() + a.clone();
+        assert_eq!(ba.max_size(), 20);
+        assert!(ba.centroids.len() <= 20);
+
+        let mut total = TDigest::new_with_size(100);
+        total += &a;
+        total += b.clone();
+        assert_eq!(total, ab);
+
+        let summed: TDigest = vec![a.clone(), b.clone()].into_iter().sum();
+        assert_eq!(summed.count(), 2_000.0);
+        assert_eq!(summed.max_size(), 100);
+        let by_ref: TDigest = [a, b].iter().sum();
+        assert_eq!(by_ref, summed);
+        assert!(core::iter::empty::<TDigest>().sum::<TDigest>().is_empty());
+    }
+
+    #[test]
+    fn test_merge_policies() {
+        let small = TDigest::new_with_size(100).merge_unsorted(lcg_values(10_000, 1));
+        let large = TDigest::new_with_size(1_000).merge_unsorted(lcg_values(10_000, 2));
+
+        let mut t = small.clone();
+        t.merge_with(&large, MergePolicy::KeepFirst).unwrap();
+        assert_eq!(t, small.clone() + &large);
+
+        t = small.clone();
+        t.merge_with(&large, MergePolicy::LargestCompression)
+            .unwrap();
+        assert_eq!(
+            (t.max_size(), t.buffer_size()),
+            (1_000, large.buffer_size())
+        );
+        assert!(t.len() > 100 && t.count() == 20_000.0);
+
+        let merged = TDigest::merge_digests_with(
+            vec![large.clone(), small.clone()],
+            MergePolicy::Compression(50),
+        )
+        .unwrap();
+        assert_eq!(merged.max_size(), 50);
+        assert!(merged.len() <= 50);
+
+        t = small.clone();
+        let err = t.merge_with(&large, MergePolicy::Strict).unwrap_err();
+        assert_eq!(
+            err,
+            TDigestError::MismatchedConfiguration { field: "max_size" }
+        );
+        assert_eq!(t, small);
+        let k2 = small.clone().with_scale_function(ScaleFunction::K2);
+        assert_eq!(
+            TDigest::merge_digests_with(vec![small.clone(), k2], MergePolicy::Strict),
+            Err(TDigestError::MismatchedConfiguration {
+                field: "scale function"
+            })
+        );
+        assert!(
+            TDigest::merge_digests_with(vec![small.clone(), small], MergePolicy::Strict).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_in_place_merges_reuse_scratch() {
+        let batch = lcg_values(1_000, 5);
+        let mut t = TDigest::new_with_size(100);
+        t.extend_unsorted(&batch);
+        assert_eq!(t, TDigest::new_with_size(100).merge_unsorted(batch.clone()));
+
+        let mut other = TDigest::new_with_size(100);
+        other.extend(lcg_values(50, 6));
+        assert_eq!(other.buffered_len(), 50);
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..10 {
+            t.extend_unsorted(&batch);
+            t.merge(&other);
+            t.extend_weighted(&[(0.5, 2.0), (f64::NAN, 1.0)]);
+            seen.insert(t.scratch.as_ptr());
+        }
+        assert_eq!(seen.len(), 1);
+        assert!(t.scratch.is_empty());
+        assert_eq!(t.count(), 11_000.0 + 500.0 + 20.0);
+
+        let mut sorted = batch;
+        sorted.sort_by(f64::total_cmp);
+        let mut s = TDigest::new_with_size(100);
+        s.extend_sorted(&sorted);
+        assert_eq!(s, TDigest::new_with_size(100).merge_unsorted(sorted));
+    }
+
+    #[test]
+    fn test_scale_weight_and_weighted_merge() {
+        let a = TDigest::new_with_size(100).merge_unsorted((0..1_000).map(f64::from).collect());
+        let mut scaled = a.clone();
+        scaled.insert(1_000.0);
+        scaled.scale_weight(0.5);
+        scaled.flush();
+        assert_eq!(scaled.count(), 500.5);
+        assert_eq!(scaled.sum(), (a.sum() + 1_000.0) * 0.5);
+        assert_eq!((scaled.min(), scaled.max()), (0.0, 1_000.0));
+
+        // Down-weighted to a's count, b holds the upper half.
+        let b = TDigest::new_with_size(100).merge_unsorted(vec![5_000.0; 4_000]);
+        let merged = TDigest::weighted_merge(&[(a.clone(), 1.0), (b.clone(), 0.25)]);
+        assert_eq!(merged.count(), 2_000.0);
+        assert!((merged.estimate_quantile(0.25) - 500.0).abs() < 10.0);
+        assert_eq!(merged.estimate_quantile(0.9), 5_000.0);
+
+        let without_b = TDigest::weighted_merge(&[(a.clone(), 1.0), (b, 0.0)]);
+        assert_eq!(without_b.count(), a.count());
+    }
+
+    #[test]
+    fn test_subtract() {
+        let yesterday =
+            TDigest::new_with_size(100).merge_unsorted((0..50).map(f64::from).collect());
+        let today = yesterday
+            .clone()
+            .merge_unsorted((50..80).map(f64::from).collect());
+
+        let daily = today.subtract(&yesterday).unwrap();
+        assert_eq!(daily.count(), 30.0);
+        assert_eq!((daily.min(), daily.max()), (50.0, 79.0));
+        assert_eq!(daily.sum(), (50..80).sum::<i32>() as f64);
+        assert_eq!(daily.estimate_quantile(0.5), 64.5);
+        assert!(today.subtract(&today).unwrap().is_empty());
+
+        // Approximate once compressed, but the totals still add up.
+        let yesterday = TDigest::new_with_size(100).merge_unsorted(lcg_values(10_000, 1));
+        let mut today = yesterday.clone();
+        today.extend(lcg_values(10_000, 2));
+        let daily = today.subtract(&yesterday).unwrap();
+        assert!((daily.count() - 10_000.0).abs() < 1e-6);
+        assert!((daily.estimate_quantile(0.5) - 0.5).abs() < 0.05);
+
+        assert_eq!(
+            yesterday.subtract(&today),
+            Err(TDigestError::SubtractionUnderflow {
+                count: 10_000.0,
+                subtracted: 20_000.0
+            })
+        );
+    }
+}
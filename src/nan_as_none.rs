@@ -0,0 +1,41 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub(crate) fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    (!value.is_nan()).then_some(*value).serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lcg::lcg_values;
+    use crate::{CompactTDigest, ScaleFunction, TDigest};
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut t = TDigest::builder()
+            .max_size(50)
+            .scale_function(ScaleFunction::K2)
+            .second_moment(true)
+            .build();
+        t.extend(lcg_values(10_000, 5));
+        t.insert_weighted(0.25, 2.0);
+        assert_eq!(t.buffered_len(), 1);
+
+        let json = serde_json::to_string(&t).unwrap();
+        let decoded: TDigest = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, t);
+        assert_eq!(decoded.variance(), t.variance());
+
+        let empty = TDigest::new_with_size(100);
+        let decoded: TDigest =
+            serde_json::from_str(&serde_json::to_string(&empty).unwrap()).unwrap();
+        assert_eq!(decoded, empty);
+
+        let json = serde_json::to_string(&CompactTDigest::from(&empty)).unwrap();
+        let compact: CompactTDigest = serde_json::from_str(&json).unwrap();
+        assert!(compact.is_empty() && compact.min().is_nan());
+    }
+}
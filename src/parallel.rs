@@ -0,0 +1,53 @@
+use crate::TDigest;
+use alloc::{vec, vec::Vec};
+
+/// Number of digests `par_merge_digests` merges sequentially at the leaves of its tree.
+const PAR_MERGE_LEAF: usize = 64;
+
+impl TDigest {
+    /// Like [`TDigest::merge_digests`], but merges in a parallel tree reduction on the rayon
+    /// thread pool: runs of up to `PAR_MERGE_LEAF` digests are merged sequentially and the
+    /// partial results combined pairwise. The result uses the first digest's
+    /// configuration.
+    ///
+    /// Each level of the tree recompresses, so the result can differ slightly from the
+    /// sequential merge, within the usual error bounds.
+    pub fn par_merge_digests(mut digests: Vec<TDigest>) -> TDigest {
+        if digests.len() <= PAR_MERGE_LEAF {
+            return Self::merge_digests(digests);
+        }
+
+        let right = digests.split_off(digests.len() / 2);
+        let (left, right) = rayon::join(
+            || Self::par_merge_digests(digests),
+            || Self::par_merge_digests(right),
+        );
+        Self::merge_digests(vec![left, right])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+
+    #[test]
+    fn test_par_merge_digests() {
+        let digests: Vec<TDigest> = (0..500)
+            .map(|seed| TDigest::new_with_size(100).merge_unsorted(lcg_values(200, seed)))
+            .collect();
+
+        let parallel = TDigest::par_merge_digests(digests.clone());
+        let sequential = TDigest::merge_digests(digests);
+        assert_eq!(parallel.count(), sequential.count());
+        assert_eq!(parallel.min(), sequential.min());
+        assert_eq!(parallel.max(), sequential.max());
+        assert!(parallel.centroids.len() <= 100);
+        for q in [0.01, 0.25, 0.5, 0.75, 0.99] {
+            let diff = parallel.estimate_quantile(q) - sequential.estimate_quantile(q);
+            assert!(diff.abs() < 0.01, "q = {}: {}", q, diff);
+        }
+
+        assert!(TDigest::par_merge_digests(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{Centroid, TDigest, TDigestError};
+use alloc::vec::Vec;
+use prost::Message;
+
+/// The `tdigest.TDigest` message of `proto/tdigest.proto`, for embedding digests in
+/// other protobuf messages, e.g. gRPC requests. It is written out by hand rather than
+/// generated, so building needs no `protoc`; the tags must stay in sync with the schema.
+#[derive(Clone, PartialEq, Message)]
+pub struct TDigestProto {
+    #[prost(uint64, tag = "1")]
+    pub compression: u64,
+    #[prost(double, optional, tag = "2")]
+    pub min: Option<f64>,
+    #[prost(double, optional, tag = "3")]
+    pub max: Option<f64>,
+    #[prost(double, repeated, tag = "4")]
+    pub means: Vec<f64>,
+    #[prost(double, repeated, tag = "5")]
+    pub weights: Vec<f64>,
+    #[prost(double, tag = "6")]
+    pub sum: f64,
+    #[prost(double, tag = "7")]
+    pub count: f64,
+}
+
+impl TDigest {
+    /// The digest as a protobuf message, compressing buffered values first. An empty
+    /// digest leaves `min`/`max` unset.
+    pub fn to_proto(&self) -> TDigestProto {
+        let flushed;
+        let digest = if self.buffer.is_empty() {
+            self
+        } else {
+            flushed = self.snapshot();
+            &flushed
+        };
+
+        let empty = digest.centroids.is_empty();
+        TDigestProto {
+            compression: digest.max_size as u64,
+            min: (!empty).then_some(digest.min),
+            max: (!empty).then_some(digest.max),
+            means: digest.centroids.iter().map(|c| c.mean()).collect(),
+            weights: digest.centroids.iter().map(|c| c.weight()).collect(),
+            sum: digest.sum,
+            count: digest.count,
+        }
+    }
+
+    /// Decodes a message built by [`TDigest::to_proto`], checking it like
+    /// [`TDigest::try_new`].
+    pub fn from_proto(message: &TDigestProto) -> Result<TDigest, TDigestError> {
+        if message.means.len() != message.weights.len() {
+            return Err(TDigestError::InvalidEncoding {
+                reason: "means and weights differ in length",
+            });
+        }
+        let centroids = message
+            .means
+            .iter()
+            .zip(message.weights.iter())
+            .map(|(&mean, &weight)| Centroid { mean, weight })
+            .collect();
+        TDigest::try_new(
+            centroids,
+            message.compression.min(usize::MAX as u64) as usize,
+            message.sum,
+            message.count,
+            message.max.unwrap_or(f64::NAN),
+            message.min.unwrap_or(f64::NAN),
+        )
+    }
+
+    /// Encodes the digest as protobuf bytes, see [`TDigest::to_proto`].
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        self.to_proto().encode_to_vec()
+    }
+
+    /// Decodes protobuf bytes written by [`TDigest::to_proto_bytes`] or any other
+    /// implementation of the schema.
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<TDigest, TDigestError> {
+        let message = TDigestProto::decode(bytes).map_err(|_| TDigestError::InvalidEncoding {
+            reason: "invalid protobuf message",
+        })?;
+        TDigest::from_proto(&message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_proto_round_trip() {
+        let t = TDigest::new_with_size(50).merge_unsorted(crate::lcg::lcg_values(1_000, 3));
+        assert_eq!(TDigest::from_proto_bytes(&t.to_proto_bytes()), Ok(t));
+
+        let mut buffered = TDigest::new_with_size(20);
+        buffered.insert(2.0);
+        let message = buffered.to_proto();
+        assert_eq!((message.means.clone(), message.min), (vec![2.0], Some(2.0)));
+
+        let empty = TDigest::from_proto(&TDigest::new_with_size(20).to_proto()).unwrap();
+        assert!(empty.is_empty() && empty.min().is_nan());
+        assert_eq!(empty.max_size(), 20);
+    }
+
+    #[test]
+    fn test_proto_wire_layout() {
+        // compression = 10, min = max = 1.0, one centroid (1.0, 1.0), sum = count = 1.0.
+        let one = 1f64.to_le_bytes();
+        let mut bytes = vec![0x08, 10];
+        for (tag, packed) in [(0x11, false), (0x19, false), (0x22, true), (0x2a, true)] {
+            bytes.push(tag);
+            if packed {
+                bytes.push(8);
+            }
+            bytes.extend_from_slice(&one);
+        }
+        for tag in [0x31, 0x39] {
+            bytes.push(tag);
+            bytes.extend_from_slice(&one);
+        }
+        assert_eq!(
+            TDigest::from_proto_bytes(&bytes),
+            Ok(TDigest::singleton(1.0, 10))
+        );
+        assert_eq!(TDigest::singleton(1.0, 10).to_proto_bytes(), bytes);
+
+        assert!(TDigest::from_proto_bytes(&[0x22, 8, 0]).is_err());
+        let mismatched = TDigestProto {
+            means: vec![1.0],
+            ..TDigestProto::default()
+        };
+        assert!(TDigest::from_proto(&mismatched).is_err());
+    }
+}
@@ -0,0 +1,1315 @@
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::{Centroid, TDigest};
+use alloc::{borrow::Cow, collections::BinaryHeap, string::String, vec, vec::Vec};
+use core::cmp::Reverse;
+
+/// The centroids of any number of digests, see [`TDigest::view`], merged lazily into one
+/// sorted sequence, which is what queries over several digests run on.
+struct MergedView<'a> {
+    views: Vec<Cow<'a, [Centroid]>>,
+    /// The next centroid of each view that has one, with the view and its position.
+    heads: BinaryHeap<Reverse<(Centroid, usize, usize)>>,
+    count: f64,
+    min: f64,
+    max: f64,
+}
+
+impl<'a> MergedView<'a> {
+    fn new(digests: impl IntoIterator<Item = &'a TDigest>) -> Self {
+        let mut merged = MergedView {
+            views: Vec::new(),
+            heads: BinaryHeap::new(),
+            count: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        };
+        for digest in digests.into_iter().filter(|d| !d.is_empty()) {
+            let view = digest.view();
+            if let Some(&first) = view.first() {
+                merged.heads.push(Reverse((first, merged.views.len(), 0)));
+                merged.views.push(view);
+            }
+            merged.count += digest.count;
+            merged.min = merged.min.min(digest.min);
+            merged.max = merged.max.max(digest.max);
+        }
+        merged
+    }
+}
+
+impl Iterator for MergedView<'_> {
+    type Item = Centroid;
+
+    fn next(&mut self) -> Option<Centroid> {
+        let Reverse((centroid, view, pos)) = self.heads.pop()?;
+        if let Some(&next) = self.views[view].get(pos + 1) {
+            self.heads.push(Reverse((next, view, pos + 1)));
+        }
+        Some(centroid)
+    }
+}
+
+/// Position of a forward scan over the centroids: the current centroid and the total
+/// weight of the centroids before it.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct QuantileCursor {
+    pub(crate) pos: usize,
+    pub(crate) t: f64,
+}
+
+/// The piecewise-linear model behind quantile and rank estimates, over sorted centroids
+/// with their total weight and the tracked extremes.
+#[derive(Debug, Clone)]
+pub(crate) struct Interpolation<'a> {
+    pub(crate) centroids: Cow<'a, [Centroid]>,
+    pub(crate) count: f64,
+    pub(crate) min: f64,
+    pub(crate) max: f64,
+}
+
+impl Interpolation<'_> {
+    /// Estimates quantile `q`, see [`TDigest::estimate_quantile`], resuming the scan at
+    /// `cursor`.
+    pub(crate) fn quantile(&self, q: f64, cursor: &mut QuantileCursor) -> f64 {
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+
+        if q <= 0.0 {
+            return self.min;
+        }
+        if q >= 1.0 {
+            return self.max;
+        }
+
+        let count_: f64 = self.count;
+        let rank: f64 = q * count_;
+
+        // Find the first centroid whose midpoint lies beyond `rank`.
+        let n = self.centroids.len();
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let cursor = &mut crate::simd::skip_to_rank(&self.centroids, cursor, rank);
+        while cursor.pos < n && cursor.t + self.centroids[cursor.pos].weight() / 2.0 <= rank {
+            cursor.t += self.centroids[cursor.pos].weight();
+            cursor.pos += 1;
+        }
+        let (pos, t) = (cursor.pos, cursor.t);
+        let prev = pos.checked_sub(1).map(|i| &self.centroids[i]);
+        interpolate_quantile(
+            rank,
+            t,
+            prev,
+            self.centroids.get(pos),
+            self.count,
+            self.min,
+            self.max,
+        )
+    }
+
+    /// Estimates the rank of `x`, see [`TDigest::rank`], resuming the scan at `cursor`.
+    pub(crate) fn rank(&self, x: f64, cursor: &mut QuantileCursor) -> f64 {
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+
+        let count_: f64 = self.count;
+        if x < self.min {
+            return 0.0;
+        }
+        if x >= self.max {
+            return count_;
+        }
+
+        // Find the first centroid whose mean lies beyond `x`.
+        let n = self.centroids.len();
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let cursor = &mut crate::simd::skip_to_value(&self.centroids, cursor, x);
+        while cursor.pos < n && x >= self.centroids[cursor.pos].mean() {
+            cursor.t += self.centroids[cursor.pos].weight();
+            cursor.pos += 1;
+        }
+        let (pos, t) = (cursor.pos, cursor.t);
+        let prev = pos.checked_sub(1).map(|i| &self.centroids[i]);
+        interpolate_rank(
+            x,
+            t,
+            prev,
+            self.centroids.get(pos),
+            self.count,
+            self.min,
+            self.max,
+        )
+    }
+}
+
+/// Interpolates the value at `rank` between `prev`, the last centroid whose midpoint lies
+/// at or below it, and `next`, the first beyond it, where `t` is the total weight up to
+/// and including `prev`. A missing neighbour is the `min` or `max` tail.
+fn interpolate_quantile(
+    rank: f64,
+    t: f64,
+    prev: Option<&Centroid>,
+    next: Option<&Centroid>,
+    count: f64,
+    min: f64,
+    max: f64,
+) -> f64 {
+    // Means are pinned into `[min, max]`, which rounding in a centroid's running mean
+    // could otherwise leave by an ulp, so the tails can never bend back and every
+    // estimate lies within the observed range.
+    let (lo_rank, lo_value) = match prev {
+        None => (0.0, min),
+        Some(prev) => (
+            t - prev.weight() / 2.0,
+            TDigest::clamp(prev.mean(), min, max),
+        ),
+    };
+    let (hi_rank, hi_value) = match next {
+        None => (count, max),
+        Some(next) => (
+            t + next.weight() / 2.0,
+            TDigest::clamp(next.mean(), min, max),
+        ),
+    };
+
+    if hi_rank <= lo_rank {
+        return hi_value;
+    }
+
+    let fraction = (rank - lo_rank) / (hi_rank - lo_rank);
+    TDigest::clamp(
+        lo_value + fraction * (hi_value - lo_value),
+        lo_value,
+        hi_value,
+    )
+}
+
+/// Interpolates the rank of `x` between `prev`, the last centroid whose mean is at most
+/// `x`, and `next`, the first beyond it, where `t` is the total weight up to and including
+/// `prev`. A missing neighbour is the `min` or `max` tail.
+fn interpolate_rank(
+    x: f64,
+    t: f64,
+    prev: Option<&Centroid>,
+    next: Option<&Centroid>,
+    count: f64,
+    min: f64,
+    max: f64,
+) -> f64 {
+    let (prev_rank, prev_mean) = match prev {
+        None => (0.0, min),
+        Some(prev) => (t - prev.weight() / 2.0, prev.mean()),
+    };
+    let (next_rank, next_mean) = match next {
+        None => (count, max),
+        Some(next) => (t + next.weight() / 2.0, next.mean()),
+    };
+
+    let fraction = (x - prev_mean) / (next_mean - prev_mean);
+    TDigest::clamp(
+        prev_rank + (next_rank - prev_rank) * fraction,
+        prev_rank.max(0.0),
+        next_rank.min(count),
+    )
+}
+
+impl TDigest {
+    /// Conservative bounds on the value at quantile `q`: the estimates at `q` minus and
+    /// plus its rank error, taken as the larger of
+    /// [`TDigest::theoretical_quantile_error`] and half the share of the centroid holding
+    /// rank `q * count`, whose values may lie anywhere within its half-weights on either
+    /// side. The bounds always enclose [`TDigest::estimate_quantile`] and lie within
+    /// `[min, max]`.
+    ///
+    /// Returns NaNs for an empty digest or a `q` outside `[0, 1]`.
+    pub fn quantile_bounds(&self, q: f64) -> (f64, f64) {
+        if self.is_empty() || !(0.0..=1.0).contains(&q) {
+            return (f64::NAN, f64::NAN);
+        }
+
+        let points = self.interpolation();
+        let rank = q * self.count;
+        let mut t = 0.0;
+        let holding = points
+            .centroids
+            .iter()
+            .find(|c| {
+                t += c.weight();
+                t >= rank
+            })
+            .or(points.centroids.last())
+            .map_or(0.0, |c| c.weight());
+        let error = self
+            .theoretical_quantile_error(q)
+            .max(holding / (2.0 * self.count));
+
+        let mut cursor = QuantileCursor::default();
+        let lower = points.quantile((q - error).max(0.0), &mut cursor);
+        let upper = points.quantile((q + error).min(1.0), &mut cursor);
+        (lower, upper)
+    }
+
+    /// To estimate the value located at `q` quantile
+    ///
+    /// Each centroid's weight is treated as centred on its mean, and the estimate is
+    /// interpolated linearly in rank between neighbouring centroid means. Below the first
+    /// and above the last centroid the tails are anchored at the tracked `min` and `max`,
+    /// so `q = 0` and `q = 1` return them exactly. A digest with a single centroid
+    /// interpolates from `min` through its mean to `max`; an empty digest returns NaN.
+    ///
+    /// Estimates are non-decreasing in `q` and always within `[min, max]`, and
+    /// [`TDigest::cdf`] inverts them: `cdf(estimate_quantile(q))` is `q` up to rounding,
+    /// except that a point mass, e.g. many equal values, covers a range of quantiles.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.interpolation()
+            .quantile(q, &mut QuantileCursor::default())
+    }
+
+    /// The model queries interpolate in, over [`TDigest::view`]. Scans resumed with a
+    /// [`QuantileCursor`] at non-decreasing quantiles or values share a single pass.
+    pub(crate) fn interpolation(&self) -> Interpolation<'_> {
+        Interpolation {
+            centroids: self.view(),
+            count: self.count,
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// Whether the quantile curves of `self` and `other` agree within `tol`: the estimates
+    /// at the quantiles 0, 0.01, ..., 1 differ by at most `tol`, in the units of the
+    /// values. Unlike `==` this ignores configuration and how the values are split into
+    /// centroids. Two empty digests are approximately equal, and an empty and a
+    /// non-empty one are not.
+    pub fn approx_eq(&self, other: &TDigest, tol: f64) -> bool {
+        match (self.is_empty(), other.is_empty()) {
+            (true, true) => return true,
+            (false, false) => {}
+            _ => return false,
+        }
+        let qs: Vec<f64> = (0..=100).map(|i| f64::from(i) / 100.0).collect();
+        self.estimate_quantiles(&qs)
+            .into_iter()
+            .zip(other.estimate_quantiles(&qs))
+            .all(|(a, b)| (a - b).abs() <= tol)
+    }
+
+    /// Estimates every quantile of the sorted `qs` in a single pass over the centroids,
+    /// e.g. `estimate_quantiles(&[0.5, 0.9, 0.99])` for a dashboard row. Each answer is
+    /// identical to [`TDigest::estimate_quantile`].
+    pub fn estimate_quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        debug_assert!(qs.windows(2).all(|w| w[0] <= w[1]), "qs must be sorted");
+
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        qs.iter()
+            .map(|&q| points.quantile(q, &mut cursor))
+            .collect()
+    }
+
+    /// Estimates the median, i.e. `estimate_quantile(0.5)`.
+    pub fn median(&self) -> f64 {
+        self.estimate_quantile(0.5)
+    }
+
+    /// Estimates the `p`-th percentile on the 0 to 100 scale of numpy and pandas, i.e.
+    /// `estimate_quantile(p / 100.0)`.
+    pub fn percentile(&self, p: f64) -> f64 {
+        self.estimate_quantile(p / 100.0)
+    }
+
+    /// Estimates the interquartile range, the distance between the 0.25 and 0.75
+    /// quantiles, in a single pass. Returns NaN for an empty digest.
+    pub fn iqr(&self) -> f64 {
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        let q1 = points.quantile(0.25, &mut cursor);
+        points.quantile(0.75, &mut cursor) - q1
+    }
+
+    /// Per-quantile differences `self.estimate_quantile(q) - other.estimate_quantile(q)`
+    /// for each of the sorted `qs`, e.g. how much slower a treatment is than its control
+    /// at every percentile. Makes one pass over each digest.
+    pub fn quantile_deltas(&self, other: &TDigest, qs: &[f64]) -> Vec<f64> {
+        debug_assert!(qs.windows(2).all(|w| w[0] <= w[1]), "qs must be sorted");
+
+        let (our_points, their_points) = (self.interpolation(), other.interpolation());
+        let mut ours = QuantileCursor::default();
+        let mut theirs = QuantileCursor::default();
+        qs.iter()
+            .map(|&q| our_points.quantile(q, &mut ours) - their_points.quantile(q, &mut theirs))
+            .collect()
+    }
+
+    /// Like [`TDigest::estimate_quantile`], but returns the true (nearest-rank) order
+    /// statistic without interpolation while the digest still keeps every point as its own
+    /// centroid, which is the case for small inputs. Larger digests fall back to the
+    /// interpolated estimate.
+    pub fn estimate_quantile_exact_if_small(&self, q: f64) -> f64 {
+        if self.is_empty() || !self.is_exact() {
+            return self.estimate_quantile(q);
+        }
+
+        let centroids = self.view();
+        let n = centroids.len();
+        let rank = (q * n as f64).ceil() as usize;
+        centroids[rank.clamp(1, n) - 1].mean()
+    }
+
+    /// Whether every centroid is a single raw value, which holds for digests of up to
+    /// `max_size` unweighted values since compression leaves those alone. Quantiles of an
+    /// exact digest are then sample quantiles rather than approximations:
+    /// `estimate_quantile` interpolates between the order statistics and
+    /// `estimate_quantile_exact_if_small` picks the nearest-rank one.
+    pub fn is_exact(&self) -> bool {
+        self.centroids
+            .iter()
+            .chain(self.buffer.iter())
+            .all(|c| c.weight() == 1.0)
+    }
+
+    /// Differences between the estimates at consecutive entries of the sorted `qs`, i.e.
+    /// how far the value moves per quantile step. Small spreads mark dense regions of
+    /// the distribution and large ones sparse regions.
+    ///
+    /// Returns `qs.len() - 1` entries, or none if fewer than two quantiles are given.
+    pub fn quantile_spread(&self, qs: &[f64]) -> Vec<f64> {
+        let values = self.estimate_quantiles(qs);
+        values.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    /// `n` evenly spaced `(q, estimate_quantile(q))` pairs from `q = 0` to `q = 1`, for
+    /// plotting, estimated in a single pass. A single point is the median. The values are
+    /// NaN for an empty digest.
+    pub fn quantile_curve(&self, n: usize) -> Vec<(f64, f64)> {
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        evenly_spaced(0.0, 1.0, n)
+            .map(|q| (q, points.quantile(q, &mut cursor)))
+            .collect()
+    }
+
+    /// `n` evenly spaced `(x, cdf(x))` pairs from `min` to `max`, for plotting, estimated
+    /// in a single pass. A single point is the midpoint of the range. Empty for an empty
+    /// digest, which has no range.
+    pub fn cdf_curve(&self, n: usize) -> Vec<(f64, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        let count = self.count();
+        evenly_spaced(self.min, self.max, n)
+            .map(|x| (x, points.rank(x, &mut cursor) / count))
+            .collect()
+    }
+
+    /// Estimates quantile `q` of the values of all `digests` together, as
+    /// [`TDigest::estimate_quantile`] would on their union, without merging them: the
+    /// centroids of all digests are walked in one lazy k-way pass, and only as far as
+    /// `q`, and are interpolated as they are rather than compressed first. This makes a
+    /// one-off query over many shards cheaper than [`TDigest::merge_digests`], and at
+    /// least as accurate. Returns NaN if every digest is empty.
+    pub fn quantile_over<'a>(digests: impl IntoIterator<Item = &'a TDigest>, q: f64) -> f64 {
+        let mut merged = MergedView::new(digests);
+        if merged.views.is_empty() {
+            return f64::NAN;
+        }
+        if q <= 0.0 {
+            return merged.min;
+        }
+        if q >= 1.0 {
+            return merged.max;
+        }
+
+        let (count, min, max) = (merged.count, merged.min, merged.max);
+        let rank = q * count;
+        let (mut t, mut prev) = (0.0, None);
+        let next = loop {
+            match merged.next() {
+                Some(c) if t + c.weight() / 2.0 <= rank => {
+                    t += c.weight();
+                    prev = Some(c);
+                }
+                next => break next,
+            }
+        };
+        interpolate_quantile(rank, t, prev.as_ref(), next.as_ref(), count, min, max)
+    }
+
+    /// Estimates the fraction of the values of all `digests` together that are less than
+    /// or equal to `x`, as [`TDigest::cdf`] would on their union, in a lazy pass like
+    /// [`TDigest::quantile_over`]. Returns NaN if every digest is empty.
+    pub fn cdf_over<'a>(digests: impl IntoIterator<Item = &'a TDigest>, x: f64) -> f64 {
+        let mut merged = MergedView::new(digests);
+        if merged.views.is_empty() {
+            return f64::NAN;
+        }
+        let (count, min, max) = (merged.count, merged.min, merged.max);
+        if x < min {
+            return 0.0;
+        }
+        if x >= max {
+            return 1.0;
+        }
+
+        let (mut t, mut prev) = (0.0, None);
+        let next = loop {
+            match merged.next() {
+                Some(c) if x >= c.mean() => {
+                    t += c.weight();
+                    prev = Some(c);
+                }
+                next => break next,
+            }
+        };
+        interpolate_rank(x, t, prev.as_ref(), next.as_ref(), count, min, max) / count
+    }
+
+    /// To estimate the fraction of values less than or equal to `x`
+    ///
+    /// This is `rank(x) / count()`, see [`TDigest::rank`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.rank(x) / self.count()
+    }
+
+    /// To estimate the number of values less than or equal to `x`
+    ///
+    /// Uses the same piecewise-linear model as [`TDigest::estimate_quantile`], with each
+    /// centroid's weight centred on its mean and the tails running out to the tracked
+    /// `min`/`max`, so `cdf` and `estimate_quantile` are approximate inverses. Returns NaN
+    /// for an empty digest.
+    pub fn rank(&self, x: f64) -> f64 {
+        self.interpolation().rank(x, &mut QuantileCursor::default())
+    }
+
+    /// To estimate the number of values in `[a, b)`
+    ///
+    /// Interpolates like [`TDigest::rank`] in a single pass over the centroids, but is
+    /// exact at the ends: a range starting at or below `min` counts from zero and one
+    /// ending above `max` counts up to `count`, so a range covering all values returns
+    /// `count` exactly. Returns 0 for an empty range and NaN for an empty digest.
+    pub fn count_between(&self, a: f64, b: f64) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        if a.is_nan() || b.is_nan() || a >= b {
+            return 0.0;
+        }
+
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        let lo = if a <= self.min() {
+            0.0
+        } else {
+            points.rank(a, &mut cursor)
+        };
+        let hi = if b > self.max() {
+            self.count()
+        } else {
+            points.rank(b, &mut cursor)
+        };
+        (hi - lo).max(0.0)
+    }
+
+    /// Fraction of the values in `[a, b)`, i.e. `count_between(a, b) / count()`.
+    pub fn fraction_between(&self, a: f64, b: f64) -> f64 {
+        self.count_between(a, b) / self.count()
+    }
+
+    /// The Kolmogorov-Smirnov distance to `other`, the largest absolute difference between
+    /// the two CDFs: 0 for identical distributions and 1 for ones that do not overlap.
+    ///
+    /// Both CDFs are piecewise linear between their `min`, centroid means and `max`, so the
+    /// largest difference lies at one of those points; the two lists are co-iterated in
+    /// ascending order and visited once. Combine with [`TDigest::quantile_deltas`] to see
+    /// where a distribution shifted. Returns NaN if either digest is empty.
+    pub fn ks_distance(&self, other: &TDigest) -> f64 {
+        if self.is_empty() || other.is_empty() {
+            return f64::NAN;
+        }
+
+        let (ours, theirs) = (self.interpolation(), other.interpolation());
+        let mut a = core::iter::once(ours.min)
+            .chain(ours.centroids.iter().map(|c| c.mean))
+            .chain(core::iter::once(ours.max))
+            .peekable();
+        let mut b = core::iter::once(theirs.min)
+            .chain(theirs.centroids.iter().map(|c| c.mean))
+            .chain(core::iter::once(theirs.max))
+            .peekable();
+
+        let (mut our_cursor, mut their_cursor) = Default::default();
+        let mut distance = 0.0f64;
+        loop {
+            let x = match (a.peek(), b.peek()) {
+                (Some(&x), Some(&y)) if x <= y => a.next(),
+                (Some(_), Some(_)) => b.next(),
+                _ => a.next().or_else(|| b.next()),
+            };
+            let Some(x) = x else { break };
+
+            let d = ours.rank(x, &mut our_cursor) / ours.count
+                - theirs.rank(x, &mut their_cursor) / theirs.count;
+            distance = distance.max(d.abs());
+        }
+        distance
+    }
+
+    /// Approximates the 1-Wasserstein (earth mover's) distance to `other`: the integral
+    /// over `q` in `[0, 1]` of the absolute difference between the two quantile functions,
+    /// each taken as the step function of its centroid means.
+    ///
+    /// Unlike rank-based distances this is sensitive to how far values moved. Returns NaN
+    /// if either digest is empty.
+    pub fn wasserstein_distance(&self, other: &TDigest) -> f64 {
+        if self.is_empty() || other.is_empty() {
+            return f64::NAN;
+        }
+
+        let (ours, theirs) = (self.view(), other.view());
+        let mut a = ours.iter();
+        let mut b = theirs.iter();
+        let (mut ca, mut cb) = (a.next().unwrap(), b.next().unwrap());
+        // Upper quantile boundaries of the current centroid of each digest.
+        let mut qa = ca.weight() / self.count();
+        let mut qb = cb.weight() / other.count();
+
+        let mut q = 0.0;
+        let mut distance = 0.0;
+        loop {
+            let next = qa.min(qb);
+            distance += (ca.mean() - cb.mean()).abs() * (next - q);
+            q = next;
+
+            if qa <= q {
+                match a.next() {
+                    Some(c) => {
+                        ca = c;
+                        qa += c.weight() / self.count();
+                    }
+                    None => break,
+                }
+            }
+            if qb <= q {
+                match b.next() {
+                    Some(c) => {
+                        cb = c;
+                        qb += c.weight() / other.count();
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        distance
+    }
+
+    /// Finds the value at which the CDFs of `self` and `other` cross, i.e. where one
+    /// distribution overtakes the other.
+    ///
+    /// Both CDFs are piecewise linear between centroid means, so scanning the union of
+    /// the means over the overlapping range locates the crossing exactly. Returns `None`
+    /// if either digest is empty or the CDFs never change order in the observed range.
+    pub fn crossover_quantile(&self, other: &TDigest) -> Option<f64> {
+        if self.is_empty() || other.is_empty() {
+            return None;
+        }
+
+        let lo = self.min().max(other.min());
+        let hi = self.max().min(other.max());
+        if lo >= hi {
+            return None;
+        }
+
+        let (ours, theirs) = (self.interpolation(), other.interpolation());
+        let mut points: Vec<f64> = ours
+            .centroids
+            .iter()
+            .chain(theirs.centroids.iter())
+            .map(|c| c.mean)
+            .filter(|&m| m > lo && m < hi)
+            .collect();
+        points.push(lo);
+        points.push(hi);
+        points.sort_by(f64::total_cmp);
+        points.dedup();
+
+        // The points ascend, so each digest's scan resumes where the last one stopped.
+        let (mut our_cursor, mut their_cursor) = Default::default();
+        let mut diff = |x: f64| {
+            ours.rank(x, &mut our_cursor) / ours.count
+                - theirs.rank(x, &mut their_cursor) / theirs.count
+        };
+
+        // The last point with a non-zero difference, and the first zero seen after it.
+        let mut last: Option<(f64, f64)> = None;
+        let mut first_zero: Option<f64> = None;
+
+        for x in points {
+            let d = diff(x);
+            if d == 0.0 {
+                first_zero = first_zero.or(Some(x));
+                continue;
+            }
+
+            if let Some((x0, d0)) = last {
+                if d0.signum() != d.signum() {
+                    return Some(first_zero.unwrap_or(x0 + (x - x0) * d0 / (d0 - d)));
+                }
+            }
+
+            last = Some((x, d));
+            first_zero = None;
+        }
+
+        None
+    }
+}
+
+impl TDigest {
+    /// Counts the centroids whose mean falls in each range `[boundaries[i], boundaries[i + 1])`
+    /// of the sorted `boundaries`; the last range also includes its upper bound. Useful to
+    /// check where the scale function concentrates centroids.
+    ///
+    /// Returns `boundaries.len() - 1` counts, or none if fewer than two boundaries are given.
+    pub fn centroid_count_in_ranges(&self, boundaries: &[f64]) -> Vec<usize> {
+        let n_ranges = boundaries.len().saturating_sub(1);
+        let mut counts = vec![0; n_ranges];
+
+        for centroid in self.centroids.iter() {
+            let mean = centroid.mean();
+            let in_range = |i: usize| {
+                mean >= boundaries[i]
+                    && (mean < boundaries[i + 1]
+                        || (i + 1 == n_ranges && mean == boundaries[i + 1]))
+            };
+            if let Some(i) = (0..n_ranges).find(|&i| in_range(i)) {
+                counts[i] += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Estimated number of values in each bucket of a histogram with the sorted upper
+    /// `bounds`, Prometheus-style but not cumulative: bucket `i` covers
+    /// `(bounds[i - 1], bounds[i]]`, with a final bucket above the last bound, so there
+    /// are `bounds.len() + 1` counts adding up to `count`. Weight is split between
+    /// buckets by the same interpolation as [`TDigest::rank`], in one pass over the
+    /// centroids.
+    pub fn to_histogram(&self, bounds: &[f64]) -> Vec<f64> {
+        debug_assert!(
+            bounds.windows(2).all(|w| w[0] <= w[1]),
+            "bounds must be sorted"
+        );
+        if self.is_empty() {
+            return vec![0.0; bounds.len() + 1];
+        }
+
+        let points = self.interpolation();
+        let mut cursor = QuantileCursor::default();
+        let mut below = 0.0;
+        let mut counts: Vec<f64> = bounds
+            .iter()
+            .map(|&bound| {
+                let rank = points.rank(bound, &mut cursor);
+                let count = rank - below;
+                below = rank;
+                count
+            })
+            .collect();
+        counts.push(self.count() - below);
+        counts
+    }
+
+    /// Draws the approximate density as `height` lines of `width` unicode block
+    /// characters, for a quick look in a terminal. Column `i` covers the `i`-th of `width`
+    /// equal slices of `[min, max]`, with its weight estimated like
+    /// [`TDigest::to_histogram`], and the fullest column reaches the top. A `height` of 1
+    /// gives a sparkline such as `▁▂▅█▅▂▁`.
+    ///
+    /// Returns an empty string for an empty digest or a zero `width` or `height`.
+    pub fn render_ascii(&self, width: usize, height: usize) -> String {
+        const LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        if self.is_empty() || width == 0 || height == 0 {
+            return String::new();
+        }
+
+        let (min, max) = (self.min(), self.max());
+        let mut columns = if max > min {
+            let step = (max - min) / width as f64;
+            let bounds: Vec<f64> = (1..width).map(|i| min + step * i as f64).collect();
+            self.to_histogram(&bounds)
+        } else {
+            vec![self.count()]
+        };
+        columns.resize(width, 0.0);
+
+        let fullest = columns.iter().copied().fold(0.0, f64::max);
+        // Column heights in eighths of a line.
+        let eighths: Vec<usize> = columns
+            .iter()
+            .map(|&c| (c / fullest * (8 * height) as f64).round() as usize)
+            .collect();
+
+        let mut out = String::with_capacity(height * (width * 3 + 1));
+        for row in (0..height).rev() {
+            for &e in eighths.iter() {
+                out.push(LEVELS[e.saturating_sub(8 * row).min(8)]);
+            }
+            if row > 0 {
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// `n` evenly spaced points from `lo` to `hi`, both included, or just their midpoint for
+/// `n = 1`.
+fn evenly_spaced(lo: f64, hi: f64, n: usize) -> impl Iterator<Item = f64> {
+    let step = (hi - lo) / n.saturating_sub(1).max(1) as f64;
+    (0..n).map(move |i| match (n, i) {
+        (1, _) => lo + (hi - lo) / 2.0,
+        _ if i + 1 == n => hi,
+        _ => lo + step * i as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+
+    #[test]
+    fn test_cdf() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=100).map(f64::from).collect());
+        assert_eq!(t.cdf(0.0), 0.0);
+        assert_eq!(t.cdf(100.0), 1.0);
+        assert!((t.cdf(50.0) - 0.5).abs() < 0.01);
+        assert!(TDigest::default().cdf(1.0).is_nan());
+    }
+
+    #[test]
+    fn test_crossover_quantile() {
+        let wide = TDigest::new_with_size(100).merge_unsorted((0..=100).map(f64::from).collect());
+        let narrow = TDigest::new_with_size(100)
+            .merge_unsorted((0..=100).map(|i| 45.0 + f64::from(i) / 5.0).collect());
+
+        // Analytically the CDFs x / 100 and (x - 45) / 20 meet at x = 56.25.
+        let x = wide.crossover_quantile(&narrow).unwrap();
+        assert!((x - 56.25).abs() < 1.0);
+        assert!((wide.cdf(x) - narrow.cdf(x)).abs() < 1e-9);
+
+        let shifted = TDigest::new_with_size(100)
+            .merge_unsorted((0..=100).map(|i| f64::from(i) + 10.0).collect());
+        assert_eq!(wide.crossover_quantile(&shifted), None);
+    }
+
+    #[test]
+    fn test_quantile_spread() {
+        let qs = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+        let uniform =
+            TDigest::new_with_size(100).merge_unsorted((0..10_000).map(f64::from).collect());
+        let spreads = uniform.quantile_spread(&qs);
+        assert_eq!(spreads.len(), qs.len() - 1);
+        for spread in &spreads {
+            assert!((spread - 2000.0).abs() < 50.0);
+        }
+
+        let skewed = TDigest::new_with_size(100)
+            .merge_unsorted((0..10_000).map(|i| f64::from(i).powi(3)).collect());
+        let spreads = skewed.quantile_spread(&qs);
+        assert!(spreads.windows(2).all(|w| w[1] > w[0]));
+        assert!(spreads[3] > 10.0 * spreads[0]);
+
+        assert!(uniform.quantile_spread(&[0.5]).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_quantile_exact_if_small() {
+        let t = TDigest::new_with_size(100).merge_unsorted(vec![40.0, 10.0, 30.0, 20.0]);
+        assert_eq!(t.estimate_quantile_exact_if_small(0.33), 20.0);
+        assert_eq!(t.estimate_quantile_exact_if_small(0.0), 10.0);
+        assert_eq!(t.estimate_quantile_exact_if_small(0.5), 20.0);
+        assert_eq!(t.estimate_quantile_exact_if_small(0.51), 30.0);
+        assert_eq!(t.estimate_quantile_exact_if_small(1.0), 40.0);
+
+        let large = TDigest::new_with_size(10).merge_unsorted((0..1000).map(f64::from).collect());
+        assert_eq!(
+            large.estimate_quantile_exact_if_small(0.33),
+            large.estimate_quantile(0.33)
+        );
+    }
+
+    #[test]
+    fn test_centroid_count_in_ranges() {
+        let t = TDigest::new_with_size(100).merge_unsorted((0..=10_000).map(f64::from).collect());
+        let boundaries: Vec<f64> = (0..=10).map(|i| f64::from(i) * 1000.0).collect();
+        let counts = t.centroid_count_in_ranges(&boundaries);
+
+        assert_eq!(counts.len(), 10);
+        assert_eq!(counts.iter().sum::<usize>(), t.centroids.len());
+        assert!(counts[0] > counts[4] && counts[0] > counts[5]);
+        assert!(counts[9] > counts[4] && counts[9] > counts[5]);
+
+        assert!(t.centroid_count_in_ranges(&[1.0]).is_empty());
+    }
+
+    #[test]
+    fn test_wasserstein_distance() {
+        let values: Vec<f64> = (0..10_000).map(f64::from).collect();
+        let t = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        assert_eq!(t.wasserstein_distance(&t.clone()), 0.0);
+
+        let shifted =
+            TDigest::new_with_size(100).merge_unsorted(values.iter().map(|v| v + 25.0).collect());
+        assert!((t.wasserstein_distance(&shifted) - 25.0).abs() < 1e-6);
+        assert!((shifted.wasserstein_distance(&t) - 25.0).abs() < 1e-6);
+
+        let coarse = TDigest::new_with_size(20).merge_unsorted(values);
+        let distance = t.wasserstein_distance(&coarse);
+        assert!(distance > 0.0 && distance < 500.0);
+
+        assert!(t.wasserstein_distance(&TDigest::default()).is_nan());
+    }
+
+    #[test]
+    fn test_ks_distance() {
+        let a = TDigest::new_with_size(100).merge_unsorted(lcg_values(50_000, 31));
+        let b = TDigest::new_with_size(100).merge_unsorted(lcg_values(50_000, 32));
+        assert_eq!(a.ks_distance(&a), 0.0);
+        assert!(a.ks_distance(&b) < 0.01);
+
+        // Uniform on [0, 1) against uniform on [0.2, 1.2).
+        let shifted = TDigest::new_with_size(100)
+            .merge_unsorted(lcg_values(50_000, 33).iter().map(|v| v + 0.2).collect());
+        assert!((a.ks_distance(&shifted) - 0.2).abs() < 0.01);
+        assert_eq!(a.ks_distance(&shifted), shifted.ks_distance(&a));
+
+        let far = TDigest::new_with_size(100).merge_sorted(vec![5.0, 6.0]);
+        assert_eq!(a.ks_distance(&far), 1.0);
+        assert!(a.ks_distance(&TDigest::new_with_size(100)).is_nan());
+    }
+
+    #[test]
+    fn test_quantile_deltas() {
+        let qs = [0.01, 0.1, 0.5, 0.9, 0.99];
+        let values: Vec<f64> = (0..10_000).map(f64::from).collect();
+        let control = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        let treatment =
+            TDigest::new_with_size(100).merge_unsorted(values.iter().map(|v| v + 7.0).collect());
+
+        assert!(control
+            .quantile_deltas(&control, &qs)
+            .iter()
+            .all(|&d| d == 0.0));
+
+        let deltas = treatment.quantile_deltas(&control, &qs);
+        assert_eq!(deltas.len(), qs.len());
+        for (delta, &q) in deltas.iter().zip(qs.iter()) {
+            assert!((delta - 7.0).abs() < 1e-6);
+            assert_eq!(
+                *delta,
+                treatment.estimate_quantile(q) - control.estimate_quantile(q)
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_quantile() {
+        let values: Vec<f64> = (1..=100_000).map(f64::from).collect();
+        let t = TDigest::new_with_size(100).merge_unsorted(values.clone());
+
+        assert_eq!(t.estimate_quantile(0.0), 1.0);
+        assert_eq!(t.estimate_quantile(1.0), 100_000.0);
+        for &q in &[0.001, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999] {
+            let exact = values[((q * values.len() as f64) as usize).min(values.len() - 1)];
+            let estimate = t.estimate_quantile(q);
+            assert!(
+                (estimate - exact).abs() / exact < 0.01,
+                "q={} estimate={} exact={}",
+                q,
+                estimate,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_quantile_edge_cases() {
+        assert!(TDigest::default().estimate_quantile(0.5).is_nan());
+
+        // A single heavy centroid interpolates from min through its mean to max.
+        let t = TDigest::new(vec![Centroid::new(5.0, 10.0)], 100, 50.0, 10.0, 9.0, 1.0);
+        assert_eq!(t.estimate_quantile(0.0), 1.0);
+        assert_eq!(t.estimate_quantile(0.25), 3.0);
+        assert_eq!(t.estimate_quantile(0.5), 5.0);
+        assert_eq!(t.estimate_quantile(0.75), 7.0);
+        assert_eq!(t.estimate_quantile(1.0), 9.0);
+
+        // Tails are anchored at the tracked extremes, not at the outer centroid means.
+        let t = TDigest::new(
+            vec![Centroid::new(2.0, 2.0), Centroid::new(8.0, 2.0)],
+            100,
+            20.0,
+            4.0,
+            10.0,
+            0.0,
+        );
+        assert_eq!(t.estimate_quantile(0.125), 1.0);
+        assert_eq!(t.estimate_quantile(0.5), 5.0);
+        assert_eq!(t.estimate_quantile(0.875), 9.0);
+
+        assert_eq!(t.estimate_quantile(-1.0), 0.0);
+        assert_eq!(t.estimate_quantile(2.0), 10.0);
+    }
+
+    #[test]
+    fn test_rank() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=1000).map(f64::from).collect());
+        assert_eq!(t.rank(0.0), 0.0);
+        assert_eq!(t.rank(1000.0), 1000.0);
+        assert!((t.rank(250.0) - 250.0).abs() < 5.0);
+        assert!(TDigest::default().rank(1.0).is_nan());
+    }
+
+    #[test]
+    fn test_cdf_inverts_estimate_quantile() {
+        let t = TDigest::new_with_size(100)
+            .merge_unsorted((0..10_000).map(|i| f64::from(i).sqrt()).collect());
+        for i in 1..100 {
+            let q = f64::from(i) / 100.0;
+            let x = t.estimate_quantile(q);
+            assert!((t.cdf(x) - q).abs() < 1e-9, "q={} cdf={}", q, t.cdf(x));
+        }
+    }
+
+    /// Weighted inputs that stress interpolation: sorted and reversed runs, ties, extreme
+    /// and tiny magnitudes, and weights spanning nine orders of magnitude.
+    fn adversarial_inputs() -> Vec<(&'static str, Vec<(f64, f64)>)> {
+        let u = lcg_values(20_000, 29);
+        let unit = |values: Vec<f64>| values.into_iter().map(|v| (v, 1.0)).collect();
+        vec![
+            ("uniform", unit(u.clone())),
+            ("ascending", unit((0..20_000).map(f64::from).collect())),
+            (
+                "descending",
+                unit((0..20_000).rev().map(f64::from).collect()),
+            ),
+            ("constant", unit(vec![3.5; 5_000])),
+            (
+                "two values",
+                unit(u.iter().map(|&v| (v * 2.0).floor()).collect()),
+            ),
+            (
+                "huge",
+                unit(u.iter().map(|&v| (2.0 * v - 1.0) * 1e300).collect()),
+            ),
+            ("subnormal", unit(u.iter().map(|&v| v * 1e-310).collect())),
+            (
+                "clustered",
+                unit(u.iter().map(|&v| 1.0 + v * 1e-12).collect()),
+            ),
+            (
+                "alternating",
+                unit(
+                    (0..20_000)
+                        .map(|i| if i % 2 == 0 { -1e6 } else { f64::from(i) })
+                        .collect(),
+                ),
+            ),
+            (
+                "weighted",
+                u.iter()
+                    .zip(lcg_values(20_000, 31))
+                    .map(|(&v, w)| (v, 10f64.powf(9.0 * w - 3.0)))
+                    .collect(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_quantile_invariants_on_adversarial_inputs() {
+        let qs: Vec<f64> = (0..=500).map(|i| f64::from(i) / 500.0).collect();
+        for (name, input) in adversarial_inputs() {
+            for max_size in [10, 100] {
+                let mut t = TDigest::new_with_size(max_size);
+                t.set_buffer_size(3_000);
+                for &(v, w) in input.iter() {
+                    t.insert_weighted(v, w);
+                }
+                // Once with values still buffered, once compressed; the pointwise checks
+                // only on the compressed digest, as each query copies the buffer.
+                for flushed in [false, true] {
+                    if flushed {
+                        t.flush();
+                    }
+                    let estimates = t.estimate_quantiles(&qs);
+                    assert!(
+                        estimates.windows(2).all(|w| w[0] <= w[1]),
+                        "{}: not monotone",
+                        name
+                    );
+                    assert!(
+                        estimates.iter().all(|&x| t.min() <= x && x <= t.max()),
+                        "{}: outside [min, max]",
+                        name
+                    );
+                    assert_eq!((estimates[0], estimates[500]), (t.min(), t.max()));
+                    if !flushed {
+                        continue;
+                    }
+
+                    let single: Vec<f64> = qs.iter().map(|&q| t.estimate_quantile(q)).collect();
+                    assert_eq!(estimates, single, "{}", name);
+                    // cdf inverts quantile up to the resolution of f64: `q` lies between
+                    // the cdf of the neighbouring floats, which also spans point masses.
+                    for (&q, &x) in qs.iter().zip(estimates.iter()) {
+                        let (below, at) = (t.cdf(x.next_down()), t.cdf(x.next_up()));
+                        assert!(
+                            below - 1e-9 <= q && q <= at + 1e-9,
+                            "{}: q={} cdf in [{}, {}]",
+                            name,
+                            q,
+                            below,
+                            at
+                        );
+                        assert!((0.0..=1.0).contains(&at), "{}: cdf={}", name, at);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_preserves_count_and_sum_exactly() {
+        for (name, input) in adversarial_inputs() {
+            let (left, right) = input.split_at(input.len() / 3);
+            let mut a = TDigest::new_with_size(50);
+            let mut b = TDigest::new_with_size(100);
+            b.set_buffer_size(100_000);
+            left.iter().for_each(|&(v, w)| a.insert_weighted(v, w));
+            right.iter().for_each(|&(v, w)| b.insert_weighted(v, w));
+            let expected = (a.count() + b.count(), a.sum() + b.sum());
+
+            let merged = TDigest::merge_digests(vec![a.clone(), b.clone()]);
+            assert_eq!((merged.count(), merged.sum()), expected, "{}", name);
+
+            let mut drained = b.clone();
+            let mut dest = a.clone();
+            drained.drain_into(&mut dest);
+            assert_eq!((dest.count(), dest.sum()), expected, "{}", name);
+
+            a.merge(&b);
+            assert_eq!((a.count(), a.sum()), expected, "{}", name);
+            a.flush();
+            assert_eq!((a.count(), a.sum()), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_quantile_bounds_enclose_true_quantiles() {
+        let mut values = lcg_values(100_000, 21);
+        values.iter_mut().for_each(|v| *v = v.powi(3));
+        let t = TDigest::new_with_size(100).merge_unsorted(values.clone());
+        values.sort_by(f64::total_cmp);
+
+        for q in [
+            0.0, 0.001, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999, 1.0,
+        ] {
+            let (lower, upper) = t.quantile_bounds(q);
+            let exact = values[((q * values.len() as f64) as usize).min(values.len() - 1)];
+            assert!(
+                lower <= exact && exact <= upper,
+                "{}: {} {} {}",
+                q,
+                lower,
+                exact,
+                upper
+            );
+            assert!(lower <= t.estimate_quantile(q) && t.estimate_quantile(q) <= upper);
+            assert!(t.min() <= lower && upper <= t.max());
+            assert!(t.theoretical_quantile_error(q) <= t.max_rank_error());
+        }
+
+        let mut coarse = t.clone();
+        coarse.coalesce_below_weight(10_000.0);
+        assert!(coarse.max_rank_error() > t.max_rank_error());
+
+        let empty = TDigest::new_with_size(100);
+        assert!(empty.quantile_bounds(0.5).0.is_nan());
+        assert!(t.quantile_bounds(1.5).1.is_nan());
+    }
+
+    #[test]
+    fn test_estimate_quantiles() {
+        let t = TDigest::new_with_size(100).merge_unsorted(lcg_values(50_000, 7));
+        let qs = [0.0, 0.001, 0.25, 0.5, 0.5, 0.9, 0.99, 0.999, 1.0];
+        let batched = t.estimate_quantiles(&qs);
+        let single: Vec<f64> = qs.iter().map(|&q| t.estimate_quantile(q)).collect();
+        assert_eq!(batched, single);
+
+        assert!(t.estimate_quantiles(&[]).is_empty());
+        assert!(TDigest::default().estimate_quantiles(&[0.5])[0].is_nan());
+    }
+
+    #[test]
+    fn test_median_percentile_and_iqr() {
+        let t: TDigest = (1..=100).map(f64::from).collect();
+        assert_eq!(t.median(), t.estimate_quantile(0.5));
+        assert_eq!(t.percentile(95.0), t.estimate_quantile(0.95));
+        assert_eq!(t.percentile(100.0), 100.0);
+        assert_eq!(
+            t.iqr(),
+            t.estimate_quantile(0.75) - t.estimate_quantile(0.25)
+        );
+        assert!((t.iqr() - 50.0).abs() < 1.0);
+        assert!(TDigest::default().iqr().is_nan());
+    }
+
+    #[test]
+    fn test_count_between() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=100).map(f64::from).collect());
+        assert_eq!(t.count_between(0.0, 1000.0), 100.0);
+        assert_eq!(t.count_between(1.0, 100.5), 100.0);
+        assert_eq!(t.count_between(200.0, 300.0), 0.0);
+        assert_eq!(t.count_between(5.0, 5.0), 0.0);
+        assert_eq!(t.count_between(60.0, 20.0), 0.0);
+        assert_eq!(t.count_between(20.0, 60.0), t.rank(60.0) - t.rank(20.0));
+        assert!((t.fraction_between(20.0, 60.0) - 0.4).abs() < 1e-9);
+
+        let constant = TDigest::new_with_size(100).merge_unsorted(vec![7.0; 10]);
+        assert_eq!(constant.count_between(7.0, 8.0), 10.0);
+        assert!(TDigest::default().count_between(0.0, 1.0).is_nan());
+
+        let large = TDigest::new_with_size(100).merge_unsorted(lcg_values(50_000, 9));
+        let expected = large.count_between(0.25, 0.75);
+        assert!((expected - 25_000.0).abs() < 250.0);
+    }
+
+    #[test]
+    fn test_exact_mode_for_small_counts() {
+        let values = lcg_values(50, 8);
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let t = TDigest::new_with_size(50).merge_unsorted(values.clone());
+        assert!(t.is_exact());
+        let means: Vec<f64> = t.centroids().map(|c| c.mean()).collect();
+        assert_eq!(means, sorted);
+        // Halfway between the 25th and 26th order statistics.
+        assert_eq!(t.estimate_quantile(0.5), (sorted[24] + sorted[25]) / 2.0);
+        assert_eq!(t.estimate_quantile_exact_if_small(0.5), sorted[24]);
+
+        // Also when the values arrive in batches.
+        let mut batched = TDigest::new_with_size(50).merge_unsorted(values[..20].to_vec());
+        batched = batched.merge_unsorted(values[20..].to_vec());
+        assert_eq!(batched, t);
+
+        // One more value crosses the threshold and switches to compression.
+        let t = t.merge_unsorted(vec![0.5]);
+        assert!(!t.is_exact());
+        assert!(t.len() < 51);
+        assert_eq!(t.count(), 51.0);
+    }
+
+    #[test]
+    fn test_quantile_and_cdf_curves() {
+        let t = TDigest::new_with_size(100).merge_unsorted(lcg_values(10_000, 4));
+        let curve = t.quantile_curve(5);
+        let qs: Vec<f64> = curve.iter().map(|&(q, _)| q).collect();
+        assert_eq!(qs, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert!(curve.iter().all(|&(q, v)| v == t.estimate_quantile(q)));
+        assert_eq!(t.quantile_curve(1), vec![(0.5, t.median())]);
+        assert!(t.quantile_curve(0).is_empty());
+
+        let cdf = t.cdf_curve(11);
+        assert_eq!((cdf[0].0, cdf[10].0), (t.min(), t.max()));
+        assert!(cdf.iter().all(|&(x, c)| c == t.cdf(x)));
+        assert_eq!(cdf[10].1, 1.0);
+        assert!(TDigest::default().cdf_curve(3).is_empty());
+        assert!(TDigest::default().quantile_curve(2)[0].1.is_nan());
+    }
+
+    #[test]
+    fn test_queries_over_unmerged_digests() {
+        let mut shards: Vec<TDigest> = (0..8u64)
+            .map(|seed| TDigest::new_with_size(50).merge_unsorted(lcg_values(2_000, seed)))
+            .collect();
+        shards[3].insert(-50.0);
+        shards[5].insert(500.0);
+        shards.push(TDigest::default());
+
+        let mut all: Vec<Centroid> = shards.iter().flat_map(|d| d.view().into_owned()).collect();
+        all.sort();
+        let union = Interpolation {
+            centroids: Cow::Owned(all),
+            count: shards.iter().map(|d| d.count()).sum(),
+            min: -50.0,
+            max: 500.0,
+        };
+        let merged = TDigest::merge_digests(shards.clone());
+        for q in [0.0, 0.001, 0.1, 0.5, 0.9, 0.999, 1.0] {
+            let estimate = TDigest::quantile_over(&shards, q);
+            assert_eq!(estimate, union.quantile(q, &mut QuantileCursor::default()));
+            if (0.1..=0.9).contains(&q) {
+                assert!(
+                    (estimate - merged.estimate_quantile(q)).abs() < 0.01,
+                    "{}",
+                    q
+                );
+            }
+        }
+        for x in [-60.0, -50.0, 0.1, 0.5, 0.9, 500.0] {
+            let rank = union.rank(x, &mut QuantileCursor::default());
+            assert_eq!(TDigest::cdf_over(&shards, x), rank / union.count);
+        }
+
+        let t = &shards[3];
+        assert_eq!(TDigest::quantile_over([t], 0.3), t.estimate_quantile(0.3));
+        assert_eq!(TDigest::cdf_over([t], 0.4), t.cdf(0.4));
+        assert!(TDigest::quantile_over(&shards[8..], 0.5).is_nan());
+        assert!(TDigest::cdf_over([], 0.5).is_nan());
+    }
+
+    #[test]
+    fn test_to_histogram() {
+        let t = TDigest::new_with_size(100).merge_unsorted((1..=1_000).map(f64::from).collect());
+        let histogram = t.to_histogram(&[0.0, 100.0, 500.0, 1_000.0]);
+        assert_eq!(histogram.len(), 5);
+        assert_eq!(histogram[0], 0.0);
+        assert!((histogram[1] - 100.0).abs() < 2.0);
+        assert!((histogram[2] - 400.0).abs() < 2.0);
+        assert_eq!(histogram[4], 0.0);
+        assert!((histogram.iter().sum::<f64>() - 1_000.0).abs() < 1e-9);
+
+        assert_eq!(t.to_histogram(&[]), vec![1_000.0]);
+        assert_eq!(TDigest::default().to_histogram(&[1.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        // A triangular density, the sum of two uniforms, peaks in the middle.
+        let u = lcg_values(20_000, 17);
+        let t: TDigest = u.chunks(2).map(|pair| pair[0] + pair[1]).collect();
+        let chart = t.render_ascii(9, 3);
+        let lines: Vec<Vec<char>> = chart.lines().map(|l| l.chars().collect()).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l.len() == 9));
+        assert_eq!(lines[0][4], '█');
+        assert_eq!((lines[0][0], lines[0][8]), (' ', ' '));
+        assert_ne!(lines[2][0], ' ');
+
+        let sparkline: Vec<char> = t.render_ascii(5, 1).chars().collect();
+        assert_eq!(sparkline.len(), 5);
+        assert!(sparkline[0] < sparkline[2] && sparkline[4] < sparkline[2]);
+
+        assert_eq!(TDigest::singleton(1.0, 10).render_ascii(3, 1), "█  ");
+        assert_eq!(TDigest::default().render_ascii(10, 2), "");
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{ConcurrentTDigest, TDigest};
+use core::fmt::Write;
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use std::collections::BTreeMap;
+use std::format;
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+impl HistogramFn for ConcurrentTDigest {
+    fn record(&self, value: f64) {
+        ConcurrentTDigest::record(self, value);
+    }
+}
+
+/// A [`metrics`] recorder that keeps every histogram as a digest and publishes it as a
+/// Prometheus summary with the configured quantiles.
+///
+/// Only histograms are recorded; counters and gauges get no-op handles, so combine it
+/// with another recorder (e.g. through `metrics_util`'s fanout layer) when those are
+/// needed as well. Install it with `metrics::set_global_recorder` and serve
+/// [`SummaryRecorder::render`] from the scrape endpoint.
+#[derive(Debug)]
+pub struct SummaryRecorder {
+    quantiles: Vec<f64>,
+    max_size: usize,
+    summaries: Mutex<BTreeMap<Key, Arc<ConcurrentTDigest>>>,
+    descriptions: Mutex<BTreeMap<String, String>>,
+}
+
+impl SummaryRecorder {
+    /// Creates a recorder publishing the given quantiles of each histogram, whose digests
+    /// keep up to 100 centroids.
+    pub fn new(quantiles: &[f64]) -> Self {
+        let mut quantiles = quantiles.to_vec();
+        quantiles.sort_by(f64::total_cmp);
+        SummaryRecorder {
+            quantiles,
+            max_size: 100,
+            summaries: Mutex::new(BTreeMap::new()),
+            descriptions: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Sets the `max_size` of the digests created from now on.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// A snapshot of the digest behind the histogram `key`, if it has been registered.
+    pub fn digest(&self, key: &Key) -> Option<TDigest> {
+        let summaries = self.summaries.lock().unwrap_or_else(|e| e.into_inner());
+        summaries.get(key).map(|digest| digest.snapshot())
+    }
+
+    /// Every summary in the Prometheus text exposition format. Keys whose names sanitize
+    /// to the same metric name, e.g. `a.b` and `a_b`, share one family.
+    pub fn render(&self) -> String {
+        let mut families: BTreeMap<String, Vec<(Key, Arc<ConcurrentTDigest>)>> = BTreeMap::new();
+        for (key, digest) in self
+            .summaries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            families
+                .entry(sanitize(key.name()))
+                .or_default()
+                .push((key.clone(), Arc::clone(digest)));
+        }
+        let descriptions = self.descriptions.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut out = String::new();
+        for (name, members) in families {
+            if let Some(help) = members
+                .iter()
+                .find_map(|(key, _)| descriptions.get(key.name()))
+            {
+                let _ = writeln!(out, "# HELP {} {}", name, escape_help(help));
+            }
+            let _ = writeln!(out, "# TYPE {} summary", name);
+
+            for (key, digest) in members {
+                let labels: Vec<String> = key
+                    .labels()
+                    .map(|l| format!("{}=\"{}\"", sanitize(l.key()), escape(l.value())))
+                    .collect();
+                let with = |extra: Option<String>| {
+                    let all: Vec<&str> = labels
+                        .iter()
+                        .map(String::as_str)
+                        .chain(extra.as_deref())
+                        .collect();
+                    if all.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{{{}}}", all.join(","))
+                    }
+                };
+
+                let snapshot = digest.snapshot();
+                for (&q, value) in self
+                    .quantiles
+                    .iter()
+                    .zip(snapshot.estimate_quantiles(&self.quantiles))
+                {
+                    let _ = writeln!(
+                        out,
+                        "{}{} {}",
+                        name,
+                        with(Some(format!("quantile=\"{}\"", q))),
+                        value
+                    );
+                }
+                let _ = writeln!(out, "{}_sum{} {}", name, with(None), snapshot.sum());
+                let _ = writeln!(out, "{}_count{} {}", name, with(None), snapshot.count());
+            }
+        }
+        out
+    }
+}
+
+/// Replaces the characters Prometheus does not allow in names with underscores, and
+/// prefixes an underscore to names that would otherwise be empty or start with a digit.
+fn sanitize(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match sanitized.chars().next() {
+        Some(c) if !c.is_ascii_digit() => sanitized,
+        _ => format!("_{}", sanitized),
+    }
+}
+
+/// Escapes backslashes and line feeds, as `# HELP` text requires.
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Escapes a label value: like `# HELP` text, plus double quotes.
+fn escape(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
+
+impl Recorder for SummaryRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        let mut descriptions = self.descriptions.lock().unwrap_or_else(|e| e.into_inner());
+        descriptions.insert(key.as_str().to_string(), description.to_string());
+    }
+
+    fn register_counter(&self, _key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::noop()
+    }
+
+    fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let mut summaries = self.summaries.lock().unwrap_or_else(|e| e.into_inner());
+        let digest = summaries
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(ConcurrentTDigest::new(self.max_size)));
+        Histogram::from_arc(Arc::clone(digest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn test_summary_recorder_renders_quantiles() {
+        let recorder = SummaryRecorder::new(&[0.99, 0.5]);
+        metrics::with_local_recorder(&recorder, || {
+            metrics::describe_histogram!("request.latency", "Request latency");
+            for i in 1..=100 {
+                metrics::histogram!("request.latency", "route" => "/a").record(i as f64);
+            }
+            metrics::histogram!("request.latency", "route" => "/b").record(7.0);
+            metrics::counter!("ignored").increment(1);
+        });
+
+        let key = Key::from_parts("request.latency", vec![metrics::Label::new("route", "/a")]);
+        assert_eq!(recorder.digest(&key).unwrap().count(), 100.0);
+
+        let text = recorder.render();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            vec![
+                "# HELP request_latency Request latency",
+                "# TYPE request_latency summary",
+                "request_latency{route=\"/a\",quantile=\"0.5\"} 50.5",
+                "request_latency{route=\"/a\",quantile=\"0.99\"} 99.5",
+                "request_latency_sum{route=\"/a\"} 5050",
+                "request_latency_count{route=\"/a\"} 100",
+                "request_latency{route=\"/b\",quantile=\"0.5\"} 7",
+                "request_latency{route=\"/b\",quantile=\"0.99\"} 7",
+                "request_latency_sum{route=\"/b\"} 7",
+                "request_latency_count{route=\"/b\"} 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_merges_families_and_escapes() {
+        let recorder = SummaryRecorder::new(&[0.5]);
+        metrics::with_local_recorder(&recorder, || {
+            metrics::describe_histogram!("a.b", "Line one\nC:\\path");
+            metrics::histogram!("a.b").record(1.0);
+            metrics::histogram!("a_b", "k" => "v").record(2.0);
+            metrics::histogram!("2xx", "1st" => "x").record(3.0);
+        });
+
+        let text = recorder.render();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            vec![
+                "# TYPE _2xx summary",
+                "_2xx{_1st=\"x\",quantile=\"0.5\"} 3",
+                "_2xx_sum{_1st=\"x\"} 3",
+                "_2xx_count{_1st=\"x\"} 1",
+                "# HELP a_b Line one\\nC:\\\\path",
+                "# TYPE a_b summary",
+                "a_b{quantile=\"0.5\"} 1",
+                "a_b_sum 1",
+                "a_b_count 1",
+                "a_b{k=\"v\",quantile=\"0.5\"} 2",
+                "a_b_sum{k=\"v\"} 2",
+                "a_b_count{k=\"v\"} 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use crate::query::QuantileCursor;
+use crate::TDigest;
+use alloc::vec::Vec;
+use rand::Rng;
+
+impl TDigest {
+    /// Draws a synthetic value following the recorded distribution, by inverse-CDF
+    /// sampling: the estimate of a uniformly random quantile, see
+    /// [`TDigest::estimate_quantile`]. Draws stay within `[min, max]` and, like the
+    /// quantile estimates, follow the linear interpolation between centroids. Returns NaN
+    /// for an empty digest.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.estimate_quantile(rng.random::<f64>())
+    }
+
+    /// Draws `n` independent values as by [`TDigest::sample`], e.g. to replay a recorded
+    /// latency distribution in a load test. Buffered values are merged once for all `n`
+    /// draws rather than per draw.
+    pub fn samples<R: Rng + ?Sized>(&self, n: usize, rng: &mut R) -> Vec<f64> {
+        let points = self.interpolation();
+        (0..n)
+            .map(|_| points.quantile(rng.random::<f64>(), &mut QuantileCursor::default()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_samples_follow_the_distribution() {
+        let values: Vec<f64> = lcg_values(20_000, 3).iter().map(|v| v * v).collect();
+        let digest = TDigest::new_with_size(100).merge_unsorted(values);
+        let mut rng = SmallRng::seed_from_u64(7);
+
+        let mut drawn = digest.samples(20_000, &mut rng);
+        assert_eq!(drawn.len(), 20_000);
+        assert!(drawn
+            .iter()
+            .all(|&v| (digest.min()..=digest.max()).contains(&v)));
+        let resampled = TDigest::new_with_size(100).merge_unsorted(drawn.clone());
+        assert!(resampled.ks_distance(&digest) < 0.02);
+        drawn.sort_by(f64::total_cmp);
+        assert!((drawn[10_000] - 0.25).abs() < 0.02);
+
+        let single = digest.sample(&mut rng);
+        assert!((digest.min()..=digest.max()).contains(&single));
+
+        let mut buffered = TDigest::new_with_size(100);
+        buffered.insert(4.0);
+        assert_eq!(buffered.samples(3, &mut rng), [4.0, 4.0, 4.0]);
+        assert!(TDigest::default().sample(&mut rng).is_nan());
+    }
+}
@@ -0,0 +1,719 @@
+use crate::centroid::{compensated_sum, NeumaierSum};
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::{Centroid, TDigest};
+use alloc::vec::Vec;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+/// Scale functions from Dunning's t-digest paper, mapping a quantile `q` to a scale `k`.
+///
+/// Compression lets every centroid span at most one unit of `k`, so the slope of the
+/// scale function decides where the digest spends its centroids. All of them are
+/// normalised for roughly `max_size` centroids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum ScaleFunction {
+    /// Linear, `k = d * q`: equal-weight centroids and uniform accuracy across quantiles.
+    K0,
+    /// `k = d * (asin(2q - 1) / pi + 1/2)`: finer centroids towards both tails.
+    #[default]
+    K1,
+    /// `k = d / Z * ln(q / (1 - q))`: even finer tails, with a normaliser `Z` that grows
+    /// with the count.
+    K2,
+    /// `k = d / Z * ln(2q)` below the median, mirrored above it: finest tails of all.
+    K3,
+}
+
+/// Which tail compression spends its resolution on, on top of the scale function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum TailBias {
+    /// Both tails as the scale function has them.
+    #[default]
+    Symmetric,
+    /// Only the upper half of the scale function, stretched over all quantiles at twice
+    /// the compression: the upper tail gets the resolution the scale function gives both
+    /// tails, for about the same number of centroids, while the lower tail is as coarse
+    /// as the median otherwise is. For digests only ever queried at, e.g., p90 and up.
+    Upper,
+    /// The mirror image of `Upper`, refining the lower tail only.
+    Lower,
+}
+
+/// A scale function for compression beyond the built-in [`ScaleFunction`]s, e.g. one
+/// that only refines the upper tail, plugged in with [`TDigest::with_scale_fn`].
+///
+/// Compression lets every centroid span at most one unit of `k`, so `k` must be
+/// non-decreasing in `q`, and `q` its inverse. The compression loop is generic over this
+/// trait, and the built-in scale functions implement it without any dynamic dispatch.
+/// Implementations must be thread- and unwind-safe, so that digests holding one still are.
+pub trait ScaleFn: core::fmt::Debug + Send + Sync + core::panic::RefUnwindSafe {
+    /// Scale `k` of quantile `q`.
+    fn k(&self, q: f64, compression: f64) -> f64;
+
+    /// Inverse of `k`, clamped to `[0, 1]`.
+    fn q(&self, k: f64, compression: f64) -> f64;
+
+    /// The largest fraction of the total weight that a centroid covering the quantiles
+    /// `q1` to `q2` may hold, on top of spanning at most one unit of `k`. Unlimited by
+    /// default.
+    fn max_weight(&self, q1: f64, q2: f64) -> f64 {
+        let _ = (q1, q2);
+        f64::INFINITY
+    }
+
+    /// The compression `k` and `q` are called with, for `max_size` centroids and a total
+    /// weight of `count`. `max_size` by default, for a `k` that spans `max_size` units
+    /// over `[0, 1]`.
+    fn compression(&self, max_size: usize, count: f64) -> f64 {
+        let _ = count;
+        max_size as f64
+    }
+}
+
+/// A scale function restricted to one half and stretched over all quantiles, see
+/// [`TailBias`]. The identity under `TailBias::Symmetric`.
+#[derive(Debug, Clone, Copy)]
+struct Biased<'a, S: ScaleFn + ?Sized> {
+    scale: &'a S,
+    bias: TailBias,
+}
+
+impl<S: ScaleFn + ?Sized> Biased<'_, S> {
+    /// The quantile of the underlying scale function that `q` maps to.
+    #[inline]
+    fn inner(&self, q: f64) -> f64 {
+        match self.bias {
+            TailBias::Symmetric => q,
+            TailBias::Upper => 0.5 + q / 2.0,
+            TailBias::Lower => q / 2.0,
+        }
+    }
+
+    /// Ratio of the widths of quantile ranges before and after the mapping.
+    #[inline]
+    fn stretch(&self) -> f64 {
+        match self.bias {
+            TailBias::Symmetric => 1.0,
+            TailBias::Upper | TailBias::Lower => 2.0,
+        }
+    }
+}
+
+impl<S: ScaleFn + ?Sized> ScaleFn for Biased<'_, S> {
+    #[inline]
+    fn k(&self, q: f64, compression: f64) -> f64 {
+        self.scale.k(self.inner(q), compression)
+    }
+
+    #[inline]
+    fn q(&self, k: f64, compression: f64) -> f64 {
+        let q = self.scale.q(k, compression);
+        match self.bias {
+            TailBias::Symmetric => q,
+            TailBias::Upper => (2.0 * q - 1.0).clamp(0.0, 1.0),
+            TailBias::Lower => (2.0 * q).clamp(0.0, 1.0),
+        }
+    }
+
+    #[inline]
+    fn max_weight(&self, q1: f64, q2: f64) -> f64 {
+        self.stretch() * self.scale.max_weight(self.inner(q1), self.inner(q2))
+    }
+
+    #[inline]
+    fn compression(&self, max_size: usize, count: f64) -> f64 {
+        self.stretch() * self.scale.compression(max_size, count)
+    }
+}
+
+/// The scale function a digest compresses under: its custom or built-in one, under its
+/// tail bias.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Scaling<'a> {
+    pub(crate) scale: ScaleFunction,
+    pub(crate) custom: Option<&'a dyn ScaleFn>,
+    pub(crate) bias: TailBias,
+}
+
+impl Scaling<'_> {
+    /// The underlying scale function under the tail bias, dynamically dispatched.
+    fn biased(&self) -> Biased<'_, dyn ScaleFn + '_> {
+        let scale: &dyn ScaleFn = match self.custom {
+            Some(custom) => custom,
+            None => &self.scale,
+        };
+        Biased {
+            scale,
+            bias: self.bias,
+        }
+    }
+
+    /// [`TDigest::compress_sorted`] under this scale function, monomorphized for the
+    /// built-in ones without a bias.
+    pub(crate) fn compress_sorted<I>(
+        &self,
+        centroids: I,
+        count: f64,
+        max_size: usize,
+        compressed: &mut Vec<Centroid>,
+    ) -> f64
+    where
+        I: IntoIterator<Item = Centroid>,
+    {
+        match (self.custom, self.bias) {
+            (None, TailBias::Symmetric) => {
+                TDigest::compress_sorted(centroids, count, max_size, &self.scale, compressed)
+            }
+            (None, bias) => {
+                let scale = Biased {
+                    scale: &self.scale,
+                    bias,
+                };
+                TDigest::compress_sorted(centroids, count, max_size, &scale, compressed)
+            }
+            (Some(custom), TailBias::Symmetric) => {
+                TDigest::compress_sorted(centroids, count, max_size, custom, compressed)
+            }
+            (Some(_), _) => {
+                TDigest::compress_sorted(centroids, count, max_size, &self.biased(), compressed)
+            }
+        }
+    }
+}
+
+impl ScaleFn for Scaling<'_> {
+    fn k(&self, q: f64, compression: f64) -> f64 {
+        self.biased().k(q, compression)
+    }
+
+    fn q(&self, k: f64, compression: f64) -> f64 {
+        self.biased().q(k, compression)
+    }
+
+    fn max_weight(&self, q1: f64, q2: f64) -> f64 {
+        self.biased().max_weight(q1, q2)
+    }
+
+    fn compression(&self, max_size: usize, count: f64) -> f64 {
+        self.biased().compression(max_size, count)
+    }
+}
+
+impl ScaleFunction {
+    /// Normaliser for `K2`/`K3`, keeping the centroid count near `d` for `n` values.
+    fn normalizer(d: f64, n: f64) -> f64 {
+        4.0 * (n / d).max(1.0).ln() + 24.0
+    }
+}
+
+/// `compression` is `max_size` for `K0` and `K1`, and `max_size / Z` for `K2` and `K3`.
+impl ScaleFn for ScaleFunction {
+    #[inline]
+    fn k(&self, q: f64, compression: f64) -> f64 {
+        let c = compression;
+        match self {
+            ScaleFunction::K0 => c * q,
+            ScaleFunction::K1 => {
+                c * ((2.0 * q - 1.0).clamp(-1.0, 1.0).asin() / core::f64::consts::PI + 0.5)
+            }
+            ScaleFunction::K2 => c * (q / (1.0 - q)).ln(),
+            ScaleFunction::K3 => {
+                if q <= 0.5 {
+                    c * (2.0 * q).ln()
+                } else {
+                    -c * (2.0 * (1.0 - q)).ln()
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn q(&self, k: f64, compression: f64) -> f64 {
+        let x = k / compression;
+        let q = match self {
+            ScaleFunction::K0 => x,
+            ScaleFunction::K1 => {
+                let x = (x.clamp(0.0, 1.0) - 0.5) * core::f64::consts::PI;
+                (x.sin() + 1.0) / 2.0
+            }
+            ScaleFunction::K2 => 1.0 / (1.0 + (-x).exp()),
+            ScaleFunction::K3 => {
+                if x <= 0.0 {
+                    x.exp() / 2.0
+                } else {
+                    1.0 - (-x).exp() / 2.0
+                }
+            }
+        };
+        q.clamp(0.0, 1.0)
+    }
+
+    #[inline]
+    fn compression(&self, max_size: usize, count: f64) -> f64 {
+        let d = max_size as f64;
+        match self {
+            ScaleFunction::K0 | ScaleFunction::K1 => d,
+            ScaleFunction::K2 | ScaleFunction::K3 => d / Self::normalizer(d, count),
+        }
+    }
+}
+
+/// The state of a merging pass over sorted centroids, which are fed in one at a time and
+/// come out merged as far as the scale function allows. Every output centroid is emitted
+/// no earlier than the last input folded into it, so the pass can also run in place.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScaleMerger<'s, S: ScaleFn + ?Sized> {
+    scale: &'s S,
+    compression: f64,
+    count: f64,
+    k_limit: f64,
+    q_limit_times_count: f64,
+    weight_so_far: f64,
+    /// The weight before `curr`.
+    curr_start: f64,
+    curr: Option<Centroid>,
+    sums_to_merge: NeumaierSum,
+    weights_to_merge: NeumaierSum,
+}
+
+impl<'s, S: ScaleFn + ?Sized> ScaleMerger<'s, S> {
+    pub(crate) fn new(count: f64, max_size: usize, scale: &'s S) -> Self {
+        // Each centroid may grow until its end reaches the next unit of the scale function.
+        // The limit advances by at least one unit per centroid, which bounds the number of
+        // centroids by the range of `k`, and skips ahead when a heavy centroid overshoots.
+        let compression = scale.compression(max_size, count);
+        let k_limit = scale.k(0.0, compression) + 1.0;
+        ScaleMerger {
+            scale,
+            compression,
+            count,
+            k_limit,
+            q_limit_times_count: scale.q(k_limit, compression) * count,
+            weight_so_far: 0.0,
+            curr_start: 0.0,
+            curr: None,
+            sums_to_merge: NeumaierSum::default(),
+            weights_to_merge: NeumaierSum::default(),
+        }
+    }
+
+    /// Folds in the next centroid, returning the previous one once it is complete.
+    pub(crate) fn push(&mut self, centroid: Centroid) -> Option<Centroid> {
+        self.weight_so_far += centroid.weight();
+        let curr = match self.curr {
+            Some(c) => c,
+            None => {
+                self.curr = Some(centroid);
+                return None;
+            }
+        };
+
+        let count = self.count;
+        let q_start = (self.weight_so_far - centroid.weight()) / count;
+        if self.weight_so_far <= self.q_limit_times_count
+            && self.weight_so_far - self.curr_start
+                <= self
+                    .scale
+                    .max_weight(self.curr_start / count, self.weight_so_far / count)
+                    * count
+        {
+            self.sums_to_merge.add(centroid.mean() * centroid.weight());
+            self.weights_to_merge.add(centroid.weight());
+            return None;
+        }
+
+        let (scale, compression) = (self.scale, self.compression);
+        self.k_limit = (self.k_limit + 1.0).max(scale.k(q_start, compression) + 1.0);
+        self.q_limit_times_count = scale.q(self.k_limit, compression) * count;
+        self.curr_start = self.weight_so_far - centroid.weight();
+        self.curr = Some(centroid);
+        Some(self.complete(curr))
+    }
+
+    /// Folds the pending sums into `curr` and starts afresh.
+    fn complete(&mut self, mut curr: Centroid) -> Centroid {
+        curr.update(self.sums_to_merge.value(), self.weights_to_merge.value());
+        self.sums_to_merge = NeumaierSum::default();
+        self.weights_to_merge = NeumaierSum::default();
+        curr
+    }
+
+    /// The last centroid, if any were pushed.
+    pub(crate) fn finish(mut self) -> Option<Centroid> {
+        let curr = self.curr.take()?;
+        Some(self.complete(curr))
+    }
+}
+
+impl TDigest {
+    /// The quantile interval `(q_lo, q_hi)` each centroid covers, i.e. the fractions of
+    /// the total weight before and up to its end. Like [`TDigest::centroids`] it excludes
+    /// buffered values, so flush first to inspect everything.
+    pub fn centroid_q_bounds(&self) -> Vec<(f64, f64)> {
+        let count = self.count_of_centroids();
+        let mut t = 0.0;
+        self.centroids
+            .iter()
+            .map(|c| {
+                let q_lo = t / count;
+                t += c.weight();
+                (q_lo, (t / count).min(1.0))
+            })
+            .collect()
+    }
+
+    /// The largest weight compression lets each centroid reach under the scale function.
+    /// A centroid ends one unit of `k` past where it starts, or past the previous
+    /// centroid's limit if that is further, which caps the number of centroids by the
+    /// range of `k`; see [`TDigest::centroid_q_bounds`] for where each one starts. A
+    /// single input, e.g. one value, is never split and may exceed its limit.
+    pub fn centroid_weight_limits(&self) -> Vec<f64> {
+        let (scale, count) = (self.scale_fn(), self.count_of_centroids());
+        let compression = scale.compression(self.max_size, count);
+        let mut k_limit = f64::NEG_INFINITY;
+        self.centroid_q_bounds()
+            .into_iter()
+            .map(|(q_lo, _)| {
+                k_limit = (k_limit + 1.0).max(scale.k(q_lo, compression) + 1.0);
+                (scale.q(k_limit, compression) - q_lo) * count
+            })
+            .collect()
+    }
+
+    /// Whether every centroid of more than one value stays within its
+    /// [`TDigest::centroid_weight_limits`], up to rounding, e.g. to validate merges.
+    ///
+    /// Merges never split an input centroid. Under `K2` and `K3` the units of `k` narrow
+    /// in the tails as the count grows, so a heavy centroid carried over from an earlier,
+    /// smaller digest may exceed the limits of the merged one; `K0` and `K1` do not depend
+    /// on the count.
+    pub fn respects_size_bound(&self) -> bool {
+        self.centroids
+            .iter()
+            .zip(self.centroid_weight_limits())
+            .all(|(c, limit)| c.weight() <= 1.0 || c.weight() <= limit * (1.0 + 1e-9))
+    }
+
+    /// Total weight of the centroids, which excludes buffered values unlike `count`.
+    fn count_of_centroids(&self) -> f64 {
+        if self.buffer.is_empty() {
+            self.count
+        } else {
+            compensated_sum(self.centroids.iter().map(|c| c.weight()))
+        }
+    }
+}
+
+impl TDigest {
+    /// Upper bound on the rank error, as a fraction of `count`, of a quantile estimate at
+    /// `q` under the t-digest analysis for the configured `max_size` and scale function.
+    ///
+    /// Compression never lets a centroid span more than one unit of the scale function,
+    /// so the error is bounded by the quantile range of one unit centred on `q`. For
+    /// `K0` and `K1` the bound only depends on `q` and `max_size`, not on the data; `K2`
+    /// and `K3` also depend on the count, for which at least `max_size` is assumed. For
+    /// every scale function but `K0` it shrinks towards the tails.
+    pub fn theoretical_quantile_error(&self, q: f64) -> f64 {
+        let scale = self.scale_fn();
+        let compression = scale.compression(self.max_size, self.count().max(self.max_size as f64));
+        let q = Self::clamp(q, 0.0, 1.0);
+        let k = scale.k(q, compression);
+
+        let lo = scale.q(k - 0.5, compression);
+        let hi = scale.q(k + 0.5, compression);
+        hi - lo
+    }
+
+    /// Rank error, as a fraction of `count`, that no quantile estimate exceeds: the
+    /// [`TDigest::theoretical_quantile_error`] at the median, where every scale function
+    /// allows its widest centroids, or half the heaviest centroid's share of the weight if
+    /// that is larger, e.g. after [`TDigest::coalesce_below_weight`].
+    pub fn max_rank_error(&self) -> f64 {
+        let heaviest = self.view().iter().map(|c| c.weight()).fold(0.0, f64::max);
+        let empirical = if self.count > 0.0 {
+            heaviest / (2.0 * self.count)
+        } else {
+            0.0
+        };
+        self.theoretical_quantile_error(0.5).max(empirical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::lcg_values;
+    use crate::{CompactTDigest, MergePolicy, TDigestError};
+    use alloc::vec;
+
+    /// `k = c * q^2`: centroids shrink towards the upper tail only.
+    #[derive(Debug)]
+    struct UpperTail;
+
+    impl ScaleFn for UpperTail {
+        fn k(&self, q: f64, compression: f64) -> f64 {
+            compression * q * q
+        }
+
+        fn q(&self, k: f64, compression: f64) -> f64 {
+            (k / compression).clamp(0.0, 1.0).sqrt()
+        }
+    }
+
+    /// Linear, with no centroid above 1% of the weight.
+    #[derive(Debug)]
+    struct Capped;
+
+    impl ScaleFn for Capped {
+        fn k(&self, q: f64, compression: f64) -> f64 {
+            compression * q
+        }
+
+        fn q(&self, k: f64, compression: f64) -> f64 {
+            (k / compression).clamp(0.0, 1.0)
+        }
+
+        fn max_weight(&self, _q1: f64, _q2: f64) -> f64 {
+            0.01
+        }
+    }
+
+    #[test]
+    fn test_centroids_respect_size_bound_after_merges() {
+        for scale in [
+            ScaleFunction::K0,
+            ScaleFunction::K1,
+            ScaleFunction::K2,
+            ScaleFunction::K3,
+        ] {
+            let parts: Vec<TDigest> = (0..20)
+                .map(|seed| {
+                    TDigest::new_with_scale(50, scale).merge_unsorted(lcg_values(2_000, seed))
+                })
+                .collect();
+            assert!(
+                parts.iter().all(TDigest::respects_size_bound),
+                "{:?}",
+                scale
+            );
+
+            let mut merged = TDigest::merge_digests(parts.clone());
+            assert!(merged.respects_size_bound(), "{:?}", scale);
+            for part in parts.iter() {
+                merged.merge(part);
+            }
+            // Only the count-independent scales keep the bound for carried-over centroids.
+            if matches!(scale, ScaleFunction::K0 | ScaleFunction::K1) {
+                assert!(merged.respects_size_bound(), "{:?}", scale);
+            }
+
+            let bounds = merged.centroid_q_bounds();
+            assert_eq!(bounds.len(), merged.len());
+            assert_eq!((bounds[0].0, bounds[bounds.len() - 1].1), (0.0, 1.0));
+            assert!(bounds.windows(2).all(|w| w[0].1 == w[1].0));
+        }
+
+        let mut t = TDigest::new_with_size(10).merge_unsorted(lcg_values(1_000, 3));
+        t.coalesce_below_weight(500.0);
+        assert!(!t.respects_size_bound());
+        assert!(TDigest::default().centroid_weight_limits().is_empty());
+    }
+
+    #[test]
+    fn test_theoretical_quantile_error() {
+        let t = TDigest::new_with_size(100);
+        let median = t.theoretical_quantile_error(0.5);
+        let tail = t.theoretical_quantile_error(0.99);
+        assert!(tail < median);
+        assert!(t.theoretical_quantile_error(0.01) < median);
+        assert!(median <= 0.02 + 1e-12);
+
+        let larger = TDigest::new_with_size(1000);
+        assert!(larger.theoretical_quantile_error(0.5) < median);
+    }
+
+    #[test]
+    fn test_scale_functions_round_trip() {
+        for scale in [
+            ScaleFunction::K0,
+            ScaleFunction::K1,
+            ScaleFunction::K2,
+            ScaleFunction::K3,
+        ] {
+            let c = scale.compression(100, 10_000.0);
+            for &q in &[0.001, 0.1, 0.5, 0.9, 0.999] {
+                let k = scale.k(q, c);
+                assert!((scale.q(k, c) - q).abs() < 1e-9, "{:?}", scale);
+            }
+            assert_eq!(scale.q(f64::NEG_INFINITY, c), 0.0);
+            assert_eq!(scale.q(f64::INFINITY, c), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_compression_with_each_scale_function() {
+        let values = lcg_values(100_000, 7);
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let mut sizes = Vec::new();
+        for scale in [
+            ScaleFunction::K0,
+            ScaleFunction::K1,
+            ScaleFunction::K2,
+            ScaleFunction::K3,
+        ] {
+            let t = TDigest::new_with_scale(100, scale).merge_unsorted(values.clone());
+            assert_eq!(t.scale_function(), scale);
+            assert_eq!(t.count(), 100_000.0);
+            assert!(
+                t.centroids.len() <= 100,
+                "{:?}: {}",
+                scale,
+                t.centroids.len()
+            );
+            assert!(t.centroids.windows(2).all(|w| w[0] <= w[1]));
+
+            for &q in &[0.01, 0.5, 0.99] {
+                let exact = sorted[(q * sorted.len() as f64) as usize];
+                assert!((t.estimate_quantile(q) - exact).abs() < 0.01, "{:?}", scale);
+            }
+            sizes.push(t.centroids[0].weight());
+        }
+
+        // Tail centroids get smaller as the scale functions get steeper at the edges.
+        assert!(sizes.windows(2).all(|w| w[0] >= w[1]), "{:?}", sizes);
+        assert!(sizes[0] > sizes[1]);
+    }
+
+    #[test]
+    fn test_theoretical_quantile_error_per_scale() {
+        let k0 = TDigest::new_with_scale(100, ScaleFunction::K0);
+        assert!((k0.theoretical_quantile_error(0.5) - 0.01).abs() < 1e-12);
+        assert!((k0.theoretical_quantile_error(0.99) - 0.01).abs() < 1e-12);
+
+        for scale in [ScaleFunction::K1, ScaleFunction::K2, ScaleFunction::K3] {
+            let t = TDigest::new_with_scale(100, scale);
+            assert!(t.theoretical_quantile_error(0.99) < t.theoretical_quantile_error(0.5));
+        }
+    }
+
+    #[test]
+    fn test_digests_stay_thread_and_unwind_safe() {
+        fn assert_safe<T: Send + Sync + core::panic::UnwindSafe + core::panic::RefUnwindSafe>(
+            _: &T,
+        ) {
+        }
+        assert_safe(&TDigest::default());
+        assert_safe(&TDigest::new_with_size(50).with_scale_fn(UpperTail));
+    }
+
+    #[test]
+    fn test_custom_scale_functions() {
+        let values = lcg_values(20_000, 6);
+        let k0 = TDigest::new_with_scale(50, ScaleFunction::K0).merge_unsorted(values.clone());
+        let upper = TDigest::new_with_size(50)
+            .with_scale_fn(UpperTail)
+            .merge_unsorted(values.clone());
+        let top = |t: &TDigest| t.centroids().last().unwrap().weight();
+        assert!(top(&upper) < top(&k0) * 0.75);
+        assert!(
+            upper.centroids().next().unwrap().weight()
+                > 5.0 * k0.centroids().next().unwrap().weight()
+        );
+        assert!(upper.respects_size_bound());
+        assert_eq!(upper.scale_fn().k(0.5, 100.0), 25.0);
+
+        let capped = TDigest::new_with_size(10)
+            .with_scale_fn(Capped)
+            .merge_unsorted(values);
+        assert!(capped.centroids().all(|c| c.weight() <= 200.0));
+        assert!(capped.len() >= 100);
+
+        // Custom scale functions carry over to clones and merges, which equal each other.
+        let merged = TDigest::merge_digests(vec![upper.clone(), upper.clone()]);
+        assert_eq!(merged.scale_fn().k(0.5, 100.0), 25.0);
+        assert_eq!(upper.clone(), upper);
+        assert_ne!(
+            TDigest::new_with_size(50).with_scale_fn(UpperTail),
+            TDigest::new_with_size(50)
+        );
+        assert_eq!(
+            upper.clone().merge_with(&k0, MergePolicy::Strict),
+            Err(TDigestError::MismatchedConfiguration {
+                field: "scale function"
+            })
+        );
+        let restored = upper.clone().with_scale_function(ScaleFunction::K1);
+        assert_eq!(restored.scale_fn().k(0.5, 100.0), 50.0);
+    }
+
+    #[test]
+    fn test_upper_tail_bias_lowers_upper_tail_error() {
+        let high: Vec<f64> = (0..100).map(|i| 0.9 + 0.000_99 * i as f64).collect();
+        let low: Vec<f64> = high.iter().map(|q| 1.0 - q).collect();
+        for scale in [ScaleFunction::K1, ScaleFunction::K2, ScaleFunction::K3] {
+            let build = |bias| {
+                TDigest::builder()
+                    .max_size(50)
+                    .scale_function(scale)
+                    .tail_bias(bias)
+                    .build()
+            };
+            // Mean rank error at `qs` over digests of several data sets.
+            let rank_error = |bias, qs: &[f64]| {
+                let mut error = 0.0;
+                for seed in 0..3u64 {
+                    let mut values = lcg_values(20_000, seed);
+                    let mut t = build(bias);
+                    t.extend(values.iter().copied());
+                    values.sort_by(f64::total_cmp);
+                    for &q in qs {
+                        let estimate = t.estimate_quantile(q);
+                        let rank = values.partition_point(|&v| v < estimate) as f64;
+                        error += (rank / values.len() as f64 - q).abs();
+                    }
+                }
+                error
+            };
+            let symmetric = (
+                rank_error(TailBias::Symmetric, &high),
+                rank_error(TailBias::Symmetric, &low),
+            );
+            assert!(
+                rank_error(TailBias::Upper, &high) < symmetric.0 / 1.5,
+                "{:?}",
+                scale
+            );
+            assert!(
+                rank_error(TailBias::Lower, &low) < symmetric.1 / 1.5,
+                "{:?}",
+                scale
+            );
+            if scale == ScaleFunction::K1 {
+                // The resolution comes out of the other tail.
+                assert!(rank_error(TailBias::Upper, &low) > symmetric.1);
+            }
+        }
+
+        let mut upper = TDigest::builder().tail_bias(TailBias::Upper).build();
+        upper.extend(lcg_values(10_000, 1));
+        upper.flush();
+        assert_eq!(upper.tail_bias(), TailBias::Upper);
+        let symmetric = upper.clone().with_tail_bias(TailBias::Symmetric);
+        assert_ne!(upper, symmetric);
+        let merged = TDigest::merge_digests(vec![upper.clone(), symmetric.clone()]);
+        assert_eq!(merged.tail_bias(), TailBias::Upper);
+        assert_eq!(TDigest::from_bytes(&upper.to_bytes()).unwrap(), upper);
+        let lower = upper.with_tail_bias(TailBias::Lower);
+        assert_eq!(TDigest::from_bytes(&lower.to_bytes()).unwrap(), lower);
+        assert_eq!(
+            TDigest::from(&CompactTDigest::from(&lower)).tail_bias(),
+            TailBias::Lower
+        );
+    }
+}
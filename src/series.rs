@@ -0,0 +1,203 @@
+use crate::codec::{write_varint, ByteReader};
+use crate::{TDigest, TDigestError};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Version byte written by [`DigestSeries::to_bytes`].
+const SERIES_ENCODING_VERSION: u8 = 1;
+
+/// Digests keyed by timestamp, e.g. one per minute of a metric, for retention tiers:
+/// recent entries at full resolution, older ones [downsampled](DigestSeries::downsample)
+/// into coarser buckets, and any time range merged on demand.
+///
+/// Timestamps are [`Duration`]s since any epoch the caller likes, as for
+/// [`crate::WindowedTDigest`]. Entries are kept in ascending order of timestamp, and
+/// pushing at an existing timestamp merges into its digest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DigestSeries {
+    entries: Vec<(Duration, TDigest)>,
+}
+
+impl DigestSeries {
+    pub fn new() -> Self {
+        DigestSeries::default()
+    }
+
+    /// Adds `digest` at `timestamp`, merging it into the entry already there, if any.
+    /// Appending in time order is cheapest.
+    pub fn push(&mut self, timestamp: Duration, digest: TDigest) {
+        let at = self.entries.partition_point(|(t, _)| *t < timestamp);
+        match self.entries.get_mut(at) {
+            Some((t, existing)) if *t == timestamp => existing.merge(&digest),
+            _ => self.entries.insert(at, (timestamp, digest)),
+        }
+    }
+
+    /// Number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries in ascending order of timestamp.
+    pub fn iter(&self) -> impl Iterator<Item = (Duration, &TDigest)> {
+        self.entries.iter().map(|(t, digest)| (*t, digest))
+    }
+
+    /// The entries with timestamps in `[start, end)`.
+    fn range_entries(&self, start: Duration, end: Duration) -> &[(Duration, TDigest)] {
+        let first = self.entries.partition_point(|(t, _)| *t < start);
+        let last = self.entries.partition_point(|(t, _)| *t < end).max(first);
+        &self.entries[first..last]
+    }
+
+    /// Merges the entries with timestamps in `[start, end)`, e.g. the hour from 14:00 to
+    /// 15:00, see [`TDigest::merge_digests`]. An empty range gives `TDigest::default()`.
+    pub fn range(&self, start: Duration, end: Duration) -> TDigest {
+        let digests = self.range_entries(start, end).iter();
+        TDigest::merge_digests(digests.map(|(_, digest)| digest.clone()).collect())
+    }
+
+    /// Merges the entries older than `before` into buckets `width` long, aligned to
+    /// multiples of `width` since the epoch and keyed by their start, e.g. minutely
+    /// entries older than a day into hourly ones. Newer entries are left alone.
+    pub fn downsample(&mut self, before: Duration, width: Duration) {
+        assert!(!width.is_zero(), "bucket width must be positive");
+        let split = self.entries.partition_point(|(t, _)| *t < before);
+        let newer = self.entries.split_off(split);
+        let bucket_of = |t: Duration| (t.as_nanos() / width.as_nanos()) * width.as_nanos();
+
+        let mut old = core::mem::take(&mut self.entries).into_iter().peekable();
+        while let Some((t, digest)) = old.next() {
+            let bucket = bucket_of(t);
+            let mut digests = Vec::new();
+            while let Some((_, next)) = old.next_if(|(next, _)| bucket_of(*next) == bucket) {
+                digests.push(next);
+            }
+            let merged = if digests.is_empty() {
+                digest
+            } else {
+                digests.insert(0, digest);
+                TDigest::merge_digests(digests)
+            };
+            let start = Duration::new(
+                (bucket / 1_000_000_000) as u64,
+                (bucket % 1_000_000_000) as u32,
+            );
+            self.entries.push((start, merged));
+        }
+        self.entries.extend(newer);
+    }
+
+    /// Encodes the series as a `u8` version and the varint number of entries, followed
+    /// per entry by its timestamp as varint seconds and `u32` nanoseconds, and its
+    /// digest as the varint length of its [`TDigest::to_bytes`] and those bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.push(SERIES_ENCODING_VERSION);
+        write_varint(&mut out, self.entries.len() as u64);
+        for (t, digest) in self.entries.iter() {
+            write_varint(&mut out, t.as_secs());
+            out.extend_from_slice(&t.subsec_nanos().to_le_bytes());
+            let bytes = digest.to_bytes();
+            write_varint(&mut out, bytes.len() as u64);
+            out.extend_from_slice(&bytes);
+        }
+        out
+    }
+
+    /// Decodes a series written by [`DigestSeries::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<DigestSeries, TDigestError> {
+        let invalid = |reason| TDigestError::InvalidEncoding { reason };
+        let mut reader = ByteReader { bytes };
+
+        let version = reader.u8()?;
+        if version != SERIES_ENCODING_VERSION {
+            return Err(TDigestError::UnsupportedVersion { version });
+        }
+        let n = reader.varint()?;
+        let mut series = DigestSeries::new();
+        for _ in 0..n {
+            let secs = reader.varint()?;
+            let nanos = reader.u32()?;
+            if nanos >= 1_000_000_000 {
+                return Err(invalid("timestamp nanoseconds out of range"));
+            }
+            let timestamp = Duration::new(secs, nanos);
+            if series.entries.last().is_some_and(|(t, _)| *t >= timestamp) {
+                return Err(invalid("timestamps out of order"));
+            }
+            let len = usize::try_from(reader.varint()?).map_err(|_| invalid("digest too long"))?;
+            let digest = TDigest::from_bytes(reader.take(len)?)?;
+            series.entries.push((timestamp, digest));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn minute(m: u64) -> Duration {
+        Duration::from_secs(60 * m)
+    }
+
+    /// 3 hours of minutely digests, each of the values `m..m + 10`.
+    fn minutely() -> DigestSeries {
+        let mut series = DigestSeries::new();
+        for m in 0..180 {
+            let values = (0..10).map(|v| (m + v) as f64).collect();
+            series.push(minute(m), TDigest::new_with_size(50).merge_unsorted(values));
+        }
+        series
+    }
+
+    #[test]
+    fn test_push_range_and_downsample() {
+        let mut series = minutely();
+        series.push(
+            minute(5),
+            TDigest::new_with_size(50).merge_unsorted(vec![1e6]),
+        );
+        assert_eq!(series.len(), 180);
+
+        let hour = series.range(minute(60), minute(120));
+        assert_eq!(hour.count(), 600.0);
+        assert_eq!((hour.min(), hour.max()), (60.0, 128.0));
+        assert!(series.range(minute(500), minute(600)).is_empty());
+
+        series.downsample(minute(120), minute(60));
+        assert_eq!(series.len(), 2 + 60);
+        let timestamps: Vec<Duration> = series.iter().take(3).map(|(t, _)| t).collect();
+        assert_eq!(timestamps, vec![minute(0), minute(60), minute(120)]);
+        assert_eq!(series.range(minute(60), minute(120)).count(), 600.0);
+        assert_eq!(series.range(minute(0), minute(180)).count(), 1_801.0);
+    }
+
+    #[test]
+    fn test_series_round_trip() {
+        let mut series = minutely();
+        series.push(Duration::new(1, 5), TDigest::default());
+        let bytes = series.to_bytes();
+        assert_eq!(DigestSeries::from_bytes(&bytes).unwrap(), series);
+
+        assert!(DigestSeries::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(DigestSeries::from_bytes(&trailing).is_err());
+        assert_eq!(
+            DigestSeries::from_bytes(&[2, 0]),
+            Err(TDigestError::UnsupportedVersion { version: 2 })
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::TDigest;
+use std::sync::{Arc, RwLock};
+
+/// The read side of a digest with one writer and many readers, e.g. dashboards querying
+/// while a service records.
+///
+/// Readers [`load`](SharedTDigest::load) the last published digest as an `Arc`: the lock
+/// is only held to clone the pointer, so readers never wait on inserts or compression,
+/// and a loaded digest stays valid and unchanged however long it is queried. The
+/// [`SharedTDigestWriter`] records into its own digest and publishes a
+/// [`snapshot`](TDigest::snapshot) of it whenever it chooses.
+#[derive(Debug, Clone)]
+pub struct SharedTDigest {
+    current: Arc<RwLock<Arc<TDigest>>>,
+}
+
+impl SharedTDigest {
+    /// The last published digest.
+    pub fn load(&self) -> Arc<TDigest> {
+        // A panic while holding the lock cannot leave the pointer half-written, so
+        // poisoning is ignored.
+        let current = self.current.read().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&current)
+    }
+}
+
+/// The single writer of a [`SharedTDigest`].
+#[derive(Debug)]
+pub struct SharedTDigestWriter {
+    digest: TDigest,
+    shared: SharedTDigest,
+}
+
+impl SharedTDigestWriter {
+    /// Creates the writer of an empty digest of `max_size` centroids, from which readers
+    /// are obtained with [`SharedTDigestWriter::reader`].
+    pub fn new(max_size: usize) -> Self {
+        SharedTDigestWriter::from_digest(TDigest::new_with_size(max_size))
+    }
+
+    /// Creates a writer that continues `digest`, publishing a snapshot of it right away.
+    pub fn from_digest(digest: TDigest) -> Self {
+        let shared = SharedTDigest {
+            current: Arc::new(RwLock::new(Arc::new(digest.snapshot()))),
+        };
+        SharedTDigestWriter { digest, shared }
+    }
+
+    /// A handle to the published digest, for any number of reader threads.
+    pub fn reader(&self) -> SharedTDigest {
+        self.shared.clone()
+    }
+
+    /// The writer's digest, including values not yet published.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// Adds a single value, see [`TDigest::insert`]. Readers see it from the next
+    /// [`publish`](SharedTDigestWriter::publish).
+    pub fn insert(&mut self, value: f64) {
+        self.digest.insert(value);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times, see [`TDigest::insert_weighted`].
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        self.digest.insert_weighted(value, weight);
+    }
+
+    /// Folds `other` into the writer's digest, see [`TDigest::merge`].
+    pub fn merge(&mut self, other: &TDigest) {
+        self.digest.merge(other);
+    }
+
+    /// Makes everything recorded so far visible to readers. Readers still holding the
+    /// previous digest keep it until they drop it.
+    pub fn publish(&mut self) {
+        self.digest.flush();
+        let published = Arc::new(self.digest.clone());
+        let mut current = self
+            .shared
+            .current
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        *current = published;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readers_see_published_snapshots() {
+        let mut writer = SharedTDigestWriter::new(100);
+        let reader = writer.reader();
+        assert!(reader.load().is_empty());
+
+        for i in 0..1_000 {
+            writer.insert(f64::from(i));
+        }
+        let before = reader.load();
+        assert!(before.is_empty());
+        assert_eq!(writer.digest().count(), 1_000.0);
+
+        writer.publish();
+        let after = reader.load();
+        assert_eq!(after.count(), 1_000.0);
+        assert_eq!(after.buffered_len(), 0);
+        assert!(before.is_empty());
+
+        let thread = std::thread::spawn(move || reader.load().estimate_quantile(0.5));
+        assert!((thread.join().unwrap() - 500.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_snapshot_leaves_the_buffer() {
+        let mut digest = TDigest::new_with_size(100);
+        digest.insert(1.0);
+        digest.insert(2.0);
+        let snapshot = digest.snapshot();
+        assert_eq!(digest.buffered_len(), 2);
+        assert_eq!(snapshot.buffered_len(), 0);
+        assert_eq!(
+            snapshot.estimate_quantile(0.5),
+            digest.estimate_quantile(0.5)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Block steps of the cumulative-weight scans behind quantile and rank queries, with SSE2,
+//! which every x86_64 CPU has. Each step checks the last of four centroids and, if the
+//! scan passes it, skips all four at once, adding up their weights in two lanes.
+//!
+//! The lanes add the weights in a different order than the one-by-one scan, so results
+//! may differ from a build without the `simd` feature in the last bits. They do not
+//! depend on where a scan resumed, though: the shared cursor only ever moves by whole
+//! blocks, and each query finishes the scan on a copy of it, so batched queries still
+//! match single ones exactly.
+//!
+//! The intrinsics are only safe to call from functions that enable SSE2 themselves, so
+//! each scan is such a function behind a safe wrapper.
+
+use crate::query::QuantileCursor;
+use crate::Centroid;
+use core::arch::x86_64::{_mm_add_pd, _mm_cvtsd_f64, _mm_mul_pd, _mm_set_pd, _mm_unpackhi_pd};
+
+/// The weights of a block of four centroids.
+#[inline]
+fn weights(block: &[Centroid]) -> (f64, f64, f64, f64) {
+    (
+        block[0].weight(),
+        block[1].weight(),
+        block[2].weight(),
+        block[3].weight(),
+    )
+}
+
+/// Advances `cursor` past whole blocks of four centroids whose midpoints all lie at or
+/// below `rank`, returning a copy of it to finish the scan on.
+pub(crate) fn skip_to_rank(
+    centroids: &[Centroid],
+    cursor: &mut QuantileCursor,
+    rank: f64,
+) -> QuantileCursor {
+    // SAFETY: SSE2 is part of the x86_64 baseline, so every x86_64 CPU has it.
+    unsafe { skip_to_rank_sse2(centroids, cursor, rank) }
+}
+
+#[target_feature(enable = "sse2")]
+fn skip_to_rank_sse2(
+    centroids: &[Centroid],
+    cursor: &mut QuantileCursor,
+    rank: f64,
+) -> QuantileCursor {
+    while let Some(block) = centroids.get(cursor.pos..cursor.pos + 4) {
+        let (w0, w1, w2, w3) = weights(block);
+        // (w0 + w2, w1 + w3 / 2): the last midpoint is t + w0 + w1 + w2 + w3 / 2.
+        let lanes = _mm_add_pd(
+            _mm_set_pd(w1, w0),
+            _mm_mul_pd(_mm_set_pd(w3, w2), _mm_set_pd(0.5, 1.0)),
+        );
+        let midpoint = _mm_cvtsd_f64(lanes) + _mm_cvtsd_f64(_mm_unpackhi_pd(lanes, lanes));
+        if cursor.t + midpoint > rank {
+            break;
+        }
+        cursor.t += midpoint + w3 / 2.0;
+        cursor.pos += 4;
+    }
+    *cursor
+}
+
+/// Advances `cursor` past whole blocks of four centroids whose means all lie at or below
+/// `x`, returning a copy of it to finish the scan on.
+pub(crate) fn skip_to_value(
+    centroids: &[Centroid],
+    cursor: &mut QuantileCursor,
+    x: f64,
+) -> QuantileCursor {
+    // SAFETY: as for `skip_to_rank`.
+    unsafe { skip_to_value_sse2(centroids, cursor, x) }
+}
+
+#[target_feature(enable = "sse2")]
+fn skip_to_value_sse2(
+    centroids: &[Centroid],
+    cursor: &mut QuantileCursor,
+    x: f64,
+) -> QuantileCursor {
+    while let Some(block) = centroids.get(cursor.pos..cursor.pos + 4) {
+        // Means ascend, so passing the last passes them all.
+        if x < block[3].mean() {
+            break;
+        }
+        let (w0, w1, w2, w3) = weights(block);
+        let lanes = _mm_add_pd(_mm_set_pd(w1, w0), _mm_set_pd(w3, w2));
+        cursor.t += _mm_cvtsd_f64(lanes) + _mm_cvtsd_f64(_mm_unpackhi_pd(lanes, lanes));
+        cursor.pos += 4;
+    }
+    *cursor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_block_scans_match_scalar_scans() {
+        // Integer weights, so both summation orders are exact.
+        let centroids: Vec<Centroid> = (0..103)
+            .map(|i| Centroid::new(f64::from(i), f64::from(i % 7 + 1)))
+            .collect();
+        let scalar = |pass: &dyn Fn(usize, f64) -> bool| {
+            let (mut pos, mut t) = (0, 0.0);
+            while pos < centroids.len() && pass(pos, t) {
+                t += centroids[pos].weight();
+                pos += 1;
+            }
+            (pos, t)
+        };
+        let check = |skipped: QuantileCursor, expected: (usize, f64), probe: f64| {
+            let QuantileCursor { pos, t } = skipped;
+            assert!(
+                pos % 4 == 0 && pos <= expected.0 && expected.0 - pos < 4,
+                "{}",
+                probe
+            );
+            let rest: f64 = centroids[pos..expected.0].iter().map(|c| c.weight()).sum();
+            assert_eq!(t + rest, expected.1);
+        };
+
+        let mut resumed = QuantileCursor::default();
+        for rank in (0..450).map(|r| f64::from(r) * 0.9) {
+            let skipped = skip_to_rank(&centroids, &mut QuantileCursor::default(), rank);
+            let expected = scalar(&|pos, t| t + centroids[pos].weight() / 2.0 <= rank);
+            check(skipped, expected, rank);
+            assert_eq!(
+                skip_to_rank(&centroids, &mut resumed, rank).pos,
+                skipped.pos
+            );
+        }
+        let mut resumed = QuantileCursor::default();
+        for x in (-2..210).map(|x| f64::from(x) * 0.5) {
+            let skipped = skip_to_value(&centroids, &mut QuantileCursor::default(), x);
+            let expected = scalar(&|pos, _| x >= centroids[pos].mean());
+            check(skipped, expected, x);
+            assert_eq!(skip_to_value(&centroids, &mut resumed, x).pos, skipped.pos);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::codec::{self, ENCODING_VERSION, FLAG_INTEGRAL_WEIGHTS};
+use crate::{TDigest, TDigestError};
+use std::io::{self, Read, Write};
+use std::vec::Vec;
+
+/// Bytes of the [`TDigest::to_bytes`] header before the centroid count: version, scale
+/// function, flags, max size, then count, sum, min and max.
+const HEADER_LEN: usize = 3 + 4 + 4 * 8;
+
+fn invalid_data(e: TDigestError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Appends `n` bytes of `reader` to `bytes`.
+fn read_n<R: Read>(reader: &mut R, bytes: &mut Vec<u8>, n: usize) -> io::Result<()> {
+    let start = bytes.len();
+    bytes.resize(start + n, 0);
+    reader.read_exact(&mut bytes[start..])
+}
+
+/// Appends a varint of `reader` to `bytes`, returning its value.
+fn read_varint<R: Read>(reader: &mut R, bytes: &mut Vec<u8>) -> io::Result<u64> {
+    codec::read_varint(
+        || {
+            read_n(reader, bytes, 1)?;
+            Ok(bytes[bytes.len() - 1])
+        },
+        invalid_data,
+    )
+}
+
+/// Reads the bytes of one encoded digest, or `None` if `reader` is at its end.
+fn read_encoded<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 1);
+    let mut version = [0];
+    loop {
+        match reader.read(&mut version) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    // Other versions may lay out the rest differently, so their end cannot be found.
+    if version[0] != ENCODING_VERSION {
+        return Err(invalid_data(TDigestError::UnsupportedVersion {
+            version: version[0],
+        }));
+    }
+    bytes.push(version[0]);
+    read_n(reader, &mut bytes, HEADER_LEN - 1)?;
+    let integral = bytes[2] & FLAG_INTEGRAL_WEIGHTS != 0;
+
+    let len = read_varint(reader, &mut bytes)?;
+    for _ in 0..len {
+        read_varint(reader, &mut bytes)?;
+        if integral {
+            read_varint(reader, &mut bytes)?;
+        } else {
+            read_n(reader, &mut bytes, 8)?;
+        }
+    }
+    Ok(Some(bytes))
+}
+
+impl TDigest {
+    /// Writes the digest to `writer` in the format of [`TDigest::to_bytes`]. Encodings are
+    /// self-delimiting, so digests written one after another, e.g. appended to a log
+    /// file, can be read back with [`DigestLogReader`].
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    /// Reads one digest written by [`TDigest::write_to`], leaving `reader` just past it.
+    ///
+    /// The format is read a few bytes at a time, so wrap unbuffered readers such as files
+    /// in a [`std::io::BufReader`]. Fails with [`io::ErrorKind::UnexpectedEof`] on
+    /// truncated input, including an empty reader, and with [`io::ErrorKind::InvalidData`]
+    /// wrapping a [`TDigestError`] on an invalid encoding.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<TDigest> {
+        match read_encoded(&mut reader)? {
+            Some(bytes) => TDigest::from_bytes(&bytes).map_err(invalid_data),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+/// Iterates over a stream of digests written one after another by
+/// [`TDigest::write_to`], e.g. a log of periodic snapshots.
+///
+/// The stream ends cleanly only between digests; a truncated last digest, e.g. one that
+/// was being appended when the log was copied, is an error. Iteration stops after the
+/// first error.
+#[derive(Debug)]
+pub struct DigestLogReader<R> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: Read> DigestLogReader<R> {
+    /// Reads digests from `reader`, which should be buffered, see [`TDigest::read_from`].
+    pub fn new(reader: R) -> Self {
+        DigestLogReader {
+            reader,
+            failed: false,
+        }
+    }
+
+    /// Merges the remaining digests as they are read, keeping only the running result in
+    /// memory. The result uses the first digest's configuration, and an empty stream
+    /// gives `TDigest::default()`, like [`TDigest::merge_digests`].
+    pub fn merge_all(self) -> io::Result<TDigest> {
+        let mut merged: Option<TDigest> = None;
+        for digest in self {
+            let digest = digest?;
+            match merged.as_mut() {
+                Some(merged) => merged.merge(&digest),
+                None => merged = Some(digest),
+            }
+        }
+        Ok(merged.unwrap_or_default())
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for DigestLogReader<R> {
+    type Item = io::Result<TDigest>;
+
+    fn next(&mut self) -> Option<io::Result<TDigest>> {
+        if self.failed {
+            return None;
+        }
+        let digest = read_encoded(&mut self.reader).and_then(|bytes| {
+            bytes
+                .map(|bytes| TDigest::from_bytes(&bytes).map_err(invalid_data))
+                .transpose()
+        });
+        let digest = digest.transpose();
+        self.failed = matches!(digest, Some(Err(_)));
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_to_and_read_from() {
+        let mut weighted = TDigest::new_with_size(20);
+        weighted.insert_weighted(1.5, 0.25);
+        weighted.flush();
+        let digests = [
+            TDigest::new_with_size(100).merge_unsorted(crate::lcg::lcg_values(1_000, 5)),
+            weighted,
+            TDigest::default(),
+        ];
+        let mut log = Vec::new();
+        for digest in digests.iter() {
+            digest.write_to(&mut log).unwrap();
+        }
+
+        let mut reader = &log[..];
+        assert_eq!(TDigest::read_from(&mut reader).unwrap(), digests[0]);
+        let read: Vec<TDigest> = DigestLogReader::new(reader)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, digests[1..]);
+
+        let err = TDigest::read_from(&[][..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = TDigest::read_from(&[9][..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_log_reader_merges_and_stops_on_truncation() {
+        let mut log = Vec::new();
+        for seed in 0..10 {
+            TDigest::new_with_size(50)
+                .merge_unsorted(crate::lcg::lcg_values(100, seed))
+                .write_to(&mut log)
+                .unwrap();
+        }
+        let merged = DigestLogReader::new(&log[..]).merge_all().unwrap();
+        assert_eq!(merged.count(), 1_000.0);
+        assert_eq!(merged.max_size(), 50);
+
+        let truncated = &log[..log.len() - 3];
+        let mut reader = DigestLogReader::new(truncated);
+        assert_eq!(reader.by_ref().take(9).filter(|d| d.is_ok()).count(), 9);
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+        assert!(DigestLogReader::new(truncated).merge_all().is_err());
+    }
+}
@@ -0,0 +1,186 @@
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::TDigest;
+use alloc::vec::Vec;
+
+/// A [`TDigest`] that also keeps its `k` smallest and `k` largest raw values, so extreme
+/// quantiles, e.g. p999 of a small window, are exact rather than smeared across the
+/// outermost centroids.
+///
+/// A quantile whose nearest-rank order statistic is among the kept values returns that
+/// value, as [`TDigest::estimate_quantile_exact_if_small`] does; all others are
+/// interpolated by the digest. With `n` values, quantiles below `k / n` and above
+/// `1 - k / n` are therefore exact, and every quantile is while `n <= k`. The tails
+/// cost `2 * k` values of memory and `O(k)` per insert that lands in them.
+#[derive(Debug, Clone)]
+pub struct ExactTailsTDigest {
+    digest: TDigest,
+    k: usize,
+    /// The smallest values, ascending.
+    smallest: Vec<f64>,
+    /// The largest values, ascending.
+    largest: Vec<f64>,
+}
+
+/// Adds `value` to the ascending `kept`, keeping at most `k` values at the end of the
+/// order selected by `keep_low`.
+fn keep(kept: &mut Vec<f64>, value: f64, k: usize, keep_low: bool) {
+    if k == 0 {
+        return;
+    }
+    if kept.len() == k {
+        match keep_low {
+            true if value.total_cmp(&kept[k - 1]).is_ge() => return,
+            false if value.total_cmp(&kept[0]).is_le() => return,
+            true => {
+                kept.pop();
+            }
+            false => {
+                kept.remove(0);
+            }
+        }
+    }
+    let at = kept.partition_point(|v| v.total_cmp(&value).is_lt());
+    kept.insert(at, value);
+}
+
+impl ExactTailsTDigest {
+    /// Creates a digest of `max_size` centroids that keeps the `k` smallest and `k`
+    /// largest values exactly.
+    pub fn new(max_size: usize, k: usize) -> Self {
+        ExactTailsTDigest::from_digest(TDigest::new_with_size(max_size), k)
+    }
+
+    /// Wraps the empty `digest`, e.g. one configured through [`TDigest::builder`].
+    pub fn from_digest(digest: TDigest, k: usize) -> Self {
+        assert!(
+            digest.is_empty(),
+            "the tails of a digest with values are unknown"
+        );
+        ExactTailsTDigest {
+            digest,
+            k,
+            smallest: Vec::with_capacity(k),
+            largest: Vec::with_capacity(k),
+        }
+    }
+
+    /// The underlying digest.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// Number of values kept exactly at each end.
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The kept smallest values, ascending.
+    pub fn smallest(&self) -> &[f64] {
+        &self.smallest
+    }
+
+    /// The kept largest values, ascending.
+    pub fn largest(&self) -> &[f64] {
+        &self.largest
+    }
+
+    /// Adds a single value, see [`TDigest::insert`]. Values the digest rejects under
+    /// its [`crate::InvalidValuePolicy`] are not kept either.
+    pub fn insert(&mut self, value: f64) {
+        if !self.digest.accepts(value) {
+            return;
+        }
+        self.digest.insert(value);
+        keep(&mut self.smallest, value, self.k, true);
+        keep(&mut self.largest, value, self.k, false);
+    }
+
+    /// Folds `other` into `self`. The tails stay exact, since the `k` smallest values of
+    /// both are among the kept ones of either; `self` keeps its `k`.
+    pub fn merge(&mut self, other: &ExactTailsTDigest) {
+        self.digest.merge(&other.digest);
+        for &value in other.smallest.iter() {
+            keep(&mut self.smallest, value, self.k, true);
+        }
+        for &value in other.largest.iter() {
+            keep(&mut self.largest, value, self.k, false);
+        }
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.digest.count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.digest.is_empty()
+    }
+
+    /// The value at quantile `q`: the nearest-rank order statistic if it is among the
+    /// kept values, and otherwise [`TDigest::estimate_quantile`]. NaN for an empty
+    /// digest.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        let n = self.count() as usize;
+        if n == 0 {
+            return f64::NAN;
+        }
+        // 0-based index of the nearest-rank order statistic.
+        let index = ((q * n as f64).ceil() as usize).clamp(1, n) - 1;
+        if index < self.smallest.len() {
+            return self.smallest[index];
+        }
+        let from_top = n - 1 - index;
+        if from_top < self.largest.len() {
+            return self.largest[self.largest.len() - 1 - from_top];
+        }
+        self.digest.estimate_quantile(q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extreme_quantiles_are_exact() {
+        let mut values = crate::lcg::lcg_values(5_000, 17);
+        // A handful of outliers that a compressed tail centroid would average away.
+        values.extend([250.0, 400.0, 1_000.0, -7.0, -0.0]);
+        let mut t = ExactTailsTDigest::new(50, 10);
+        let mut halves = (
+            ExactTailsTDigest::new(50, 10),
+            ExactTailsTDigest::new(50, 10),
+        );
+        for (i, &v) in values.iter().enumerate() {
+            t.insert(v);
+            let half = if i % 2 == 0 {
+                &mut halves.0
+            } else {
+                &mut halves.1
+            };
+            half.insert(v);
+        }
+        halves.0.merge(&halves.1);
+        values.sort_by(f64::total_cmp);
+
+        let n = values.len() as f64;
+        for q in [0.0, 0.5 / n, 0.001, 0.999, 0.9995, 1.0] {
+            let exact = values[((q * n).ceil() as usize).clamp(1, values.len()) - 1];
+            assert_eq!(t.estimate_quantile(q), exact, "{}", q);
+            assert_eq!(halves.0.estimate_quantile(q), exact, "{}", q);
+        }
+        assert_eq!(t.estimate_quantile(0.5), t.digest().estimate_quantile(0.5));
+        assert_eq!(t.largest(), &values[values.len() - 10..]);
+        assert_eq!(halves.0.smallest(), t.smallest());
+
+        t.insert(f64::NAN);
+        assert_eq!(t.count(), n);
+        assert!(ExactTailsTDigest::new(50, 10)
+            .estimate_quantile(0.5)
+            .is_nan());
+    }
+}
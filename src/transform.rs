@@ -0,0 +1,208 @@
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)]
+use crate::math::Float;
+use crate::TDigest;
+use alloc::vec::Vec;
+
+/// A strictly increasing map applied to values before they enter a
+/// [`TransformedTDigest`], and undone on the way out.
+#[derive(Debug, Clone, Copy)]
+pub enum Transform {
+    /// `ln(x)`, for positive data spanning many orders of magnitude, e.g. latencies:
+    /// every centroid then covers a range of relative rather than absolute size.
+    /// Values that are not positive are invalid.
+    Log,
+    /// `sqrt(x)`, a milder compression of the upper tail. Negative values are invalid.
+    Sqrt,
+    /// A caller-supplied bijection, e.g. `Transform::Custom { forward: f64::cbrt, inverse:
+    /// |y| y * y * y }`. `forward` must be strictly increasing and `inverse` must undo it.
+    Custom {
+        forward: fn(f64) -> f64,
+        inverse: fn(f64) -> f64,
+    },
+}
+
+impl Transform {
+    fn forward(self, x: f64) -> f64 {
+        match self {
+            Transform::Log => x.ln(),
+            Transform::Sqrt => x.sqrt(),
+            Transform::Custom { forward, .. } => forward(x),
+        }
+    }
+
+    fn inverse(self, y: f64) -> f64 {
+        match self {
+            Transform::Log => y.exp(),
+            Transform::Sqrt => y * y,
+            Transform::Custom { inverse, .. } => inverse(y),
+        }
+    }
+}
+
+/// A [`TDigest`] over transformed values, for data whose spread the linear digest
+/// compresses badly.
+///
+/// Values are mapped through the [`Transform`] on insert, so the centroids live in the
+/// transformed space, and quantiles are mapped back: the transform is increasing, so the
+/// quantile of the originals is the inverse of the quantile of the transformed values.
+/// Under [`Transform::Log`] estimates interpolate geometrically between centroids, so
+/// their error stays relative to the value for microseconds as for minutes.
+///
+/// Values the transform maps to NaN or an infinity, e.g. zero under `Log`, are handled by
+/// the digest's [`crate::InvalidValuePolicy`].
+///
+/// Sums and means of the originals are not available, since the digest only sees the
+/// transformed values.
+#[derive(Debug, Clone)]
+pub struct TransformedTDigest {
+    digest: TDigest,
+    transform: Transform,
+}
+
+impl TransformedTDigest {
+    /// Creates a digest of `max_size` centroids over values mapped through `transform`.
+    pub fn new(max_size: usize, transform: Transform) -> Self {
+        TransformedTDigest::from_digest(TDigest::new_with_size(max_size), transform)
+    }
+
+    /// Wraps `digest`, whose values must already be in the transformed space, e.g. one
+    /// configured through [`TDigest::builder`].
+    pub fn from_digest(digest: TDigest, transform: Transform) -> Self {
+        TransformedTDigest { digest, transform }
+    }
+
+    #[inline]
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// The underlying digest, over the transformed values.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+
+    /// Adds a single value, see [`TDigest::insert`].
+    pub fn insert(&mut self, value: f64) {
+        self.insert_weighted(value, 1.0);
+    }
+
+    /// Adds `value` as if it had been seen `weight` times, see [`TDigest::insert_weighted`].
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        self.digest
+            .insert_weighted(self.transform.forward(value), weight);
+    }
+
+    /// Folds `other` into `self`. Both must use the same transform; mixing the built-in
+    /// kinds panics, while two `Custom` transforms are trusted to be the same.
+    pub fn merge(&mut self, other: &TransformedTDigest) {
+        assert!(
+            core::mem::discriminant(&self.transform) == core::mem::discriminant(&other.transform),
+            "cannot merge digests with different transforms"
+        );
+        self.digest.merge(&other.digest);
+    }
+
+    /// Compresses buffered values, see [`TDigest::flush`].
+    pub fn flush(&mut self) {
+        self.digest.flush();
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.digest.count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.digest.is_empty()
+    }
+
+    /// The smallest original value, or NaN for an empty digest.
+    pub fn min(&self) -> f64 {
+        self.transform.inverse(self.digest.min())
+    }
+
+    /// The largest original value, or NaN for an empty digest.
+    pub fn max(&self) -> f64 {
+        self.transform.inverse(self.digest.max())
+    }
+
+    /// Estimates the original value at quantile `q`, see [`TDigest::estimate_quantile`].
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        self.transform.inverse(self.digest.estimate_quantile(q))
+    }
+
+    /// Estimates every quantile of the sorted `qs`, see [`TDigest::estimate_quantiles`].
+    pub fn estimate_quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        let mut values = self.digest.estimate_quantiles(qs);
+        values
+            .iter_mut()
+            .for_each(|v| *v = self.transform.inverse(*v));
+        values
+    }
+
+    /// Estimates the fraction of original values at most `x`, see [`TDigest::cdf`].
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.digest.cdf(self.transform.forward(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_transform_keeps_relative_accuracy() {
+        // Log-uniform over nine orders of magnitude, from 1e-6 to 1e3.
+        let mut values: Vec<f64> = crate::lcg::lcg_values(100_000, 41)
+            .into_iter()
+            .map(|u| 10f64.powf(9.0 * u - 6.0))
+            .collect();
+        let mut log = TransformedTDigest::new(100, Transform::Log);
+        values.iter().for_each(|&v| log.insert(v));
+        values.sort_by(f64::total_cmp);
+
+        for q in [0.01, 0.1, 0.5, 0.9, 0.99] {
+            let exact = values[(q * values.len() as f64) as usize];
+            let relative = (log.estimate_quantile(q) - exact).abs() / exact;
+            assert!(relative < 0.1, "{}: {}", q, relative);
+        }
+
+        assert_eq!(log.count(), 100_000.0);
+        assert!((log.min() - values[0]).abs() <= 1e-12 * values[0]);
+        assert!((log.cdf(values[50_000]) - 0.5).abs() < 0.01);
+        assert_eq!(
+            log.estimate_quantiles(&[0.5])[0],
+            log.estimate_quantile(0.5)
+        );
+    }
+
+    #[test]
+    fn test_sqrt_custom_and_invalid_values() {
+        let cube = Transform::Custom {
+            forward: f64::cbrt,
+            inverse: |y| y * y * y,
+        };
+        for transform in [Transform::Sqrt, cube] {
+            let mut t = TransformedTDigest::new(100, transform);
+            for v in [1.0, 4.0, 9.0] {
+                t.insert(v);
+            }
+            assert!((t.estimate_quantile(0.5) - 4.0).abs() < 1e-9);
+            assert!((t.max() - 9.0).abs() < 1e-9);
+        }
+
+        let mut log = TransformedTDigest::new(100, Transform::Log);
+        log.insert(0.0);
+        log.insert(-1.0);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_merge_rejects_other_transform() {
+        let mut log = TransformedTDigest::new(100, Transform::Log);
+        log.merge(&TransformedTDigest::new(100, Transform::Sqrt));
+    }
+}
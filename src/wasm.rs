@@ -0,0 +1,98 @@
+use crate::TDigest;
+use std::string::ToString;
+use std::vec::Vec;
+use wasm_bindgen::prelude::*;
+
+/// A digest for JavaScript, e.g. client-side telemetry in the browser, exported as
+/// `TDigest`. Its `toBytes` output is the format of [`TDigest::to_bytes`], so a server
+/// can decode browser digests with [`TDigest::from_bytes`] and merge them with its own.
+#[wasm_bindgen(js_name = TDigest)]
+#[derive(Debug, Clone)]
+pub struct WasmTDigest {
+    digest: TDigest,
+}
+
+#[wasm_bindgen(js_class = TDigest)]
+impl WasmTDigest {
+    /// Creates an empty digest of at most `max_size` centroids.
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_size: usize) -> WasmTDigest {
+        WasmTDigest {
+            digest: TDigest::new_with_size(max_size),
+        }
+    }
+
+    /// Decodes bytes written by `toBytes` or [`TDigest::to_bytes`].
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmTDigest, JsError> {
+        TDigest::from_bytes(bytes)
+            .map(|digest| WasmTDigest { digest })
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        self.digest.insert(value);
+    }
+
+    #[wasm_bindgen(js_name = insertWeighted)]
+    pub fn insert_weighted(&mut self, value: f64, weight: f64) {
+        self.digest.insert_weighted(value, weight);
+    }
+
+    /// Adds every value of a `Float64Array`.
+    #[wasm_bindgen(js_name = insertMany)]
+    pub fn insert_many(&mut self, values: &[f64]) {
+        self.digest.extend(values.iter().copied());
+    }
+
+    pub fn merge(&mut self, other: &WasmTDigest) {
+        self.digest.merge(&other.digest);
+    }
+
+    pub fn quantile(&self, q: f64) -> f64 {
+        self.digest.estimate_quantile(q)
+    }
+
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.digest.cdf(x)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> f64 {
+        self.digest.count()
+    }
+
+    /// The digest in the format of [`TDigest::to_bytes`], as a `Uint8Array`.
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.digest.to_bytes()
+    }
+}
+
+impl WasmTDigest {
+    /// The wrapped digest.
+    pub fn digest(&self) -> &TDigest {
+        &self.digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn test_browser_bytes_merge_server_side() {
+        let mut browser = WasmTDigest::new(100);
+        browser.insert_many(&[1.0, 2.0, 3.0]);
+        browser.insert_weighted(4.0, 2.0);
+        let copy = WasmTDigest::from_bytes(&browser.to_bytes()).unwrap();
+        assert_eq!(copy.count(), 5.0);
+
+        let mut server = TDigest::new_with_size(100).merge_sorted(vec![10.0]);
+        server.merge(&TDigest::from_bytes(&browser.to_bytes()).unwrap());
+        assert_eq!(server.count(), 6.0);
+        assert_eq!(server.min(), 1.0);
+        assert_eq!(browser.quantile(0.0), 1.0);
+    }
+}
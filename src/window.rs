@@ -0,0 +1,196 @@
+use crate::TDigest;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// One slot of the ring: the digest of every value recorded during bucket `epoch`, i.e.
+/// at times in `[epoch * width, (epoch + 1) * width)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bucket {
+    epoch: u64,
+    digest: TDigest,
+}
+
+/// A digest over a sliding time window, e.g. the last 60 seconds as 60 one-second
+/// buckets, for latency SLO monitoring.
+///
+/// Values are recorded into the bucket covering their timestamp, and queries merge the
+/// buckets still inside the window ending at the given time. Buckets that fall out of
+/// the window are cleared automatically as time advances; values older than the window
+/// are dropped.
+///
+/// Times are [`Duration`]s since any epoch the caller likes, e.g. `Instant::elapsed` on a
+/// start instant or `SystemTime::duration_since(UNIX_EPOCH)`, and should be roughly
+/// non-decreasing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowedTDigest {
+    buckets: Vec<Bucket>,
+    width: Duration,
+    max_size: usize,
+    /// Bucket epoch of the latest time seen.
+    latest: u64,
+}
+
+impl WindowedTDigest {
+    /// Creates a window of `n_buckets` buckets each `width` long, whose digests keep at
+    /// most `max_size` centroids.
+    pub fn new(max_size: usize, n_buckets: usize, width: Duration) -> Self {
+        assert!(n_buckets > 0, "a window needs at least one bucket");
+        assert!(!width.is_zero(), "bucket width must be positive");
+        WindowedTDigest {
+            buckets: (0..n_buckets)
+                .map(|_| Bucket {
+                    epoch: 0,
+                    digest: TDigest::new_with_size(max_size),
+                })
+                .collect(),
+            width,
+            max_size,
+            latest: 0,
+        }
+    }
+
+    /// Total length of the window, `n_buckets * width`.
+    pub fn window(&self) -> Duration {
+        self.width * self.buckets.len() as u32
+    }
+
+    fn epoch(&self, time: Duration) -> u64 {
+        (time.as_nanos() / self.width.as_nanos()) as u64
+    }
+
+    /// Whether bucket `epoch` lies within the window ending in bucket `latest`.
+    fn is_live(&self, epoch: u64, latest: u64) -> bool {
+        epoch <= latest && latest - epoch < self.buckets.len() as u64
+    }
+
+    /// Moves the window forward to end at `now`, clearing the buckets that expired.
+    pub fn advance(&mut self, now: Duration) {
+        let latest = self.epoch(now).max(self.latest);
+        self.latest = latest;
+
+        let n = self.buckets.len() as u64;
+        for bucket in self.buckets.iter_mut() {
+            let expired = bucket.epoch > latest || latest - bucket.epoch >= n;
+            if expired {
+                bucket.digest.reset();
+            }
+        }
+    }
+
+    /// Records `value` observed at `time`, see [`WindowedTDigest::record_weighted`].
+    pub fn record(&mut self, time: Duration, value: f64) {
+        self.record_weighted(time, value, 1.0);
+    }
+
+    /// Records `value` with `weight` into the bucket covering `time`, advancing the window
+    /// if `time` is the latest seen so far. Values older than the window are dropped.
+    pub fn record_weighted(&mut self, time: Duration, value: f64, weight: f64) {
+        let epoch = self.epoch(time);
+        if epoch > self.latest {
+            self.advance(time);
+        }
+        if !self.is_live(epoch, self.latest) {
+            return;
+        }
+
+        let slot = (epoch % self.buckets.len() as u64) as usize;
+        let bucket = &mut self.buckets[slot];
+        if bucket.epoch != epoch {
+            bucket.epoch = epoch;
+            bucket.digest.reset();
+        }
+        bucket.digest.insert_weighted(value, weight);
+    }
+
+    /// Merges the buckets inside the window ending at `now` into one digest. Run several
+    /// queries against one snapshot rather than calling the shortcuts below repeatedly,
+    /// since each of them merges the window afresh.
+    pub fn snapshot(&self, now: Duration) -> TDigest {
+        let latest = self.epoch(now).max(self.latest);
+        let live: Vec<TDigest> = self
+            .buckets
+            .iter()
+            .filter(|b| !b.digest.is_empty())
+            .filter(|b| self.is_live(b.epoch, latest))
+            .map(|b| b.digest.clone())
+            .collect();
+
+        if live.is_empty() {
+            TDigest::new_with_size(self.max_size)
+        } else {
+            TDigest::merge_digests(live)
+        }
+    }
+
+    /// Estimates quantile `q` over the window ending at `now`.
+    pub fn estimate_quantile(&self, now: Duration, q: f64) -> f64 {
+        self.snapshot(now).estimate_quantile(q)
+    }
+
+    /// Estimates every quantile of the sorted `qs` over the window ending at `now`.
+    pub fn estimate_quantiles(&self, now: Duration, qs: &[f64]) -> Vec<f64> {
+        self.snapshot(now).estimate_quantiles(qs)
+    }
+
+    /// Estimates the fraction of values in the window ending at `now` that are at most `x`.
+    pub fn cdf(&self, now: Duration, x: f64) -> f64 {
+        self.snapshot(now).cdf(x)
+    }
+
+    /// Total weight recorded in the window ending at `now`.
+    pub fn count(&self, now: Duration) -> f64 {
+        self.snapshot(now).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn test_window_evicts_old_buckets() {
+        let mut w = WindowedTDigest::new(100, 10, Duration::from_secs(1));
+        assert_eq!(w.window(), Duration::from_secs(10));
+
+        for i in 0..100 {
+            w.record(secs(i as f64 * 0.1), 1.0);
+        }
+        for i in 0..100 {
+            w.record(secs(10.0 + i as f64 * 0.1), 5.0);
+        }
+
+        // Only the second ten seconds remain.
+        let now = secs(19.9);
+        assert_eq!(w.count(now), 100.0);
+        assert_eq!(w.estimate_quantile(now, 0.5), 5.0);
+
+        // Half of the window has expired five seconds later.
+        assert_eq!(w.count(secs(24.9)), 50.0);
+        assert!(w.snapshot(secs(100.0)).is_empty());
+    }
+
+    #[test]
+    fn test_window_drops_late_values_and_keeps_recent_ones() {
+        let mut w = WindowedTDigest::new(100, 5, Duration::from_secs(1));
+        w.record(secs(10.0), 2.0);
+        w.record(secs(7.5), 3.0);
+        w.record(secs(1.0), 100.0);
+
+        let snapshot = w.snapshot(secs(10.0));
+        assert_eq!(snapshot.count(), 2.0);
+        assert_eq!(snapshot.max(), 3.0);
+        assert_eq!(w.cdf(secs(10.0), 2.5), 0.5);
+
+        w.advance(secs(12.0));
+        assert_eq!(w.count(secs(12.0)), 1.0);
+        assert_eq!(
+            w.estimate_quantiles(secs(12.0), &[0.0, 1.0]),
+            vec![2.0, 2.0]
+        );
+    }
+}